@@ -1,10 +1,25 @@
-use crate::error::Result;
+use crate::error::{ErrorCode, RecpartError, Result};
 use crate::exec::execute_plan;
 use crate::json::to_pretty_json;
-use crate::plan::build_plan_with_layout_request;
-use crate::preflight::{list_disk_inventory, query_disk_target};
-use crate::types::{ApplyOptions, InstallMode, LayoutRequest};
+use crate::plan::{
+    build_free_space_plan, build_mirrored_plan, build_multi_disk_plan, build_plan_with_profile,
+    build_shrink_plan, build_slot_reinstall_plan,
+};
+use crate::preflight::{
+    inspect_disk, list_disk_inventory, query_disk_target, query_memory_mb, query_shrink_candidate,
+    read_partition_table,
+};
+use crate::profile::{builtin_profile, load_profile};
+use crate::size::SizeExpr;
+use crate::table::find_partition;
+use crate::types::{
+    AbSlot, ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutProfile, LayoutRequest,
+    LuksKey, PartitionPlan,
+};
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
@@ -24,6 +39,8 @@ pub enum Commands {
     Plan(PlanArgs),
     /// Apply a partition plan directly to disk.
     Apply(ApplyArgs),
+    /// Show the partitions, filesystems and free space on a disk.
+    Inspect(InspectArgs),
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -33,6 +50,17 @@ pub struct ListDisksArgs {
     pub json: bool,
 }
 
+#[derive(Debug, Clone, Parser)]
+pub struct InspectArgs {
+    /// Disk block device to read (for example /dev/sda)
+    #[arg(long)]
+    pub disk: PathBuf,
+
+    /// Emit machine-readable JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
 #[derive(Debug, Clone, Parser)]
 pub struct PlanArgs {
     /// Target disk block device (for example /dev/sda)
@@ -43,10 +71,18 @@ pub struct PlanArgs {
     #[arg(long, value_enum, default_value_t = ModeArg::Ab)]
     pub mode: ModeArg,
 
+    /// Second disk partitioned identically and mirrored with md RAID1
+    #[arg(long)]
+    pub mirror_disk: Option<PathBuf>,
+
     /// Emit machine-readable JSON
     #[arg(long)]
     pub json: bool,
 
+    /// Layout profile file (.toml or .json) replacing the built-in mode layout
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+
     #[command(flatten)]
     pub layout: LayoutRequestArgs,
 }
@@ -61,6 +97,10 @@ pub struct ApplyArgs {
     #[arg(long, value_enum, default_value_t = ModeArg::Ab)]
     pub mode: ModeArg,
 
+    /// Second disk partitioned identically and mirrored with md RAID1
+    #[arg(long)]
+    pub mirror_disk: Option<PathBuf>,
+
     /// Mount root used for target topology (default /mnt)
     #[arg(long, default_value = "/mnt")]
     pub mount_root: PathBuf,
@@ -77,6 +117,18 @@ pub struct ApplyArgs {
     #[arg(long)]
     pub confirm: Option<String>,
 
+    /// Layout profile file (.toml or .json) replacing the built-in mode layout
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+
+    /// Keyfile whose contents unlock --encrypt partitions
+    #[arg(long)]
+    pub luks_keyfile: Option<PathBuf>,
+
+    /// Read the LUKS passphrase for --encrypt partitions from stdin
+    #[arg(long, conflicts_with = "luks_keyfile")]
+    pub luks_passphrase_stdin: bool,
+
     #[command(flatten)]
     pub layout: LayoutRequestArgs,
 }
@@ -102,6 +154,134 @@ pub struct LayoutRequestArgs {
     /// A/B state partition size in MB (ab mode only). Omit to use remaining space.
     #[arg(long)]
     pub state_size_mb: Option<u32>,
+
+    /// EFI partition size expression (for example 512MiB, 2%, or 1G,max:2G)
+    #[arg(long, conflicts_with = "efi_size_mb")]
+    pub efi_size: Option<String>,
+
+    /// Mutable root size expression (for example 40G, 50%, or min:8G,max:40G)
+    #[arg(long, conflicts_with = "root_size_mb")]
+    pub root_size: Option<String>,
+
+    /// A/B slot A size expression (for example 20G or 10%)
+    #[arg(long, conflicts_with = "root_a_size_mb")]
+    pub root_a_size: Option<String>,
+
+    /// A/B slot B size expression (for example 20G or 10%)
+    #[arg(long, conflicts_with = "root_b_size_mb")]
+    pub root_b_size: Option<String>,
+
+    /// A/B state size expression (for example 25% or min:8G,max:40G)
+    #[arg(long, conflicts_with = "state_size_mb")]
+    pub state_size: Option<String>,
+
+    /// Swap partition size in MB (either mode). Omit for no swap partition.
+    #[arg(long)]
+    pub swap_size_mb: Option<u32>,
+
+    /// Swap size expression, or "auto" to size for hibernation from /proc/meminfo
+    #[arg(long, conflicts_with = "swap_size_mb")]
+    pub swap_size: Option<String>,
+
+    /// Separate /home partition size in MB (mutable mode only)
+    #[arg(long)]
+    pub home_size_mb: Option<u32>,
+
+    /// Separate /home size expression, or "remaining" for the rest of the disk
+    #[arg(long, conflicts_with = "home_size_mb")]
+    pub home_size: Option<String>,
+
+    /// XBOOTLDR /boot partition size in MB; the ESP shrinks and moves to /efi
+    #[arg(long)]
+    pub xbootldr_size_mb: Option<u32>,
+
+    /// XBOOTLDR size expression (for example 1G)
+    #[arg(long, conflicts_with = "xbootldr_size_mb")]
+    pub xbootldr_size: Option<String>,
+
+    /// Add a RECOVERY partition to the ab layout (default 2G)
+    #[arg(long)]
+    pub recovery: bool,
+
+    /// RECOVERY partition size in MB (implies --recovery)
+    #[arg(long)]
+    pub recovery_size_mb: Option<u32>,
+
+    /// RECOVERY size expression (for example 4G; implies --recovery)
+    #[arg(long, conflicts_with = "recovery_size_mb")]
+    pub recovery_size: Option<String>,
+
+    /// Give each ab slot its own boot partition (BOOT_A/BOOT_B, default 1G each)
+    #[arg(long)]
+    pub slot_boot: bool,
+
+    /// Per-slot boot partition size in MB (implies --slot-boot)
+    #[arg(long)]
+    pub slot_boot_size_mb: Option<u32>,
+
+    /// Per-slot boot size expression (for example 2G; implies --slot-boot)
+    #[arg(long, conflicts_with = "slot_boot_size_mb")]
+    pub slot_boot_size: Option<String>,
+
+    /// Reserve unformatted dm-verity hash partitions for both ab root slots
+    #[arg(long)]
+    pub verity: bool,
+
+    /// Per-partition filesystem override as NAME=FS (for example root=btrfs)
+    #[arg(long = "filesystem", value_name = "NAME=FS")]
+    pub filesystems: Vec<String>,
+
+    /// Wrap a partition in LUKS2 (state in ab; root or home in mutable)
+    #[arg(long = "encrypt", value_name = "NAME")]
+    pub encrypt: Vec<String>,
+
+    /// Put everything except boot partitions and A/B slots on LVM in this volume group
+    #[arg(long, value_name = "VG")]
+    pub lvm_vg: Option<String>,
+
+    /// Place a partition on another disk as NAME=DISK (for example state=/dev/sdb)
+    #[arg(long = "partition-disk", value_name = "NAME=DISK")]
+    pub partition_disks: Vec<String>,
+
+    /// Boot firmware: uefi (default), bios-gpt, or bios-mbr
+    #[arg(long, value_enum)]
+    pub firmware: Option<FirmwareArg>,
+
+    /// Architecture for DPS root partition types (default: host)
+    #[arg(long, value_enum)]
+    pub arch: Option<ArchArg>,
+
+    /// Seed for PARTUUIDs and filesystem UUIDs (default: derived from the disk and profile)
+    #[arg(long = "uuid-seed", value_name = "SEED")]
+    pub uuid_seed: Option<String>,
+
+    /// Keep the existing partitions and install into the largest free gap
+    #[arg(long)]
+    pub use_free_space: bool,
+
+    /// Install into the free gap containing this sector (implies --use-free-space)
+    #[arg(long = "free-space-start", value_name = "SECTOR")]
+    pub free_space_start: Option<u64>,
+
+    /// Share this existing ESP (device or PARTUUID) instead of creating one
+    #[arg(long, value_name = "DEVICE|PARTUUID")]
+    pub reuse_esp: Option<String>,
+
+    /// Shrink this ext4/NTFS partition (device or PARTUUID) and install behind it
+    #[arg(long, value_name = "DEVICE|PARTUUID")]
+    pub shrink_partition: Option<String>,
+
+    /// New size of the --shrink-partition partition in MB (default: just enough for the layout)
+    #[arg(long, requires = "shrink_partition")]
+    pub shrink_size_mb: Option<u32>,
+
+    /// A/B slot to install into (default A)
+    #[arg(long, value_enum, value_name = "A|B")]
+    pub install_slot: Option<SlotArg>,
+
+    /// Reformat only this slot of the A/B install on --disk, keeping the ESP and STATE
+    #[arg(long, value_enum, value_name = "A|B")]
+    pub reinstall_slot: Option<SlotArg>,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -110,6 +290,57 @@ pub enum ModeArg {
     Mutable,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FirmwareArg {
+    Uefi,
+    BiosGpt,
+    BiosMbr,
+}
+
+impl From<FirmwareArg> for Firmware {
+    fn from(value: FirmwareArg) -> Self {
+        match value {
+            FirmwareArg::Uefi => Firmware::Uefi,
+            FirmwareArg::BiosGpt => Firmware::BiosGpt,
+            FirmwareArg::BiosMbr => Firmware::BiosMbr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ArchArg {
+    #[value(name = "x86-64", alias = "x86_64")]
+    X86_64,
+    #[value(name = "arm64", alias = "aarch64")]
+    Arm64,
+}
+
+impl From<ArchArg> for Arch {
+    fn from(value: ArchArg) -> Self {
+        match value {
+            ArchArg::X86_64 => Arch::X86_64,
+            ArchArg::Arm64 => Arch::Arm64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SlotArg {
+    #[value(name = "A", alias = "a")]
+    A,
+    #[value(name = "B", alias = "b")]
+    B,
+}
+
+impl From<SlotArg> for AbSlot {
+    fn from(value: SlotArg) -> Self {
+        match value {
+            SlotArg::A => AbSlot::A,
+            SlotArg::B => AbSlot::B,
+        }
+    }
+}
+
 impl From<ModeArg> for InstallMode {
     fn from(value: ModeArg) -> Self {
         match value {
@@ -125,19 +356,147 @@ impl Cli {
             Commands::ListDisks(args) => args.json,
             Commands::Plan(args) => args.json,
             Commands::Apply(args) => args.json,
+            Commands::Inspect(args) => args.json,
         }
     }
 }
 
 impl LayoutRequestArgs {
-    fn to_layout_request(&self) -> LayoutRequest {
-        LayoutRequest {
+    fn to_layout_request(&self) -> Result<LayoutRequest> {
+        Ok(LayoutRequest {
             efi_size_mb: self.efi_size_mb,
             root_size_mb: self.root_size_mb,
             root_a_size_mb: self.root_a_size_mb,
             root_b_size_mb: self.root_b_size_mb,
             state_size_mb: self.state_size_mb,
+            efi_size: parse_size_arg("efi-size", self.efi_size.as_deref())?,
+            root_size: parse_size_arg("root-size", self.root_size.as_deref())?,
+            root_a_size: parse_size_arg("root-a-size", self.root_a_size.as_deref())?,
+            root_b_size: parse_size_arg("root-b-size", self.root_b_size.as_deref())?,
+            state_size: parse_size_arg("state-size", self.state_size.as_deref())?,
+            swap_size_mb: self.swap_size_mb,
+            swap_size: match self.swap_size.as_deref() {
+                Some("auto") => None,
+                other => parse_size_arg("swap-size", other)?,
+            },
+            swap_hibernate_memory_mb: match self.swap_size.as_deref() {
+                Some("auto") => Some(query_memory_mb()?),
+                _ => None,
+            },
+            home_size_mb: self.home_size_mb,
+            home_size: parse_size_arg("home-size", self.home_size.as_deref())?,
+            xbootldr_size_mb: self.xbootldr_size_mb,
+            xbootldr_size: parse_size_arg("xbootldr-size", self.xbootldr_size.as_deref())?,
+            recovery: self.recovery,
+            recovery_size_mb: self.recovery_size_mb,
+            recovery_size: parse_size_arg("recovery-size", self.recovery_size.as_deref())?,
+            slot_boot: self.slot_boot,
+            slot_boot_size_mb: self.slot_boot_size_mb,
+            slot_boot_size: parse_size_arg("slot-boot-size", self.slot_boot_size.as_deref())?,
+            verity: self.verity,
+            filesystems: self
+                .filesystems
+                .iter()
+                .map(|arg| parse_filesystem_arg(arg))
+                .collect::<Result<_>>()?,
+            encrypt: self.encrypt.clone(),
+            lvm_volume_group: self.lvm_vg.clone(),
+            partition_disks: self
+                .partition_disks
+                .iter()
+                .map(|arg| parse_partition_disk_arg(arg))
+                .collect::<Result<_>>()?,
+            firmware: self.firmware.map(Firmware::from),
+            arch: self.arch.map(Arch::from),
+            uuid_seed: self.uuid_seed.clone(),
+            use_free_space: self.use_free_space || self.free_space_start.is_some(),
+            free_space_start_sector: self.free_space_start,
+            reuse_esp: self.reuse_esp.clone(),
+            shrink_partition: self.shrink_partition.clone(),
+            shrink_size_mb: self.shrink_size_mb,
+            install_slot: self.install_slot.map(AbSlot::from),
+            reinstall_slot: self.reinstall_slot.map(AbSlot::from),
+        })
+    }
+}
+
+impl ApplyArgs {
+    fn luks_key(&self) -> Result<Option<LuksKey>> {
+        let key = if let Some(path) = &self.luks_keyfile {
+            fs::read(path).map_err(|err| {
+                RecpartError::new(
+                    ErrorCode::PlanGeneration,
+                    "cli",
+                    format!("LUKS keyfile '{}' is readable", path.display()),
+                    err.to_string(),
+                    "Check the --luks-keyfile path and file permissions.",
+                )
+            })?
+        } else if self.luks_passphrase_stdin {
+            let mut passphrase = String::new();
+            io::stdin().read_line(&mut passphrase).map_err(|err| {
+                RecpartError::new(
+                    ErrorCode::PlanGeneration,
+                    "cli",
+                    "LUKS passphrase is readable from stdin",
+                    err.to_string(),
+                    "Pipe the passphrase on stdin when using --luks-passphrase-stdin.",
+                )
+            })?;
+            passphrase
+                .trim_end_matches(['\r', '\n'])
+                .as_bytes()
+                .to_vec()
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(LuksKey(key)))
+    }
+}
+
+fn parse_size_arg(flag: &str, value: Option<&str>) -> Result<Option<SizeExpr>> {
+    value
+        .map(|value| {
+            value.parse::<SizeExpr>().map_err(|err| {
+                RecpartError::new(
+                    ErrorCode::PlanGeneration,
+                    "cli",
+                    format!("--{flag} is a valid size expression"),
+                    err,
+                    "Use sizes like 512MiB, 20G, 25%, or min:8G,max:40G.",
+                )
+            })
+        })
+        .transpose()
+}
+
+fn parse_filesystem_arg(value: &str) -> Result<(String, String)> {
+    match value.split_once('=') {
+        Some((name, fs)) if !name.is_empty() && !fs.is_empty() => {
+            Ok((name.to_string(), fs.to_string()))
         }
+        _ => Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "cli",
+            "--filesystem has the form NAME=FS",
+            format!("--filesystem {value}"),
+            "Use --filesystem root=btrfs or --filesystem state=btrfs.",
+        )),
+    }
+}
+
+fn parse_partition_disk_arg(value: &str) -> Result<(String, PathBuf)> {
+    match value.split_once('=') {
+        Some((name, disk)) if !name.is_empty() && !disk.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(disk)))
+        }
+        _ => Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "cli",
+            "--partition-disk has the form NAME=DISK",
+            format!("--partition-disk {value}"),
+            "Use --partition-disk state=/dev/sdb or --partition-disk home=/dev/sdb.",
+        )),
     }
 }
 
@@ -146,6 +505,7 @@ pub fn run(cli: Cli) -> Result<()> {
         Commands::ListDisks(args) => run_list_disks(args),
         Commands::Plan(args) => run_plan(args),
         Commands::Apply(args) => run_apply(args),
+        Commands::Inspect(args) => run_inspect(args),
     }
 }
 
@@ -174,29 +534,206 @@ fn run_list_disks(args: ListDisksArgs) -> Result<()> {
     Ok(())
 }
 
+fn run_inspect(args: InspectArgs) -> Result<()> {
+    let inspection = inspect_disk(&args.disk)?;
+
+    if args.json {
+        println!("{}", to_pretty_json(&inspection)?);
+    } else {
+        println!("recpart inspect");
+        println!("  disk: {}", inspection.disk.path.display());
+        println!("  size: {} bytes", inspection.disk.size_bytes);
+        match &inspection.label {
+            Some(label) => println!(
+                "  table: {label} ({})",
+                inspection.label_id.as_deref().unwrap_or("no id")
+            ),
+            None => println!("  table: none"),
+        }
+        for part in &inspection.partitions {
+            println!(
+                "  {} (sectors {}-{}, {}MB, type {})",
+                part.device, part.start_sector, part.end_sector, part.size_mb, part.gpt_type
+            );
+            if let Some(partlabel) = &part.partlabel {
+                println!("    partlabel: {partlabel}");
+            }
+            if let Some(filesystem) = &part.filesystem {
+                println!(
+                    "    filesystem: {filesystem} | label: {} | uuid: {}",
+                    part.label.as_deref().unwrap_or("-"),
+                    part.fs_uuid.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        for gap in &inspection.free_gaps {
+            println!(
+                "  free space: sectors {}-{} ({}MB)",
+                gap.start_sector, gap.end_sector, gap.size_mb
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_profile(mode: InstallMode, path: Option<&Path>) -> Result<LayoutProfile> {
+    let Some(path) = path else {
+        return builtin_profile(mode);
+    };
+
+    let profile = load_profile(path)?;
+    if profile.mode != mode {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "profile",
+            format!("profile mode matches --mode {mode}"),
+            format!("profile '{}' is for mode {}", profile.name, profile.mode),
+            format!(
+                "Re-run with --mode {} or choose a matching profile.",
+                profile.mode
+            ),
+        ));
+    }
+
+    Ok(profile)
+}
+
+fn plan_from_args(
+    disk: &Path,
+    mirror_disk: Option<&Path>,
+    mode: ModeArg,
+    profile: Option<&Path>,
+    layout: &LayoutRequestArgs,
+) -> Result<PartitionPlan> {
+    let disk = query_disk_target(disk)?;
+    let profile = resolve_profile(mode.into(), profile)?;
+    let mut request = layout.to_layout_request()?;
+    if let Some(mirror) = mirror_disk {
+        return build_mirrored_plan(disk, query_disk_target(mirror)?, &profile, request);
+    }
+    if request.reinstall_slot.is_some() {
+        return build_slot_reinstall_plan(inspect_disk(&disk.path)?, &profile, request);
+    }
+    if let Some(selector) = request
+        .shrink_partition
+        .as_deref()
+        .filter(|_| request.partition_disks.is_empty())
+    {
+        let table = read_partition_table(&disk.path)?;
+        let part = find_partition(&table, selector).ok_or_else(|| {
+            RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "cli",
+                format!(
+                    "--shrink-partition names a partition on '{}'",
+                    disk.path.display()
+                ),
+                format!("no partition matches '{selector}'"),
+                "Pass a partition device or PARTUUID from 'sfdisk --json <disk>'.",
+            )
+        })?;
+        let candidate = query_shrink_candidate(&part.device)?;
+        return build_shrink_plan(disk, table, &candidate, &profile, request);
+    }
+    if request.use_free_space && request.partition_disks.is_empty() {
+        let table = read_partition_table(&disk.path)?;
+        return build_free_space_plan(disk, table, &profile, request);
+    }
+    if request.partition_disks.is_empty() {
+        return build_plan_with_profile(disk, &profile, request);
+    }
+
+    // Placement paths are canonicalized so they match the queried disks.
+    let mut data_disks: Vec<DiskTarget> = Vec::new();
+    for path in request.partition_disks.values_mut() {
+        let target = query_disk_target(path)?;
+        *path = target.path.clone();
+        if target.path != disk.path && !data_disks.iter().any(|d| d.path == target.path) {
+            data_disks.push(target);
+        }
+    }
+    build_multi_disk_plan(disk, data_disks, &profile, request)
+}
+
 fn run_plan(args: PlanArgs) -> Result<()> {
-    let disk = query_disk_target(&args.disk)?;
-    let mode: InstallMode = args.mode.into();
-    let plan = build_plan_with_layout_request(disk, mode, args.layout.to_layout_request())?;
+    let plan = plan_from_args(
+        &args.disk,
+        args.mirror_disk.as_deref(),
+        args.mode,
+        args.profile.as_deref(),
+        &args.layout,
+    )?;
 
     if args.json {
         println!("{}", to_pretty_json(&plan)?);
     } else {
         println!("recpart plan");
         println!("  mode: {}", plan.mode);
+        println!("  profile: {}", plan.profile);
+        println!("  firmware: {}", plan.firmware);
+        if let Some(arch) = plan.arch {
+            println!("  arch: {arch}");
+        }
         println!("  disk: {}", plan.disk.path.display());
+        if let Some(mirror) = &plan.mirror_disk {
+            println!("  mirror disk: {}", mirror.path.display());
+        }
+        for data_disk in &plan.data_disks {
+            println!("  data disk: {}", data_disk.path.display());
+        }
         println!("  size: {} bytes", plan.disk.size_bytes);
-        println!("\nGenerated sfdisk script:\n");
-        print!("{}", plan.sfdisk_script);
+        println!("  uuid seed: {}", plan.uuid_seed);
+        if let Some(slot) = plan.install_slot {
+            println!("  install slot: {slot}");
+        }
+        if plan.layout_request.reinstall_slot.is_some() {
+            println!("  reinstall: no partitioning; other partitions kept");
+        }
+        for part in &plan.preserved_partitions {
+            println!(
+                "  preserved: {} (sectors {}-{}, type {})",
+                part.device, part.start_sector, part.end_sector, part.gpt_type
+            );
+        }
+        if let Some(shrink) = &plan.shrink {
+            println!(
+                "  shrink: {} ({}) to sectors {}-{} ({}MB, minimum {}MB)",
+                shrink.device,
+                shrink.filesystem,
+                shrink.start_sector,
+                shrink.new_end_sector,
+                shrink.new_size_bytes / (1024 * 1024),
+                shrink.min_size_bytes.div_ceil(1024 * 1024)
+            );
+        }
+        if let Some(gap) = &plan.free_space {
+            println!(
+                "  free space: sectors {}-{} ({}MB)",
+                gap.start_sector, gap.end_sector, gap.size_mb
+            );
+        }
+        for disk_script in &plan.sfdisk_scripts {
+            println!(
+                "\nGenerated sfdisk script for {}:\n",
+                disk_script.disk.display()
+            );
+            print!("{}", disk_script.script);
+        }
     }
 
     Ok(())
 }
 
 fn run_apply(args: ApplyArgs) -> Result<()> {
-    let disk = query_disk_target(&args.disk)?;
-    let mode: InstallMode = args.mode.into();
-    let plan = build_plan_with_layout_request(disk, mode, args.layout.to_layout_request())?;
+    let plan = plan_from_args(
+        &args.disk,
+        args.mirror_disk.as_deref(),
+        args.mode,
+        args.profile.as_deref(),
+        &args.layout,
+    )?;
+    let luks_key = args.luks_key()?;
 
     let result = execute_plan(
         &plan,
@@ -204,6 +741,7 @@ fn run_apply(args: ApplyArgs) -> Result<()> {
             dry_run: args.dry_run,
             confirm_token: args.confirm,
             mount_root: args.mount_root,
+            luks_key,
         },
     )?;
 
@@ -0,0 +1,70 @@
+// GPT partition type GUIDs from the Discoverable Partitions Specification,
+// so systemd-gpt-auto-generator and other DPS consumers find each partition
+// by role. https://uapi-group.org/specifications/specs/discoverable_partitions_specification/
+use crate::types::{Arch, PartitionTemplate};
+
+pub const ESP: &str = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B";
+pub const XBOOTLDR: &str = "BC13C2FF-59E6-4262-A352-B275FD6F7172";
+pub const SWAP: &str = "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F";
+pub const HOME: &str = "933AC7E1-2EB4-4F13-B844-0E14E2AEF915";
+pub const SRV: &str = "3B8F8425-20E0-4F3B-907F-1A25A76F98E8";
+pub const VAR: &str = "4D21B016-B534-45C2-A9FB-5C16E091FD2D";
+pub const VAR_TMP: &str = "7EC6F557-3BC5-4ACA-B293-16EF5DF639D1";
+pub const LINUX_DATA: &str = "0FC63DAF-8483-4772-8E79-3D69D8477DE4";
+
+pub fn root_type(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86_64 => "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709",
+        Arch::Arm64 => "B921B045-1DF0-41C3-AF44-4C6F280D3FAE",
+    }
+}
+
+pub fn root_verity_type(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86_64 => "2C7357ED-EBD2-46D9-AEC1-23D437EC2BF5",
+        Arch::Arm64 => "DF3300CE-D69F-4C92-978C-9BFB0F38D820",
+    }
+}
+
+pub fn is_esp_type(gpt_type: &str) -> bool {
+    gpt_type == "U" || gpt_type.eq_ignore_ascii_case(ESP)
+}
+
+// A per-slot /boot, such as /slots/A/boot.
+pub fn is_slot_boot(mountpoint: &str) -> bool {
+    mountpoint
+        .strip_prefix("/slots/")
+        .and_then(|rest| rest.split_once('/'))
+        .is_some_and(|(_, path)| path == "boot")
+}
+
+// Mountpoints that end up at sysroot/boot: /boot itself or a slot's /boot.
+pub fn is_boot_mountpoint(mountpoint: &str) -> bool {
+    mountpoint == "/boot" || is_slot_boot(mountpoint)
+}
+
+// The DPS type for a template still using an sfdisk shortcut. Roots (and
+// A/B slots) need a known architecture; without one they stay generic data.
+// The recpart-only `root-verity` shortcut resolves the same way. Explicit
+// GUIDs and the non-DPS RAID/LVM shortcuts are left alone.
+pub fn dps_type(part: &PartitionTemplate, arch: Option<Arch>) -> Option<&'static str> {
+    match part.gpt_type.as_str() {
+        "U" => Some(ESP),
+        "S" => Some(SWAP),
+        "H" => Some(HOME),
+        "root-verity" => Some(arch.map_or(LINUX_DATA, root_verity_type)),
+        "L" => Some(match part.mountpoint.as_str() {
+            "/" => arch.map_or(LINUX_DATA, root_type),
+            // DPS allows one XBOOTLDR per disk, so per-slot /boot stays generic.
+            boot if is_slot_boot(boot) => LINUX_DATA,
+            slot if slot.starts_with("/slots/") => arch.map_or(LINUX_DATA, root_type),
+            "/boot" => XBOOTLDR,
+            "/home" => HOME,
+            "/srv" => SRV,
+            "/var" => VAR,
+            "/var/tmp" => VAR_TMP,
+            _ => LINUX_DATA,
+        }),
+        _ => None,
+    }
+}
@@ -17,6 +17,8 @@ pub enum ErrorCode {
     NotImplemented = 10,
     NotRoot = 11,
     Internal = 12,
+    ResizeFailed = 13,
+    MissingLuksKey = 14,
 }
 
 impl ToolErrorCode for ErrorCode {
@@ -34,6 +36,8 @@ impl ToolErrorCode for ErrorCode {
             ErrorCode::NotImplemented => "E010",
             ErrorCode::NotRoot => "E011",
             ErrorCode::Internal => "E012",
+            ErrorCode::ResizeFailed => "E013",
+            ErrorCode::MissingLuksKey => "E014",
         }
     }
 
@@ -1,14 +1,22 @@
 use crate::error::{ErrorCode, RecpartError, Result};
-use crate::handoff::build_handoff;
-use crate::plan::partition_device;
+use crate::filesystem::filesystem_spec;
+use crate::handoff::{build_handoff, luks_volumes};
+use crate::plan::{
+    block_device, luks_mapper_name, partition_copies, reinstall_attrs, target_disks,
+    template_device, LVM_PV_FILESYSTEM,
+};
+use crate::policy::{reused_esp_min_free_mb, DEFAULT_AB_TARGET_SLOT};
 use crate::preflight::{
     ensure_block_device, ensure_disk_not_mounted, ensure_disk_writable, ensure_mount_root_safe,
-    ensure_required_tools, ensure_root_for_apply,
+    ensure_partition_not_mounted, ensure_required_tools, ensure_reusable_esp,
+    ensure_root_for_apply,
 };
 use crate::types::{
-    ApplyOptions, ApplyResult, CommandRecord, InstallMode, MountMapping, PartitionPlan,
-    APPLY_SCHEMA_VERSION,
+    ApplyOptions, ApplyResult, CommandRecord, Firmware, LuksKey, MountMapping, PartitionPlan,
+    PartitionShrink, PartitionTemplate, APPLY_SCHEMA_VERSION,
 };
+use crate::uuid::filesystem_uuid;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -24,7 +32,7 @@ enum ExecutionAction {
         phase: String,
         program: String,
         args: Vec<String>,
-        stdin: Option<String>,
+        stdin: Option<Vec<u8>>,
     },
     CreateDir {
         phase: String,
@@ -45,17 +53,48 @@ pub fn execute_plan_with_reporter(
 
     if !opts.dry_run {
         ensure_confirmation(opts.confirm_token.as_deref())?;
+        ensure_luks_key(plan, opts.luks_key.as_ref())?;
         ensure_root_for_apply()?;
-        ensure_required_tools()?;
-        ensure_block_device(&plan.disk.path)?;
-        ensure_disk_writable(&plan.disk.path)?;
-        ensure_disk_not_mounted(&plan.disk.path)?;
+        ensure_required_tools(plan)?;
+        for disk in target_disks(plan) {
+            ensure_block_device(disk)?;
+            ensure_disk_writable(disk)?;
+            ensure_disk_not_mounted(disk)?;
+        }
+        // A slot reinstall wipes no disk; the kept ESP and STATE may be
+        // mounted, but the slot it reformats must not be.
+        if plan.layout_request.reinstall_slot.is_some() {
+            ensure_block_device(&plan.disk.path)?;
+            ensure_disk_writable(&plan.disk.path)?;
+            for part in plan.partitions.iter().filter(|p| !p.reused) {
+                ensure_partition_not_mounted(&template_device(part))?;
+            }
+        }
+    }
+    // A slot reinstall keeps the ESP it installed; only a shared one is checked.
+    // The check only reads the ESP, so a dry run makes it too when the device
+    // is present.
+    for part in plan
+        .partitions
+        .iter()
+        .filter(|p| p.reused && plan.layout_request.reinstall_slot.is_none())
+    {
+        let device = block_device(part);
+        if !opts.dry_run || Path::new(&device).exists() {
+            ensure_reusable_esp(&device, reused_esp_min_free_mb(&part.mountpoint))?;
+        }
     }
 
-    let actions = build_apply_actions(plan, &opts.mount_root);
+    let actions = build_apply_actions(plan, &opts.mount_root, opts.luks_key.as_ref());
     let mounted = mount_map_for_plan(plan, &opts.mount_root);
-    let handoff = build_handoff(plan, mounted.clone());
+    let mut handoff = build_handoff(plan, mounted.clone());
     let formatted_devices = formatted_devices(plan);
+    let reused_devices = plan
+        .partitions
+        .iter()
+        .filter(|part| part.reused)
+        .map(block_device)
+        .collect::<Vec<_>>();
 
     if opts.dry_run {
         let steps = actions
@@ -82,6 +121,7 @@ pub fn execute_plan_with_reporter(
             steps,
             partition_map: plan.partitions.clone(),
             formatted_devices,
+            reused_devices,
             mounted,
             handoff,
             warnings: vec![],
@@ -96,6 +136,7 @@ pub fn execute_plan_with_reporter(
         }
         records.push(record);
     }
+    handoff.luks_volumes = luks_volumes(plan, &luks_uuids(&records));
 
     Ok(ApplyResult {
         schema_version: APPLY_SCHEMA_VERSION,
@@ -107,6 +148,7 @@ pub fn execute_plan_with_reporter(
         steps: records,
         partition_map: plan.partitions.clone(),
         formatted_devices,
+        reused_devices,
         mounted,
         handoff,
         warnings: vec![],
@@ -127,26 +169,82 @@ fn ensure_confirmation(token: Option<&str>) -> Result<()> {
     ))
 }
 
-fn build_apply_actions(plan: &PartitionPlan, mount_root: &Path) -> Vec<ExecutionAction> {
+fn ensure_luks_key(plan: &PartitionPlan, key: Option<&LuksKey>) -> Result<()> {
+    let encrypted = plan
+        .partitions
+        .iter()
+        .filter(|p| p.encrypted)
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    if encrypted.is_empty() || key.is_some_and(|key| !key.0.is_empty()) {
+        return Ok(());
+    }
+
+    Err(RecpartError::new(
+        ErrorCode::MissingLuksKey,
+        "apply",
+        "a LUKS passphrase or keyfile is provided for encrypted partitions",
+        format!("no LUKS key for: {}", encrypted.join(", ")),
+        "Re-run with --luks-keyfile <path> or --luks-passphrase-stdin.",
+    ))
+}
+
+fn build_apply_actions(
+    plan: &PartitionPlan,
+    mount_root: &Path,
+    luks_key: Option<&LuksKey>,
+) -> Vec<ExecutionAction> {
     let mut actions = Vec::new();
 
-    actions.push(ExecutionAction::Run {
-        phase: "partition".to_string(),
-        program: "wipefs".to_string(),
-        args: vec![
-            "-a".to_string(),
-            "--force".to_string(),
-            plan.disk.path.to_string_lossy().to_string(),
-        ],
-        stdin: None,
-    });
+    if let Some(shrink) = &plan.shrink {
+        actions.extend(resize_actions(plan, shrink));
+    }
 
-    actions.push(ExecutionAction::Run {
-        phase: "partition".to_string(),
-        program: "sfdisk".to_string(),
-        args: vec![plan.disk.path.to_string_lossy().to_string()],
-        stdin: Some(plan.sfdisk_script.clone()),
-    });
+    for disk_script in &plan.sfdisk_scripts {
+        let disk = disk_script.disk.to_string_lossy().to_string();
+        // Appending into free space keeps the existing table and signatures.
+        let mut args = vec![disk.clone()];
+        if disk_script.append {
+            args.insert(0, "--append".to_string());
+        } else {
+            actions.push(ExecutionAction::Run {
+                phase: "partition".to_string(),
+                program: "wipefs".to_string(),
+                args: vec!["-a".to_string(), "--force".to_string(), disk],
+                stdin: None,
+            });
+        }
+
+        actions.push(ExecutionAction::Run {
+            phase: "partition".to_string(),
+            program: "sfdisk".to_string(),
+            args,
+            stdin: Some(disk_script.script.clone().into_bytes()),
+        });
+    }
+
+    // A slot reinstall writes no table, so it moves the slots' no-auto bit
+    // in place.
+    if plan.layout_request.reinstall_slot.is_some() && plan.firmware != Firmware::BiosMbr {
+        for (part, attrs) in plan
+            .partitions
+            .iter()
+            .filter(|p| p.mountpoint.starts_with("/slots/"))
+            .filter_map(|p| reinstall_attrs(p).map(|attrs| (p, attrs)))
+        {
+            actions.push(ExecutionAction::Run {
+                phase: "partition".to_string(),
+                program: "sfdisk".to_string(),
+                args: vec![
+                    "--part-attrs".to_string(),
+                    part.disk.to_string_lossy().to_string(),
+                    part.index.to_string(),
+                    attrs,
+                ],
+                stdin: None,
+            });
+        }
+    }
 
     actions.push(ExecutionAction::Run {
         phase: "partition".to_string(),
@@ -155,164 +253,355 @@ fn build_apply_actions(plan: &PartitionPlan, mount_root: &Path) -> Vec<Execution
         stdin: None,
     });
 
-    let efi = partition_device(&plan.disk.path, 1);
-    actions.push(ExecutionAction::Run {
-        phase: "format".to_string(),
-        program: "mkfs.vfat".to_string(),
-        args: vec![
-            "-F".to_string(),
-            "32".to_string(),
-            "-n".to_string(),
-            "EFI".to_string(),
-            efi.clone(),
-        ],
-        stdin: None,
-    });
+    actions.extend(raid_actions(plan));
+    actions.extend(lvm_actions(plan));
 
-    match plan.mode {
-        InstallMode::Ab => {
-            let root_a = partition_device(&plan.disk.path, 2);
-            let root_b = partition_device(&plan.disk.path, 3);
-            let state = partition_device(&plan.disk.path, 4);
-
-            for (label, dev) in [
-                ("ROOT_A", root_a.clone()),
-                ("ROOT_B", root_b),
-                ("STATE", state.clone()),
-            ] {
-                actions.push(ExecutionAction::Run {
-                    phase: "format".to_string(),
-                    program: "mkfs.ext4".to_string(),
-                    args: vec!["-F".to_string(), "-L".to_string(), label.to_string(), dev],
-                    stdin: None,
-                });
-            }
+    for part in plan.partitions.iter().filter(|p| p.encrypted) {
+        actions.extend(luks_actions(part, luks_key));
+    }
 
-            let sysroot = mount_root.join("sysroot");
-            actions.push(ExecutionAction::CreateDir {
-                phase: "mount".to_string(),
-                path: sysroot.clone(),
+    for part in plan.partitions.iter().filter(|p| !p.reused) {
+        // Profiles are validated before planning, so every filesystem is known.
+        let Some(spec) = filesystem_spec(&part.filesystem) else {
+            continue;
+        };
+        for device in format_devices(plan, part) {
+            // Mirrored ESP copies get their own seed-derived UUID.
+            let uuid = part.fs_uuid.as_ref().map(|uuid| {
+                if device == template_device(part) {
+                    uuid.clone()
+                } else {
+                    filesystem_uuid(&plan.uuid_seed, spec, &device)
+                }
             });
             actions.push(ExecutionAction::Run {
-                phase: "mount".to_string(),
-                program: "mount".to_string(),
-                args: vec![root_a, sysroot.to_string_lossy().to_string()],
+                phase: "format".to_string(),
+                program: spec.mkfs.to_string(),
+                args: spec.format_args(&part.label, uuid.as_deref(), &device),
                 stdin: None,
             });
+        }
+    }
 
-            let boot = sysroot.join("boot");
-            actions.push(ExecutionAction::CreateDir {
-                phase: "mount".to_string(),
-                path: boot.clone(),
-            });
-            actions.push(ExecutionAction::Run {
-                phase: "mount".to_string(),
-                program: "mount".to_string(),
-                args: vec![efi, boot.to_string_lossy().to_string()],
-                stdin: None,
-            });
+    for part in plan
+        .partitions
+        .iter()
+        .filter(|p| !p.subvolumes.is_empty() && !p.reused)
+    {
+        actions.extend(subvolume_actions(part, mount_root));
+    }
 
-            let state_mount = sysroot.join("state");
-            actions.push(ExecutionAction::CreateDir {
-                phase: "mount".to_string(),
-                path: state_mount.clone(),
-            });
-            actions.push(ExecutionAction::Run {
-                phase: "mount".to_string(),
-                program: "mount".to_string(),
-                args: vec![state, state_mount.to_string_lossy().to_string()],
-                stdin: None,
-            });
+    for mapping in mount_map_for_plan(plan, mount_root) {
+        actions.push(ExecutionAction::CreateDir {
+            phase: "mount".to_string(),
+            path: PathBuf::from(&mapping.path),
+        });
+        let mut args = Vec::new();
+        if let Some(options) = mapping.options {
+            args.extend(["-o".to_string(), options]);
         }
-        InstallMode::Mutable => {
-            let root = partition_device(&plan.disk.path, 2);
-            actions.push(ExecutionAction::Run {
-                phase: "format".to_string(),
-                program: "mkfs.ext4".to_string(),
-                args: vec![
-                    "-F".to_string(),
-                    "-L".to_string(),
-                    "ROOT".to_string(),
-                    root.clone(),
-                ],
-                stdin: None,
-            });
+        args.extend([mapping.device, mapping.path]);
+        actions.push(ExecutionAction::Run {
+            phase: "mount".to_string(),
+            program: "mount".to_string(),
+            args,
+            stdin: None,
+        });
+    }
 
-            let sysroot = mount_root.join("sysroot");
-            actions.push(ExecutionAction::CreateDir {
-                phase: "mount".to_string(),
-                path: sysroot.clone(),
-            });
-            actions.push(ExecutionAction::Run {
-                phase: "mount".to_string(),
-                program: "mount".to_string(),
-                args: vec![root, sysroot.to_string_lossy().to_string()],
+    actions
+}
+
+// The filesystem shrinks first, then its partition entry; the start sector
+// and every other field of the entry are kept.
+fn resize_actions(plan: &PartitionPlan, shrink: &PartitionShrink) -> Vec<ExecutionAction> {
+    let device = shrink.device.clone();
+    let mut actions = match shrink.filesystem.as_str() {
+        "ntfs" => vec![ExecutionAction::Run {
+            phase: "resize".to_string(),
+            program: "ntfsresize".to_string(),
+            // No --force, so unclean or hibernated volumes are still refused;
+            // the "y" answers the proceed prompt for clean ones.
+            args: vec![
+                "--no-progress-bar".to_string(),
+                "--size".to_string(),
+                shrink.new_size_bytes.to_string(),
+                device,
+            ],
+            stdin: Some(b"y\n".to_vec()),
+        }],
+        _ => vec![
+            ExecutionAction::Run {
+                phase: "resize".to_string(),
+                program: "e2fsck".to_string(),
+                args: vec!["-f".to_string(), "-y".to_string(), device.clone()],
                 stdin: None,
-            });
+            },
+            ExecutionAction::Run {
+                phase: "resize".to_string(),
+                program: "resize2fs".to_string(),
+                args: vec![device, format!("{}K", shrink.new_size_bytes / 1024)],
+                stdin: None,
+            },
+        ],
+    };
+    actions.push(ExecutionAction::Run {
+        phase: "resize".to_string(),
+        program: "sfdisk".to_string(),
+        args: vec![
+            "-N".to_string(),
+            shrink.index.to_string(),
+            plan.disk.path.to_string_lossy().to_string(),
+        ],
+        stdin: Some(
+            format!(
+                "start={}, size={}\n",
+                shrink.start_sector,
+                shrink.new_end_sector - shrink.start_sector + 1
+            )
+            .into_bytes(),
+        ),
+    });
+    actions
+}
 
-            let boot = sysroot.join("boot");
-            actions.push(ExecutionAction::CreateDir {
-                phase: "mount".to_string(),
-                path: boot.clone(),
-            });
-            actions.push(ExecutionAction::Run {
-                phase: "mount".to_string(),
-                program: "mount".to_string(),
-                args: vec![efi, boot.to_string_lossy().to_string()],
+// Plain partitions in a mirrored plan (the ESPs) exist once per disk and are
+// formatted identically; everything else has a single backing device.
+fn format_devices(plan: &PartitionPlan, part: &PartitionTemplate) -> Vec<String> {
+    if part.mirrored || part.volume_group.is_some() || part.encrypted {
+        return vec![template_device(part)];
+    }
+    partition_copies(plan, part)
+}
+
+fn raid_actions(plan: &PartitionPlan) -> Vec<ExecutionAction> {
+    plan.partitions
+        .iter()
+        .filter(|p| p.mirrored)
+        .map(|part| {
+            let mut args = vec![
+                "--create".to_string(),
+                block_device(part),
+                "--run".to_string(),
+                "--level=1".to_string(),
+                "--raid-devices=2".to_string(),
+                "--metadata=1.2".to_string(),
+            ];
+            args.extend(partition_copies(plan, part));
+            ExecutionAction::Run {
+                phase: "raid".to_string(),
+                program: "mdadm".to_string(),
+                args,
                 stdin: None,
-            });
+            }
+        })
+        .collect()
+}
+
+fn lvm_actions(plan: &PartitionPlan) -> Vec<ExecutionAction> {
+    let Some(pv) = plan
+        .partitions
+        .iter()
+        .find(|p| p.filesystem == LVM_PV_FILESYSTEM)
+    else {
+        return vec![];
+    };
+    let pv = block_device(pv);
+    let run = |program: &str, args: Vec<String>| ExecutionAction::Run {
+        phase: "lvm".to_string(),
+        program: program.to_string(),
+        args,
+        stdin: None,
+    };
+
+    let mut actions = vec![run(
+        "pvcreate",
+        vec!["-ff".to_string(), "-y".to_string(), pv.clone()],
+    )];
+    let mut vg_created = false;
+    for lv in &plan.partitions {
+        let Some(vg) = &lv.volume_group else {
+            continue;
+        };
+        if !vg_created {
+            actions.push(run("vgcreate", vec![vg.clone(), pv.clone()]));
+            vg_created = true;
         }
+        let size = match lv.size_mb {
+            Some(mb) => vec!["-L".to_string(), format!("{mb}M")],
+            None => vec!["-l".to_string(), "100%FREE".to_string()],
+        };
+        let mut args = vec![
+            "-y".to_string(),
+            "--wipesignatures".to_string(),
+            "y".to_string(),
+            "-n".to_string(),
+            lv.name.clone(),
+        ];
+        args.extend(size);
+        args.push(vg.clone());
+        actions.push(run("lvcreate", args));
     }
+    actions
+}
+
+// The key reaches cryptsetup on stdin (--key-file -) so it never shows up in
+// the rendered command list.
+fn luks_actions(part: &PartitionTemplate, luks_key: Option<&LuksKey>) -> Vec<ExecutionAction> {
+    let device = block_device(part);
+    let key = luks_key.map(|key| key.0.clone());
+    let cryptsetup = |args: &[&str], stdin: Option<Vec<u8>>| ExecutionAction::Run {
+        phase: "encrypt".to_string(),
+        program: "cryptsetup".to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        stdin,
+    };
+
+    vec![
+        cryptsetup(
+            &[
+                "luksFormat",
+                "--type",
+                "luks2",
+                "--batch-mode",
+                "--key-file",
+                "-",
+                &device,
+            ],
+            key.clone(),
+        ),
+        cryptsetup(
+            &["open", "--key-file", "-", &device, &luks_mapper_name(part)],
+            key,
+        ),
+        cryptsetup(&["luksUUID", &device], None),
+    ]
+}
+
+fn luks_uuids(records: &[CommandRecord]) -> HashMap<String, String> {
+    records
+        .iter()
+        .filter(|r| r.program == "cryptsetup" && r.args.first().is_some_and(|a| a == "luksUUID"))
+        .filter_map(|r| Some((r.args.get(1)?.clone(), r.stdout.clone()?)))
+        .collect()
+}
 
+// Subvolumes are created from a temporary top-level mount that is released
+// before the real subvol= mounts happen.
+fn subvolume_actions(part: &PartitionTemplate, mount_root: &Path) -> Vec<ExecutionAction> {
+    let device = template_device(part);
+    let top_level = mount_root.join(format!(".btrfs-{}", part.name));
+    let top_level_arg = top_level.to_string_lossy().to_string();
+
+    let mut actions = vec![
+        ExecutionAction::CreateDir {
+            phase: "format".to_string(),
+            path: top_level.clone(),
+        },
+        ExecutionAction::Run {
+            phase: "format".to_string(),
+            program: "mount".to_string(),
+            args: vec![device, top_level_arg.clone()],
+            stdin: None,
+        },
+    ];
+    for sub in &part.subvolumes {
+        actions.push(ExecutionAction::Run {
+            phase: "format".to_string(),
+            program: "btrfs".to_string(),
+            args: vec![
+                "subvolume".to_string(),
+                "create".to_string(),
+                top_level.join(&sub.name).to_string_lossy().to_string(),
+            ],
+            stdin: None,
+        });
+    }
+    actions.push(ExecutionAction::Run {
+        phase: "format".to_string(),
+        program: "umount".to_string(),
+        args: vec![top_level_arg],
+        stdin: None,
+    });
     actions
 }
 
 fn formatted_devices(plan: &PartitionPlan) -> Vec<String> {
     plan.partitions
         .iter()
-        .map(|part| partition_device(&plan.disk.path, part.index))
+        .filter(|part| filesystem_spec(&part.filesystem).is_some() && !part.reused)
+        .flat_map(|part| format_devices(plan, part))
+        .collect()
+}
+
+fn partition_devices(plan: &PartitionPlan) -> Vec<String> {
+    plan.partitions
+        .iter()
+        .filter(|part| part.volume_group.is_none())
+        .flat_map(|part| partition_copies(plan, part))
         .collect()
 }
 
 fn mount_map_for_plan(plan: &PartitionPlan, mount_root: &Path) -> Vec<MountMapping> {
-    match plan.mode {
-        InstallMode::Mutable => vec![
-            MountMapping {
-                path: mount_root.join("sysroot").to_string_lossy().to_string(),
-                device: partition_device(&plan.disk.path, 2),
-            },
-            MountMapping {
-                path: mount_root
-                    .join("sysroot")
-                    .join("boot")
-                    .to_string_lossy()
-                    .to_string(),
-                device: partition_device(&plan.disk.path, 1),
-            },
-        ],
-        InstallMode::Ab => vec![
-            MountMapping {
-                path: mount_root.join("sysroot").to_string_lossy().to_string(),
-                device: partition_device(&plan.disk.path, 2),
-            },
-            MountMapping {
-                path: mount_root
-                    .join("sysroot")
-                    .join("boot")
-                    .to_string_lossy()
-                    .to_string(),
-                device: partition_device(&plan.disk.path, 1),
-            },
-            MountMapping {
-                path: mount_root
-                    .join("sysroot")
-                    .join("state")
+    let sysroot = mount_root.join("sysroot");
+    let target_slot = format!(
+        "/slots/{}",
+        plan.install_slot.unwrap_or(DEFAULT_AB_TARGET_SLOT)
+    );
+    let mut root = Vec::new();
+    let mut nested = Vec::new();
+
+    for part in plan
+        .partitions
+        .iter()
+        .filter(|p| p.mountpoint.starts_with('/'))
+    {
+        let device = template_device(part);
+        let mounts = if part.subvolumes.is_empty() {
+            vec![(part.mountpoint.as_str(), None)]
+        } else {
+            part.subvolumes
+                .iter()
+                .map(|sub| {
+                    (
+                        sub.mountpoint.as_str(),
+                        Some(format!("subvol={}", sub.name)),
+                    )
+                })
+                .collect()
+        };
+
+        for (mountpoint, options) in mounts {
+            if mountpoint == "/" || mountpoint == target_slot {
+                root.push(MountMapping {
+                    path: sysroot.to_string_lossy().to_string(),
+                    device: device.clone(),
+                    options,
+                });
+                continue;
+            }
+            // Paths under the target slot (its /boot) nest under sysroot;
+            // the other slot stays unmounted.
+            let relative = match mountpoint.strip_prefix(target_slot.as_str()) {
+                Some(rest) if rest.starts_with('/') => rest,
+                _ if mountpoint.starts_with("/slots/") => continue,
+                _ => mountpoint,
+            };
+            nested.push(MountMapping {
+                path: sysroot
+                    .join(relative.trim_start_matches('/'))
                     .to_string_lossy()
                     .to_string(),
-                device: partition_device(&plan.disk.path, 4),
-            },
-        ],
+                device: device.clone(),
+                options,
+            });
+        }
     }
+
+    // Parents must be mounted before anything nested beneath them.
+    nested.sort_by_key(|m| Path::new(&m.path).components().count());
+    root.extend(nested);
+    root
 }
 
 fn run_action(
@@ -369,7 +658,7 @@ fn run_action(
 
             if let Some(stdin_payload) = stdin {
                 if let Some(mut handle) = child.stdin.take() {
-                    handle.write_all(stdin_payload.as_bytes()).map_err(|err| {
+                    handle.write_all(stdin_payload).map_err(|err| {
                         RecpartError::new(
                             classify_phase_error(phase),
                             "exec",
@@ -395,7 +684,7 @@ fn run_action(
             let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
             let status = output.status.code().unwrap_or(-1);
 
-            if !output.status.success() {
+            if !accepted_exit_status(program, status) {
                 return Err(RecpartError::new(
                     classify_phase_error(phase),
                     "exec",
@@ -411,7 +700,7 @@ fn run_action(
             }
 
             if phase == "partition" {
-                wait_for_partition_devices(&formatted_devices(plan), Duration::from_secs(8))?;
+                wait_for_partition_devices(&partition_devices(plan), Duration::from_secs(8))?;
             }
 
             Ok(CommandRecord {
@@ -436,10 +725,17 @@ fn run_action(
     }
 }
 
+// e2fsck exits 1 when it corrected errors, which still leaves the filesystem
+// ready to resize.
+pub fn accepted_exit_status(program: &str, status: i32) -> bool {
+    status == 0 || (program == "e2fsck" && status == 1)
+}
+
 fn classify_phase_error(phase: &str) -> ErrorCode {
     match phase {
-        "partition" => ErrorCode::PartitionApplyFailed,
-        "format" => ErrorCode::FormatFailed,
+        "resize" => ErrorCode::ResizeFailed,
+        "partition" | "raid" | "lvm" => ErrorCode::PartitionApplyFailed,
+        "encrypt" | "format" => ErrorCode::FormatFailed,
         "mount" => ErrorCode::MountFailed,
         _ => ErrorCode::Internal,
     }
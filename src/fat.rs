@@ -0,0 +1,99 @@
+// Free space on a FAT12/16/32 filesystem, read straight from the device so
+// a reused ESP can be checked without mounting it. Free clusters are counted
+// in the first FAT; the FAT32 FSInfo hint is not trusted because other
+// operating systems leave it stale.
+use std::io::{self, Read, Seek, SeekFrom};
+
+const FAT12_MAX_CLUSTERS: u64 = 4084;
+const FAT16_MAX_CLUSTERS: u64 = 65524;
+
+pub fn fat_free_bytes<R: Read + Seek>(reader: &mut R) -> io::Result<u64> {
+    let mut boot = [0u8; 512];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut boot)?;
+
+    let u16_at = |pos: usize| u64::from(u16::from_le_bytes([boot[pos], boot[pos + 1]]));
+    let u32_at = |pos: usize| {
+        u64::from(u32::from_le_bytes([
+            boot[pos],
+            boot[pos + 1],
+            boot[pos + 2],
+            boot[pos + 3],
+        ]))
+    };
+    let bytes_per_sector = u16_at(11);
+    let sectors_per_cluster = u64::from(boot[13]);
+    let reserved = u16_at(14);
+    let fats = u64::from(boot[16]);
+    let root_entries = u16_at(17);
+    let total = match u16_at(19) {
+        0 => u32_at(32),
+        sectors => sectors,
+    };
+    let fat_sectors = match u16_at(22) {
+        0 => u32_at(36),
+        sectors => sectors,
+    };
+    if boot[510..] != [0x55, 0xaa]
+        || !bytes_per_sector.is_power_of_two()
+        || !(512..=4096).contains(&bytes_per_sector)
+        || !sectors_per_cluster.is_power_of_two()
+        || reserved == 0
+        || fats == 0
+        || fat_sectors == 0
+    {
+        return Err(invalid("no FAT boot sector"));
+    }
+
+    let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+    let meta_sectors = reserved + fats * fat_sectors + root_sectors;
+    let clusters = total
+        .checked_sub(meta_sectors)
+        .ok_or_else(|| invalid("FAT metadata exceeds the filesystem size"))?
+        / sectors_per_cluster;
+
+    let entry_bits = if clusters <= FAT12_MAX_CLUSTERS {
+        12
+    } else if clusters <= FAT16_MAX_CLUSTERS {
+        16
+    } else {
+        32
+    };
+    // Cluster numbering starts at 2.
+    let fat_bytes = ((clusters + 2) * entry_bits).div_ceil(8);
+    if fat_bytes > fat_sectors * bytes_per_sector {
+        return Err(invalid("FAT is too small for the cluster count"));
+    }
+    let mut fat = vec![0u8; fat_bytes as usize];
+    reader.seek(SeekFrom::Start(reserved * bytes_per_sector))?;
+    reader.read_exact(&mut fat)?;
+
+    let entry = |cluster: u64| -> u32 {
+        let byte = |pos: u64| fat.get(pos as usize).copied().map_or(0xff, u32::from);
+        match entry_bits {
+            12 => {
+                let pos = cluster * 3 / 2;
+                let value = byte(pos) | byte(pos + 1) << 8;
+                if cluster.is_multiple_of(2) {
+                    value & 0xfff
+                } else {
+                    value >> 4
+                }
+            }
+            16 => byte(cluster * 2) | byte(cluster * 2 + 1) << 8,
+            _ => {
+                let pos = cluster * 4;
+                (byte(pos) | byte(pos + 1) << 8 | byte(pos + 2) << 16 | byte(pos + 3) << 24)
+                    & 0x0fff_ffff
+            }
+        }
+    };
+    let free = (2..clusters + 2)
+        .filter(|&cluster| entry(cluster) == 0)
+        .count() as u64;
+    Ok(free * sectors_per_cluster * bytes_per_sector)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,135 @@
+// Format rules for every filesystem a plan may use. The executor, profile
+// validation and preflight all read this table so they cannot drift apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemSpec {
+    pub name: &'static str,
+    pub mkfs: &'static str,
+    pub mkfs_args: &'static [&'static str],
+    pub label_flag: &'static str,
+    pub max_label_bytes: usize,
+    pub min_size_mb: u32,
+    pub data: bool,
+    // Flag and value prefix that set the filesystem UUID at mkfs time.
+    pub uuid_flag: &'static str,
+    pub uuid_prefix: &'static str,
+    // The filesystem takes a 32-bit volume ID rather than a full UUID.
+    pub volume_id: bool,
+}
+
+pub const FILESYSTEMS: &[FilesystemSpec] = &[
+    FilesystemSpec {
+        name: "vfat",
+        mkfs: "mkfs.vfat",
+        mkfs_args: &["-F", "32"],
+        label_flag: "-n",
+        max_label_bytes: 11,
+        min_size_mb: 33,
+        data: false,
+        uuid_flag: "-i",
+        uuid_prefix: "",
+        volume_id: true,
+    },
+    FilesystemSpec {
+        name: "ext4",
+        mkfs: "mkfs.ext4",
+        mkfs_args: &["-F"],
+        label_flag: "-L",
+        max_label_bytes: 16,
+        min_size_mb: 8,
+        data: true,
+        uuid_flag: "-U",
+        uuid_prefix: "",
+        volume_id: false,
+    },
+    FilesystemSpec {
+        name: "btrfs",
+        mkfs: "mkfs.btrfs",
+        mkfs_args: &["-f"],
+        label_flag: "-L",
+        max_label_bytes: 255,
+        min_size_mb: 256,
+        data: true,
+        uuid_flag: "-U",
+        uuid_prefix: "",
+        volume_id: false,
+    },
+    // mkfs.xfs refuses filesystems smaller than 300MB since xfsprogs 5.19.
+    FilesystemSpec {
+        name: "xfs",
+        mkfs: "mkfs.xfs",
+        mkfs_args: &["-f"],
+        label_flag: "-L",
+        max_label_bytes: 12,
+        min_size_mb: 300,
+        data: true,
+        uuid_flag: "-m",
+        uuid_prefix: "uuid=",
+        volume_id: false,
+    },
+    FilesystemSpec {
+        name: "f2fs",
+        mkfs: "mkfs.f2fs",
+        mkfs_args: &["-f"],
+        label_flag: "-l",
+        max_label_bytes: 512,
+        min_size_mb: 64,
+        data: true,
+        uuid_flag: "-U",
+        uuid_prefix: "",
+        volume_id: false,
+    },
+    FilesystemSpec {
+        name: "swap",
+        mkfs: "mkswap",
+        mkfs_args: &[],
+        label_flag: "-L",
+        max_label_bytes: 16,
+        min_size_mb: 1,
+        data: false,
+        uuid_flag: "-U",
+        uuid_prefix: "",
+        volume_id: false,
+    },
+];
+
+// Profiles use this filesystem for partitions left unformatted, such as
+// dm-verity hash partitions.
+pub const UNFORMATTED_FILESYSTEM: &str = "none";
+
+pub fn filesystem_spec(name: &str) -> Option<&'static FilesystemSpec> {
+    FILESYSTEMS.iter().find(|spec| spec.name == name)
+}
+
+pub fn filesystem_names(data_only: bool) -> Vec<&'static str> {
+    FILESYSTEMS
+        .iter()
+        .filter(|spec| spec.data || !data_only)
+        .map(|spec| spec.name)
+        .collect()
+}
+
+impl FilesystemSpec {
+    // `uuid` is in the form blkid reports; mkfs.vfat wants the volume ID
+    // without its dash.
+    pub fn format_args(&self, label: &str, uuid: Option<&str>, device: &str) -> Vec<String> {
+        let mut args = self
+            .mkfs_args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        args.extend([self.label_flag.to_string(), label.to_string()]);
+        if let Some(uuid) = uuid {
+            let value = if self.volume_id {
+                uuid.replace('-', "")
+            } else {
+                uuid.to_string()
+            };
+            args.extend([
+                self.uuid_flag.to_string(),
+                format!("{}{value}", self.uuid_prefix),
+            ]);
+        }
+        args.push(device.to_string());
+        args
+    }
+}
@@ -0,0 +1,154 @@
+use crate::error::{ErrorCode, RecpartError, Result};
+use crate::types::{DiskTarget, Firmware, FreeGap, PartitionTable, PartitionTemplate};
+
+const MIB: u64 = 1024 * 1024;
+// The backup GPT header plus its 16KiB partition entry array.
+const GPT_ENTRIES_BYTES: u64 = 16 * 1024;
+
+// Partition starts land on multiples of this many bytes (after the disk's
+// alignment offset): 1MiB, widened to the physical sector and the optimal
+// I/O size. Some USB bridges report non-power-of-two optimal I/O sizes;
+// those are ignored rather than inflating the grain.
+pub fn alignment_grain_bytes(disk: &DiskTarget) -> u64 {
+    let mut grain = MIB;
+    for bytes in [disk.physical_sector_bytes, disk.optimal_io_bytes] {
+        let bytes = u64::from(bytes);
+        if bytes.is_power_of_two() {
+            grain = grain.max(bytes);
+        }
+    }
+    grain
+}
+
+// Last logical sector a partition may use: MBR can use the whole disk,
+// GPT keeps its backup header and entries at the end.
+pub fn last_usable_sector(disk: &DiskTarget, firmware: Firmware) -> u64 {
+    let sector = sector_bytes(disk);
+    let total = disk.size_bytes / sector;
+    match firmware {
+        Firmware::BiosMbr => total.saturating_sub(1),
+        Firmware::Uefi | Firmware::BiosGpt => {
+            total.saturating_sub(2 + GPT_ENTRIES_BYTES.div_ceil(sector))
+        }
+    }
+}
+
+// Lays out the partitions placed on `disk` back to back from the first
+// aligned offset past 1MiB. Fixed sizes are exact; the remaining-space
+// partition ends at the last usable sector.
+pub fn assign_sectors(
+    partitions: &mut [PartitionTemplate],
+    disk: &DiskTarget,
+    firmware: Firmware,
+) -> Result<()> {
+    let first = MIB / sector_bytes(disk);
+    assign_sectors_between(partitions, disk, first, last_usable_sector(disk, firmware))
+}
+
+// Same as `assign_sectors`, confined to sectors `first..=last_usable` (a
+// free-space gap between existing partitions).
+pub fn assign_sectors_between(
+    partitions: &mut [PartitionTemplate],
+    disk: &DiskTarget,
+    first: u64,
+    last_usable: u64,
+) -> Result<()> {
+    let sector = sector_bytes(disk);
+    let grain = alignment_grain_bytes(disk);
+    let offset = u64::from(disk.alignment_offset_bytes) % grain;
+    let mut next_bytes = first * sector;
+
+    for part in partitions
+        .iter_mut()
+        .filter(|p| p.disk == disk.path && p.volume_group.is_none() && !p.reused)
+    {
+        let start = align_up(next_bytes, grain, offset).div_ceil(sector);
+        let end = match part.size_mb {
+            Some(mb) => start + u64::from(mb) * MIB / sector - 1,
+            None => last_usable,
+        };
+        if end > last_usable || start > end {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "aligned partitions fit inside the disk's usable sectors",
+                format!(
+                    "partition '{}' needs sectors {start}-{end}, last usable sector is {last_usable}",
+                    part.name
+                ),
+                "Reduce fixed partition sizes or select a larger disk.",
+            ));
+        }
+        part.start_sector = Some(start);
+        part.end_sector = Some(end);
+        next_bytes = (end + 1) * sector;
+    }
+    Ok(())
+}
+
+// Unallocated runs of at least 1MiB in an existing table, with starts
+// aligned like new partitions. Sectors before 1MiB are never offered.
+pub fn free_gaps(table: &PartitionTable, disk: &DiskTarget, firmware: Firmware) -> Vec<FreeGap> {
+    let sector = sector_bytes(disk);
+    let grain = alignment_grain_bytes(disk);
+    let offset = u64::from(disk.alignment_offset_bytes) % grain;
+    let first = table.first_lba.unwrap_or(0).max(MIB / sector);
+    let last = table
+        .last_lba
+        .unwrap_or_else(|| last_usable_sector(disk, firmware));
+
+    let mut gaps = Vec::new();
+    let mut next = first;
+    let bounds = table
+        .partitions
+        .iter()
+        .map(|p| (p.start_sector, p.end_sector))
+        .chain([(last + 1, last)]);
+    for (start, end) in bounds {
+        let gap_start = align_up(next * sector, grain, offset).div_ceil(sector);
+        if start > gap_start {
+            let gap_end = start - 1;
+            let size_mb = (gap_end - gap_start + 1) * sector / MIB;
+            if size_mb > 0 {
+                gaps.push(FreeGap {
+                    start_sector: gap_start,
+                    end_sector: gap_end,
+                    size_mb,
+                });
+            }
+        }
+        next = next.max(end + 1);
+    }
+    gaps
+}
+
+// The nearest partition start at or after (up) or at or before (down)
+// `sector`, aligned like new partitions.
+pub fn align_sector_up(disk: &DiskTarget, sector: u64) -> u64 {
+    let bytes = sector_bytes(disk);
+    let grain = alignment_grain_bytes(disk);
+    let offset = u64::from(disk.alignment_offset_bytes) % grain;
+    align_up(sector * bytes, grain, offset).div_ceil(bytes)
+}
+
+pub fn align_sector_down(disk: &DiskTarget, sector: u64) -> u64 {
+    let bytes = sector_bytes(disk);
+    let grain = alignment_grain_bytes(disk);
+    let offset = u64::from(disk.alignment_offset_bytes) % grain;
+    let position = sector * bytes;
+    if position < offset {
+        return 0;
+    }
+    (offset + (position - offset) / grain * grain) / bytes
+}
+
+fn sector_bytes(disk: &DiskTarget) -> u64 {
+    u64::from(disk.logical_sector_bytes.max(1))
+}
+
+fn align_up(bytes: u64, grain: u64, offset: u64) -> u64 {
+    if bytes <= offset {
+        return offset;
+    }
+    offset + (bytes - offset).div_ceil(grain) * grain
+}
@@ -1,8 +1,13 @@
-use crate::plan::partition_device;
+use crate::dps::{is_esp_type, XBOOTLDR};
+use crate::plan::{
+    block_device, luks_mapper_name, partition_copies, partition_device, template_device,
+};
 use crate::policy::DEFAULT_AB_TARGET_SLOT;
 use crate::types::{
-    HandoffPayload, InstallMode, ModeContext, MountMapping, PartitionPlan, HANDOFF_SCHEMA_VERSION,
+    AbSlot, BootloaderTarget, Firmware, HandoffPayload, InstallMode, LuksVolume, ModeContext,
+    MountMapping, PartitionPlan, RaidArray, HANDOFF_SCHEMA_VERSION,
 };
+use std::collections::HashMap;
 
 pub fn build_handoff(plan: &PartitionPlan, mounted: Vec<MountMapping>) -> HandoffPayload {
     let install_target = mounted
@@ -17,15 +22,34 @@ pub fn build_handoff(plan: &PartitionPlan, mounted: Vec<MountMapping>) -> Handof
         format!("recchroot {install_target}"),
     ];
 
-    let mode_context = match plan.mode {
+    let mut mode_context = match plan.mode {
         InstallMode::Ab => {
-            let slot_a_device = Some(partition_device(&plan.disk.path, 2));
-            let slot_b_device = Some(partition_device(&plan.disk.path, 3));
-            let install_target_slot = DEFAULT_AB_TARGET_SLOT.to_string();
-            let inactive_slot_hint = if install_target_slot == "A" {
-                "B".to_string()
+            let slot = plan.install_slot.unwrap_or(DEFAULT_AB_TARGET_SLOT);
+            let inactive = slot.other();
+            let slot_device = |slot: AbSlot| {
+                plan.partitions
+                    .iter()
+                    .find(|part| part.mountpoint == format!("/slots/{slot}"))
+                    .map(template_device)
+            };
+            let named_device = |name: &str| {
+                plan.partitions
+                    .iter()
+                    .find(|part| part.name == name)
+                    .map(template_device)
+            };
+            let boot_device = |slot: AbSlot| {
+                plan.partitions
+                    .iter()
+                    .find(|part| part.mountpoint == format!("/slots/{slot}/boot"))
+                    .map(template_device)
+            };
+            let target_note = if plan.layout_request.reinstall_slot.is_some() {
+                format!(
+                    "Slot {slot} was reformatted and cleared of no-auto; slot {inactive} is marked no-auto; the ESP and state were kept."
+                )
             } else {
-                "A".to_string()
+                format!("A/B install targets slot {slot}; slot {inactive} is marked no-auto until recab fills it.")
             };
             next_commands.push("recab status".to_string());
             next_commands.push(
@@ -33,12 +57,19 @@ pub fn build_handoff(plan: &PartitionPlan, mounted: Vec<MountMapping>) -> Handof
             );
 
             ModeContext {
-                install_target_slot: Some(install_target_slot),
-                inactive_slot_hint: Some(inactive_slot_hint),
-                slot_a_device,
-                slot_b_device,
+                install_target_slot: Some(slot.to_string()),
+                inactive_slot_hint: Some(inactive.to_string()),
+                slot_a_device: slot_device(AbSlot::A),
+                slot_b_device: slot_device(AbSlot::B),
+                install_target_device: slot_device(slot),
+                inactive_slot_device: slot_device(inactive),
+                recovery_device: named_device("recovery"),
+                boot_a_device: boot_device(AbSlot::A),
+                boot_b_device: boot_device(AbSlot::B),
+                root_a_verity_device: named_device("root_a_verity"),
+                root_b_verity_device: named_device("root_b_verity"),
                 notes: vec![
-                    "A/B mode defaults install target to slot A for first install run.".to_string(),
+                    target_note,
                     "Use recab to manage active/inactive slot transitions after installation."
                         .to_string(),
                 ],
@@ -49,16 +80,133 @@ pub fn build_handoff(plan: &PartitionPlan, mounted: Vec<MountMapping>) -> Handof
             inactive_slot_hint: None,
             slot_a_device: None,
             slot_b_device: None,
+            install_target_device: None,
+            inactive_slot_device: None,
+            recovery_device: None,
+            boot_a_device: None,
+            boot_b_device: None,
+            root_a_verity_device: None,
+            root_b_verity_device: None,
             notes: vec!["Mutable mode uses a single writable root partition.".to_string()],
         },
     };
 
+    let swap_devices = plan
+        .partitions
+        .iter()
+        .filter(|part| part.filesystem == "swap")
+        .map(template_device)
+        .collect();
+    let raid_arrays = raid_arrays(plan);
+    if !raid_arrays.is_empty() {
+        next_commands.push(format!(
+            "mdadm --detail --scan >> {install_target}/etc/mdadm.conf"
+        ));
+    }
+    if let Some(mirror) = &plan.mirror_disk {
+        for part in plan.partitions.iter().filter(|p| p.filesystem == "vfat") {
+            mode_context.notes.push(format!(
+                "{} on {} is formatted identically to {}; sync it after bootloader installs.",
+                part.label,
+                partition_device(&mirror.path, part.index),
+                partition_device(&part.disk, part.index)
+            ));
+        }
+    }
+
+    let bootloader = bootloader_target(plan);
+    if !bootloader.boot_disks.is_empty() {
+        next_commands.push(format!(
+            "# install BIOS boot code (grub-install --target=i386-pc) to {}",
+            bootloader.boot_disks.join(" ")
+        ));
+    }
+
+    let luks_volumes = luks_volumes(plan, &HashMap::new());
+    if !luks_volumes.is_empty() {
+        next_commands.push(format!(
+            "# write luks_volumes[].crypttab lines to {install_target}/etc/crypttab"
+        ));
+    }
+
     HandoffPayload {
         schema_version: HANDOFF_SCHEMA_VERSION,
         mode: plan.mode,
         install_target,
         mount_map: mounted,
+        swap_devices,
+        luks_volumes,
+        raid_arrays,
+        bootloader,
         next_commands,
         mode_context,
     }
 }
+
+fn bootloader_target(plan: &PartitionPlan) -> BootloaderTarget {
+    match plan.firmware {
+        Firmware::Uefi => BootloaderTarget {
+            firmware: plan.firmware,
+            efi_directory: plan
+                .partitions
+                .iter()
+                .find(|part| is_esp_type(&part.gpt_type))
+                .map(|part| part.mountpoint.clone()),
+            xbootldr_directory: plan
+                .partitions
+                .iter()
+                .find(|part| part.gpt_type == XBOOTLDR)
+                .map(|part| part.mountpoint.clone()),
+            boot_disks: vec![],
+        },
+        Firmware::BiosGpt | Firmware::BiosMbr => BootloaderTarget {
+            firmware: plan.firmware,
+            efi_directory: None,
+            xbootldr_directory: None,
+            boot_disks: [Some(&plan.disk), plan.mirror_disk.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|disk| disk.path.to_string_lossy().to_string())
+                .collect(),
+        },
+    }
+}
+
+// LUKS UUIDs are only known once luksFormat has run; until then crypttab
+// falls back to the partition device path.
+pub fn luks_volumes(plan: &PartitionPlan, uuids: &HashMap<String, String>) -> Vec<LuksVolume> {
+    plan.partitions
+        .iter()
+        .filter(|part| part.encrypted)
+        .map(|part| {
+            let device = block_device(part);
+            let mapper_name = luks_mapper_name(part);
+            let uuid = uuids.get(&device).cloned();
+            let source = match &uuid {
+                Some(uuid) => format!("UUID={uuid}"),
+                None => device.clone(),
+            };
+            LuksVolume {
+                partition: part.name.clone(),
+                crypttab: format!("{mapper_name} {source} none luks"),
+                mapper_device: template_device(part),
+                device,
+                mapper_name,
+                uuid,
+            }
+        })
+        .collect()
+}
+
+fn raid_arrays(plan: &PartitionPlan) -> Vec<RaidArray> {
+    plan.partitions
+        .iter()
+        .filter(|part| part.mirrored)
+        .map(|part| RaidArray {
+            partition: part.name.clone(),
+            device: block_device(part),
+            level: 1,
+            members: partition_copies(plan, part),
+        })
+        .collect()
+}
@@ -1,17 +1,32 @@
 pub mod cli;
+pub mod dps;
 pub mod error;
 pub mod exec;
+pub mod fat;
+pub mod filesystem;
+pub mod geometry;
 pub mod handoff;
 pub mod json;
 pub mod plan;
 pub mod policy;
 pub mod preflight;
+pub mod profile;
+pub mod resize;
+pub mod size;
+pub mod table;
 pub mod types;
+pub mod uuid;
 
 pub use error::{ErrorCode, RecpartError, Result};
 pub use exec::{execute_plan, execute_plan_with_reporter};
-pub use plan::{build_plan, build_plan_with_layout_request};
+pub use plan::{
+    build_free_space_plan, build_mirrored_plan, build_multi_disk_plan, build_plan,
+    build_plan_with_layout_request, build_plan_with_profile, build_shrink_plan,
+    build_slot_reinstall_plan,
+};
+pub use size::SizeExpr;
 pub use types::{
-    ApplyOptions, ApplyResult, DiskInventory, DiskListResult, DiskTarget, HandoffPayload,
-    InstallMode, LayoutRequest, PartitionPlan, ResolvedLayout,
+    ApplyOptions, ApplyResult, Arch, DiskInspectResult, DiskInventory, DiskListResult, DiskTarget,
+    Firmware, HandoffPayload, InstallMode, LayoutProfile, LayoutRequest, PartitionPlan,
+    ResolvedLayout,
 };
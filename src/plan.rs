@@ -1,11 +1,34 @@
+use crate::dps::{dps_type, is_boot_mountpoint, is_esp_type};
 use crate::error::{ErrorCode, RecpartError, Result};
-use crate::policy::{build_templates, required_min_mb, resolve_layout_request};
+use crate::filesystem::filesystem_spec;
+use crate::geometry::{
+    align_sector_down, align_sector_up, assign_sectors, assign_sectors_between, free_gaps,
+    last_usable_sector,
+};
+use crate::policy::{required_min_mb, resolve_layout_request, DEFAULT_AB_TARGET_SLOT};
+use crate::profile::{
+    apply_filesystem_overrides, builtin_profile, record_layout_sizes, templates_from_profile,
+    validate_profile,
+};
+use crate::table::find_partition;
 use crate::types::{
-    DiskTarget, InstallMode, LayoutRequest, PartitionPlan, PartitionTemplate, PLAN_SCHEMA_VERSION,
+    AbSlot, Arch, DiskInspectResult, DiskScript, DiskTarget, ExistingPartition, Firmware, FreeGap,
+    GptAttribute, InspectedPartition, InstallMode, LayoutProfile, LayoutRequest, PartitionPlan,
+    PartitionShrink, PartitionTable, PartitionTemplate, ResolvedLayout, ShrinkCandidate,
+    PLAN_SCHEMA_VERSION,
+};
+use crate::uuid::{
+    default_seed, disk_label_id, filesystem_uuid, mbr_partition_uuid, partition_uuid,
 };
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 const MB: u64 = 1024 * 1024;
+pub const LVM_PV_FILESYSTEM: &str = "lvm";
+pub const BIOS_BOOT_FILESYSTEM: &str = "bios_boot";
+const BIOS_BOOT_GPT_TYPE: &str = "21686148-6449-6E6F-744E-656564454649";
+// sfdisk type shortcuts shared by GPT and MBR labels.
+const MBR_TYPE_SHORTCUTS: &[&str] = &["L", "S", "E", "X", "U", "R", "V"];
 
 pub fn build_plan(disk: DiskTarget, mode: InstallMode) -> Result<PartitionPlan> {
     build_plan_with_layout_request(disk, mode, LayoutRequest::default())
@@ -16,53 +39,1161 @@ pub fn build_plan_with_layout_request(
     mode: InstallMode,
     layout_request: LayoutRequest,
 ) -> Result<PartitionPlan> {
-    let resolved_layout = resolve_layout_request(mode, &layout_request)?;
-    let disk_mb = disk.size_bytes / MB;
-    let min_required = u64::from(required_min_mb(&resolved_layout));
+    let profile = builtin_profile(mode)?;
+    build_plan_with_profile(disk, &profile, layout_request)
+}
+
+pub fn build_plan_with_profile(
+    disk: DiskTarget,
+    profile: &LayoutProfile,
+    layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    build_plan_for_disks(disk, None, vec![], None, None, profile, layout_request)
+}
+
+// Keeps `table` (the disk's current partitions) and places the profile's
+// partitions in one of its free gaps. Nothing outside the gap is written.
+pub fn build_free_space_plan(
+    disk: DiskTarget,
+    table: PartitionTable,
+    profile: &LayoutProfile,
+    mut layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    layout_request.use_free_space = true;
+    build_plan_for_disks(
+        disk,
+        None,
+        vec![],
+        Some(table),
+        None,
+        profile,
+        layout_request,
+    )
+}
+
+// Shrinks the existing partition `layout_request.shrink_partition` names and
+// places the profile's partitions in the space freed behind it. `candidate`
+// is what preflight learned about the partition's filesystem.
+pub fn build_shrink_plan(
+    disk: DiskTarget,
+    mut table: PartitionTable,
+    candidate: &ShrinkCandidate,
+    profile: &LayoutProfile,
+    mut layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    layout_request.use_free_space = true;
+    let shrink = plan_shrink(&disk, &table, candidate, profile, &layout_request)?;
+    if let Some(part) = table
+        .partitions
+        .iter_mut()
+        .find(|p| p.index == shrink.index)
+    {
+        part.end_sector = shrink.new_end_sector;
+    }
+    build_plan_for_disks(
+        disk,
+        None,
+        vec![],
+        Some(table),
+        Some(shrink),
+        profile,
+        layout_request,
+    )
+}
+
+// Reformats one slot of the A/B install already on the disk and keeps the
+// rest: nothing is partitioned, and the ESP, the other slot and STATE are
+// reused as they are. Each profile partition is found in `inspection` by its
+// GPT name or filesystem label.
+pub fn build_slot_reinstall_plan(
+    inspection: DiskInspectResult,
+    profile: &LayoutProfile,
+    layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    let Some(slot) = layout_request.reinstall_slot else {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "slot reinstall plans name the slot to reformat",
+            "reinstall_slot is not set",
+            "Pass --reinstall-slot A or --reinstall-slot B.",
+        ));
+    };
+    let profile = &apply_filesystem_overrides(profile, &layout_request.filesystems)?;
+    validate_profile(profile)?;
+    if profile.mode != InstallMode::Ab {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "slot reinstalls target an ab layout",
+            format!("profile '{}' is for mode {}", profile.name, profile.mode),
+            "Re-run with --mode ab and the profile the disk was installed with.",
+        ));
+    }
+    if layout_request
+        .install_slot
+        .is_some_and(|install| install != slot)
+    {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "a slot reinstall installs into the slot it reformats",
+            format!("install_slot differs from reinstall_slot {slot}"),
+            "Drop --install-slot; --reinstall-slot picks the install slot.",
+        ));
+    }
+    let reshaping = [
+        (
+            "use_free_space",
+            layout_request.use_free_space || layout_request.free_space_start_sector.is_some(),
+        ),
+        ("reuse_esp", layout_request.reuse_esp.is_some()),
+        (
+            "shrink_partition",
+            layout_request.shrink_partition.is_some(),
+        ),
+        ("encrypt", !layout_request.encrypt.is_empty()),
+        (
+            "lvm_volume_group",
+            layout_request.lvm_volume_group.is_some(),
+        ),
+        (
+            "partition_disks",
+            !layout_request.partition_disks.is_empty(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, set)| set.then_some(field))
+    .collect::<Vec<_>>();
+    if !reshaping.is_empty() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "slot reinstalls keep a plain single-disk A/B layout as installed",
+            format!("reinstall_slot is set with {}", reshaping.join(", ")),
+            "Drop the layout-changing flags, or run a full apply to repartition the disk.",
+        ));
+    }
 
-    if disk_mb < min_required {
+    let disk = inspection.disk;
+    let firmware = layout_request.firmware.unwrap_or_default();
+    let expected_label = match firmware {
+        Firmware::BiosMbr => "dos",
+        Firmware::Uefi | Firmware::BiosGpt => "gpt",
+    };
+    let label = inspection.label.as_deref().unwrap_or("no");
+    if label != expected_label {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            format!("the existing partition table is {expected_label} for firmware {firmware}"),
+            format!(
+                "disk '{}' has a {label} partition table",
+                disk.path.display()
+            ),
+            "Use --firmware bios-mbr for dos tables, or uefi/bios-gpt for gpt tables.",
+        ));
+    }
+    let mut resolved_layout =
+        resolve_layout_request(InstallMode::Ab, &layout_request, disk.size_bytes / MB)?;
+    let mut partitions = templates_from_profile(profile, &resolved_layout, &disk.path);
+    apply_firmware(&mut partitions, firmware, &disk.path);
+    let target = format!("/slots/{slot}");
+    if !partitions.iter().any(|p| p.mountpoint == target) {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            format!("the profile has a {target} partition"),
+            format!("profile '{}' has no slot {slot}", profile.name),
+            "Reinstall a slot the profile defines.",
+        ));
+    }
+    // An optional partition left out of the request would drop out of the
+    // mount map and handoff, e.g. sysroot/boot falling back to the ESP.
+    let left_out = profile
+        .partitions
+        .iter()
+        .filter(|p| p.optional && !partitions.iter().any(|t| t.name == p.name))
+        .filter(|p| {
+            let partlabel = p.partlabel.as_deref().unwrap_or(&p.name);
+            inspection.partitions.iter().any(|i| {
+                i.partlabel.as_deref() == Some(partlabel) || i.label.as_deref() == Some(&p.label)
+            })
+        })
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    if !left_out.is_empty() {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            "the request enables every optional profile partition the disk holds",
+            format!(
+                "disk '{}' holds {}, left out of the request",
+                disk.path.display(),
+                left_out.join(", ")
+            ),
+            "Pass the layout flags the disk was installed with (--slot-boot, --verity, --recovery, --xbootldr-size-mb, --swap-size-mb).",
+        ));
+    }
+
+    let uuid_seed = match &layout_request.uuid_seed {
+        Some(seed) => seed.clone(),
+        None => default_seed()?,
+    };
+    for part in &mut partitions {
+        let installed = installed_partition(&inspection.partitions, part, &disk)?;
+        let reinstalled = Path::new(&part.mountpoint).starts_with(&target);
+        // Kept partitions are mounted as they are; the other slot is not.
+        let mounted = part.mountpoint.starts_with('/') && !part.mountpoint.starts_with("/slots/");
+        if mounted
+            && filesystem_spec(&part.filesystem).is_some()
+            && installed.filesystem.as_deref() != Some(part.filesystem.as_str())
+        {
+            return Err(RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "plan",
+                format!(
+                    "the kept {} partition holds {}",
+                    part.label, part.filesystem
+                ),
+                format!(
+                    "'{}' holds {}",
+                    installed.device,
+                    installed.filesystem.as_deref().unwrap_or("no filesystem")
+                ),
+                "Pass the --filesystem overrides the disk was installed with.",
+            ));
+        }
+        part.index = installed.index;
+        part.gpt_type = installed.gpt_type.clone();
+        part.partlabel = installed.partlabel.clone();
+        part.size_mb = u32::try_from(installed.size_mb).ok();
+        part.start_sector = Some(installed.start_sector);
+        part.end_sector = Some(installed.end_sector);
+        part.partuuid = installed.partuuid.clone();
+        part.installed_attrs = Some(installed.attrs.clone().unwrap_or_default());
+        part.fs_uuid = if reinstalled {
+            let device = template_device(part);
+            filesystem_spec(&part.filesystem).map(|spec| filesystem_uuid(&uuid_seed, spec, &device))
+        } else {
+            installed.fs_uuid.clone()
+        };
+        part.reused = !reinstalled;
+    }
+    // Apply moves the slots' no-auto bit, so the reinstalled slot loses it
+    // and the other slot gains it.
+    if firmware == Firmware::BiosMbr {
+        for part in &mut partitions {
+            part.attributes.clear();
+        }
+    } else {
+        mark_inactive_slot(&mut partitions, slot);
+    }
+    record_layout_sizes(profile, &mut resolved_layout, &partitions);
+
+    Ok(PartitionPlan {
+        schema_version: PLAN_SCHEMA_VERSION,
+        mode: InstallMode::Ab,
+        arch: layout_request.arch.or_else(Arch::host),
+        layout_request,
+        resolved_layout,
+        profile: profile.name.clone(),
+        disk,
+        mirror_disk: None,
+        firmware,
+        install_slot: Some(slot),
+        data_disks: vec![],
+        free_space: None,
+        preserved_partitions: vec![],
+        shrink: None,
+        uuid_seed,
+        partitions,
+        sfdisk_script: String::new(),
+        sfdisk_scripts: vec![],
+    })
+}
+
+// The partition on disk a reinstall keeps for `part`, matched by GPT name
+// or filesystem label.
+fn installed_partition<'a>(
+    existing: &'a [InspectedPartition],
+    part: &PartitionTemplate,
+    disk: &DiskTarget,
+) -> Result<&'a InspectedPartition> {
+    let matches = existing
+        .iter()
+        .filter(|p| {
+            (p.partlabel.is_some() && p.partlabel == part.partlabel)
+                || p.label.as_deref() == Some(part.label.as_str())
+        })
+        .collect::<Vec<_>>();
+    let describe = |parts: &mut dyn Iterator<Item = &InspectedPartition>| {
+        parts
+            .map(|p| {
+                format!(
+                    "{} ({}/{})",
+                    p.device,
+                    p.partlabel.as_deref().unwrap_or("-"),
+                    p.label.as_deref().unwrap_or("-")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match matches.as_slice() {
+        [found] => Ok(found),
+        [] => Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            format!(
+                "disk '{}' has the {} partition (GPT name '{}' or label '{}')",
+                disk.path.display(),
+                part.name,
+                part.partlabel.as_deref().unwrap_or(&part.name),
+                part.label
+            ),
+            format!("partitions: {}", describe(&mut existing.iter())),
+            "Check the disk with 'recpart inspect'; only recpart A/B installs can reinstall a slot.",
+        )),
+        _ => Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            format!("one partition on '{}' is the {} partition", disk.path.display(), part.name),
+            format!("several match: {}", describe(&mut matches.iter().copied())),
+            "Rename or relabel the extra partitions, then retry.",
+        )),
+    }
+}
+
+// Partitions both disks identically (sized for the smaller one) and mirrors
+// every non-ESP partition as md RAID1.
+pub fn build_mirrored_plan(
+    disk: DiskTarget,
+    mirror_disk: DiskTarget,
+    profile: &LayoutProfile,
+    layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    if mirror_disk.path == disk.path {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            "mirror disk differs from the primary disk",
+            format!("both disks are '{}'", disk.path.display()),
+            "Pass a second, different disk with --mirror-disk.",
+        ));
+    }
+    if mirror_disk.logical_sector_bytes != disk.logical_sector_bytes {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            "mirrored disks share a logical sector size",
+            format!(
+                "'{}' uses {}-byte sectors, '{}' uses {}-byte sectors",
+                disk.path.display(),
+                disk.logical_sector_bytes,
+                mirror_disk.path.display(),
+                mirror_disk.logical_sector_bytes
+            ),
+            "Mirror disks with the same logical sector size.",
+        ));
+    }
+    build_plan_for_disks(
+        disk,
+        Some(mirror_disk),
+        vec![],
+        None,
+        None,
+        profile,
+        layout_request,
+    )
+}
+
+// Mirrored disks share one sector layout: the smaller size and the
+// coarser alignment of the two.
+fn mirror_layout_disk(disk: &DiskTarget, mirror_disk: Option<&DiskTarget>) -> DiskTarget {
+    let mut layout = disk.clone();
+    if let Some(mirror) = mirror_disk {
+        layout.size_bytes = layout.size_bytes.min(mirror.size_bytes);
+        layout.physical_sector_bytes = layout
+            .physical_sector_bytes
+            .max(mirror.physical_sector_bytes);
+        layout.optimal_io_bytes = layout.optimal_io_bytes.max(mirror.optimal_io_bytes);
+    }
+    layout
+}
+
+// Places the partitions named in `layout_request.partition_disks` on the
+// given data disks; everything else stays on `disk`.
+pub fn build_multi_disk_plan(
+    disk: DiskTarget,
+    data_disks: Vec<DiskTarget>,
+    profile: &LayoutProfile,
+    layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    let mut seen = HashSet::from([disk.path.as_path()]);
+    if let Some(dup) = data_disks.iter().find(|d| !seen.insert(d.path.as_path())) {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            "every target disk is listed once",
+            format!("disk '{}' is listed more than once", dup.path.display()),
+            "Pass each disk once; several partitions may share a data disk.",
+        ));
+    }
+    build_plan_for_disks(disk, None, data_disks, None, None, profile, layout_request)
+}
+
+fn build_plan_for_disks(
+    disk: DiskTarget,
+    mirror_disk: Option<DiskTarget>,
+    data_disks: Vec<DiskTarget>,
+    existing: Option<PartitionTable>,
+    shrink: Option<PartitionShrink>,
+    profile: &LayoutProfile,
+    layout_request: LayoutRequest,
+) -> Result<PartitionPlan> {
+    let profile = &apply_filesystem_overrides(profile, &layout_request.filesystems)?;
+    validate_profile(profile)?;
+    let mode = profile.mode;
+    let firmware = layout_request.firmware.unwrap_or_default();
+    let wants_free_space = layout_request.use_free_space
+        || layout_request.free_space_start_sector.is_some()
+        || layout_request.shrink_partition.is_some();
+    if wants_free_space && (mirror_disk.is_some() || !layout_request.partition_disks.is_empty()) {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "free-space installs use a single disk",
+            "use_free_space is set together with a mirror disk or partition_disks",
+            "Drop --mirror-disk/--partition-disk, or partition the whole disk instead.",
+        ));
+    }
+    if layout_request.reinstall_slot.is_some() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "slot reinstalls are planned from the disk's existing layout",
+            "reinstall_slot is set for a plan that partitions the disk",
+            "Plan with build_slot_reinstall_plan (recpart plan --reinstall-slot).",
+        ));
+    }
+    if layout_request.shrink_partition.is_some() && existing.is_some() && shrink.is_none() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "shrink plans know how far the partition's filesystem can shrink",
+            "shrink_partition is set but no shrink candidate was queried",
+            "Plan with build_shrink_plan (recpart plan --shrink-partition).",
+        ));
+    }
+    // A shrink plan fills the space freed behind the shrunk partition.
+    let start_sector = shrink
+        .as_ref()
+        .map(|shrink| shrink.new_end_sector + 1)
+        .or(layout_request.free_space_start_sector);
+    let free_space = match &existing {
+        Some(table) => Some(select_free_gap(table, &disk, firmware, start_sector)?),
+        None if wants_free_space => {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "free-space plans are built from the disk's existing partition table",
+                "use_free_space is set but no partition table was read",
+                "Plan with build_free_space_plan (recpart plan --use-free-space).",
+            ));
+        }
+        None => None,
+    };
+    let reused_esp = layout_request
+        .reuse_esp
+        .as_deref()
+        .map(|selector| select_reused_esp(existing.as_ref(), &disk, firmware, selector))
+        .transpose()?;
+    let disk_mb = match &free_space {
+        Some(gap) => gap.size_mb,
+        None => mirror_disk
+            .iter()
+            .chain([&disk])
+            .map(|d| d.size_bytes / MB)
+            .min()
+            .unwrap_or_default(),
+    };
+    // Size expressions resolve against the primary disk.
+    let mut resolved_layout = resolve_layout_request(mode, &layout_request, disk_mb)?;
+    let min_required = layout_min_mb(&resolved_layout, reused_esp.is_some());
+    let total_mb = disk_mb + data_disks.iter().map(|d| d.size_bytes / MB).sum::<u64>();
+
+    if total_mb < min_required {
         return Err(RecpartError::new(
             ErrorCode::PlanGeneration,
             "plan",
             format!("disk must have at least {min_required}MB for mode {mode}"),
-            format!("disk has {disk_mb}MB"),
+            if free_space.is_some() {
+                format!("free space gap has {total_mb}MB")
+            } else if data_disks.is_empty() {
+                format!("disk has {total_mb}MB")
+            } else {
+                format!("disks have {total_mb}MB combined")
+            },
             "Choose a larger disk or adjust partition policy defaults.",
         ));
     }
 
-    let partitions = build_templates(&resolved_layout);
-    validate_partition_fit(&partitions, disk_mb, mode)?;
-    let sfdisk_script = build_sfdisk_script(&partitions);
+    if firmware != Firmware::Uefi && resolved_layout.slot_boot_size_mb.is_some() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "per-slot boot partitions are only planned for uefi firmware",
+            format!("slot_boot is set with firmware {firmware}"),
+            "Drop --slot-boot for BIOS firmware; /boot then lives on the boot partition.",
+        ));
+    }
+    if firmware == Firmware::BiosMbr && layout_request.verity {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "verity partitions use DPS GPT types",
+            "verity is set with firmware bios-mbr".to_string(),
+            "Drop --verity or use uefi or bios-gpt firmware.",
+        ));
+    }
+    if firmware != Firmware::Uefi && resolved_layout.xbootldr_size_mb.is_some() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "XBOOTLDR partitions are only planned for uefi firmware",
+            format!("xbootldr_size is set with firmware {firmware}"),
+            "Drop --xbootldr-size for BIOS firmware; /boot then lives on the boot partition.",
+        ));
+    }
+    let mut partitions = templates_from_profile(profile, &resolved_layout, &disk.path);
+    apply_firmware(&mut partitions, firmware, &disk.path);
+    if !layout_request.partition_disks.is_empty() {
+        if mirror_disk.is_some() || layout_request.lvm_volume_group.is_some() {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "multi-disk placement is not combined with RAID1 mirroring or LVM",
+                "partition_disks is set together with a mirror disk or volume group",
+                "Drop --partition-disk, or drop --mirror-disk/--lvm-vg.",
+            ));
+        }
+        partitions = assign_disks(
+            partitions,
+            &layout_request.partition_disks,
+            &disk,
+            &data_disks,
+        )?;
+    } else if !data_disks.is_empty() {
+        return Err(unused_disk_error(&data_disks[0].path));
+    }
+
+    for target in [&disk].into_iter().chain(&data_disks) {
+        let disk_parts = partitions
+            .iter()
+            .filter(|p| {
+                p.disk == target.path && !(reused_esp.is_some() && is_esp_type(&p.gpt_type))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let target_mb = if target.path == disk.path {
+            disk_mb
+        } else {
+            target.size_bytes / MB
+        };
+        validate_partition_fit(&disk_parts, target_mb, mode)?;
+    }
+    mark_encrypted(&mut partitions, &layout_request.encrypt)?;
+    if let Some(vg) = &layout_request.lvm_volume_group {
+        validate_volume_group_name(vg)?;
+        partitions = move_to_lvm(partitions, vg);
+    }
+    if mirror_disk.is_some() {
+        mark_mirrored(&mut partitions);
+    }
+    let install_slot = (mode == InstallMode::Ab).then(|| {
+        layout_request
+            .install_slot
+            .unwrap_or(DEFAULT_AB_TARGET_SLOT)
+    });
+    if let Some(slot) = install_slot {
+        mark_inactive_slot(&mut partitions, slot);
+    }
+    let arch = layout_request.arch.or_else(Arch::host);
+    if firmware != Firmware::BiosMbr {
+        for part in &mut partitions {
+            if let Some(gpt_type) = dps_type(part, arch) {
+                part.gpt_type = gpt_type.to_string();
+            }
+        }
+    }
+    if let Some(esp) = &reused_esp {
+        resolved_layout.efi_size_mb = reuse_esp(&mut partitions, esp, &disk);
+    }
+    if let (Some(table), Some(gap)) = (&existing, &free_space) {
+        renumber_around(&mut partitions, &disk.path, table);
+        assign_sectors_between(&mut partitions, &disk, gap.start_sector, gap.end_sector)?;
+    } else {
+        assign_sectors(
+            &mut partitions,
+            &mirror_layout_disk(&disk, mirror_disk.as_ref()),
+            firmware,
+        )?;
+    }
+    for target in &data_disks {
+        assign_sectors(&mut partitions, target, firmware)?;
+    }
+    let uuid_seed = match &layout_request.uuid_seed {
+        Some(seed) => seed.clone(),
+        None => default_seed()?,
+    };
+    assign_uuids(&mut partitions, &uuid_seed, firmware);
+    // Appended MBR partitions report the identifier already on the disk.
+    if let Some(disk_id) = existing
+        .as_ref()
+        .filter(|table| table.label == "dos")
+        .and_then(|table| table.id.as_deref())
+        .and_then(|id| u32::from_str_radix(id.trim_start_matches("0x"), 16).ok())
+    {
+        for part in partitions.iter_mut().filter(|p| p.volume_group.is_none()) {
+            part.partuuid = Some(mbr_partition_uuid(disk_id, part.index));
+        }
+    }
+
+    // A mirror disk carries the same layout as the primary disk, with its
+    // own PARTUUIDs.
+    let disk_layout = |target: &DiskTarget| {
+        let is_mirror = Some(target) == mirror_disk.as_ref();
+        let source = if is_mirror { &disk.path } else { &target.path };
+        partitions
+            .iter()
+            .filter(|p| p.disk == *source && p.volume_group.is_none())
+            .map(|part| {
+                let mut part = part.clone();
+                if is_mirror {
+                    part.partuuid = Some(partition_uuid(
+                        &uuid_seed,
+                        firmware,
+                        &target.path,
+                        part.index,
+                        &part.name,
+                    ));
+                }
+                part
+            })
+            .collect::<Vec<_>>()
+    };
+    let targets = [&disk]
+        .into_iter()
+        .chain(&mirror_disk)
+        .chain(&data_disks)
+        .collect::<Vec<_>>();
+    let preserved_partitions = existing.map(|table| table.partitions).unwrap_or_default();
+    if firmware == Firmware::BiosMbr {
+        for target in &targets {
+            validate_mbr_disk(target, &disk_layout(target), &preserved_partitions)?;
+        }
+    }
+    let sfdisk_scripts = targets
+        .iter()
+        .map(|target| DiskScript {
+            disk: target.path.clone(),
+            script: if free_space.is_some() {
+                build_append_script(&disk_layout(target), firmware)
+            } else {
+                build_sfdisk_script(
+                    &disk_layout(target),
+                    firmware,
+                    Some(&disk_label_id(&uuid_seed, firmware, &target.path)),
+                )
+            },
+            append: free_space.is_some(),
+        })
+        .collect::<Vec<_>>();
+    let sfdisk_script = sfdisk_scripts[0].script.clone();
 
     Ok(PartitionPlan {
         schema_version: PLAN_SCHEMA_VERSION,
         mode,
         layout_request,
         resolved_layout,
+        profile: profile.name.clone(),
         disk,
+        mirror_disk,
+        firmware,
+        arch,
+        install_slot,
+        data_disks,
+        free_space,
+        preserved_partitions,
+        shrink,
+        uuid_seed,
         partitions,
         sfdisk_script,
+        sfdisk_scripts,
     })
 }
 
-pub fn build_sfdisk_script(partitions: &[PartitionTemplate]) -> String {
-    let mut lines = Vec::with_capacity(partitions.len() + 1);
-    lines.push("label: gpt".to_string());
+// The gap a free-space plan fills: the one containing the requested start
+// sector, or the largest.
+fn select_free_gap(
+    table: &PartitionTable,
+    disk: &DiskTarget,
+    firmware: Firmware,
+    start_sector: Option<u64>,
+) -> Result<FreeGap> {
+    let expected_label = match firmware {
+        Firmware::BiosMbr => "dos",
+        Firmware::Uefi | Firmware::BiosGpt => "gpt",
+    };
+    if table.label != expected_label {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            format!("the existing partition table is {expected_label} for firmware {firmware}"),
+            format!(
+                "disk '{}' has a {} partition table",
+                disk.path.display(),
+                table.label
+            ),
+            "Use --firmware bios-mbr for dos tables, or uefi/bios-gpt for gpt tables.",
+        ));
+    }
+
+    let gaps = free_gaps(table, disk, firmware);
+    let describe = || {
+        gaps.iter()
+            .map(|g| format!("{}-{} ({}MB)", g.start_sector, g.end_sector, g.size_mb))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let gap = match start_sector {
+        Some(sector) => gaps
+            .iter()
+            .find(|g| g.start_sector <= sector && sector <= g.end_sector)
+            .ok_or_else(|| {
+                RecpartError::new(
+                    ErrorCode::InvalidTargetDisk,
+                    "plan",
+                    "the requested start sector lies in a free gap",
+                    format!("sector {sector} is not free; free gaps: [{}]", describe()),
+                    "Pass --free-space-start with a sector from one of the listed gaps.",
+                )
+            })?,
+        None => gaps.iter().rev().max_by_key(|g| g.size_mb).ok_or_else(|| {
+            RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "plan",
+                "the disk has at least 1MiB of unallocated space",
+                format!("disk '{}' has no free gap", disk.path.display()),
+                "Shrink an existing partition or install to the whole disk.",
+            )
+        })?,
+    };
+    Ok(*gap)
+}
+
+// Space the layout needs at minimum; a reused ESP takes none.
+fn layout_min_mb(layout: &ResolvedLayout, reuses_esp: bool) -> u64 {
+    let min_mb = u64::from(required_min_mb(layout));
+    if reuses_esp {
+        min_mb - u64::from(layout.efi_size_mb)
+    } else {
+        min_mb
+    }
+}
 
-    for part in partitions {
-        let line = match (part.size_mb, part.index == 1 && part.gpt_type == "U") {
-            (Some(size_mb), true) => format!(",{size_mb}M,{},*", part.gpt_type),
-            (Some(size_mb), false) => format!(",{size_mb}M,{}", part.gpt_type),
-            (None, _) => format!(",,{}", part.gpt_type),
+// The partition keeps its start and ends just before an aligned boundary:
+// `shrink_size_mb` past its start, or by default as late as still leaves the
+// layout's minimum between the boundary and the next partition.
+fn plan_shrink(
+    disk: &DiskTarget,
+    table: &PartitionTable,
+    candidate: &ShrinkCandidate,
+    profile: &LayoutProfile,
+    layout_request: &LayoutRequest,
+) -> Result<PartitionShrink> {
+    let Some(selector) = layout_request.shrink_partition.as_deref() else {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "shrink plans name the partition to shrink",
+            "shrink_partition is not set",
+            "Pass --shrink-partition with a device or PARTUUID.",
+        ));
+    };
+    let part = existing_partition(table, disk, selector, "--shrink-partition")?;
+    if candidate.device != part.device || !matches!(candidate.filesystem.as_str(), "ext4" | "ntfs")
+    {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            format!("the shrink candidate is ext4 or ntfs on '{}'", part.device),
+            format!(
+                "candidate is {} on '{}'",
+                candidate.filesystem, candidate.device
+            ),
+            "Query the partition being shrunk with query_shrink_candidate.",
+        ));
+    }
+
+    let sector = u64::from(disk.logical_sector_bytes.max(1));
+    let firmware = layout_request.firmware.unwrap_or_default();
+    let region_end = table
+        .partitions
+        .iter()
+        .map(|p| p.start_sector)
+        .filter(|start| *start > part.end_sector)
+        .min()
+        .map(|start| start - 1)
+        .or(table.last_lba)
+        .unwrap_or_else(|| last_usable_sector(disk, firmware));
+    let boundary = match layout_request.shrink_size_mb {
+        Some(mb) => align_sector_up(disk, part.start_sector + u64::from(mb) * MB / sector),
+        None => {
+            let region_mb = (region_end - part.start_sector + 1) * sector / MB;
+            let layout = resolve_layout_request(profile.mode, layout_request, region_mb)?;
+            let needed = layout_min_mb(&layout, layout_request.reuse_esp.is_some()) * MB / sector;
+            align_sector_down(disk, (region_end + 1).saturating_sub(needed))
+        }
+    };
+
+    if boundary > part.end_sector {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            format!("'{}' gets smaller", part.device),
+            match layout_request.shrink_size_mb {
+                Some(mb) => format!(
+                    "shrink_size_mb is {mb}MB, the partition is {}MB",
+                    (part.end_sector - part.start_sector + 1) * sector / MB
+                ),
+                None => "the layout already fits in the free space behind it".to_string(),
+            },
+            "Pass a smaller --shrink-size-mb, or use --use-free-space without shrinking.",
+        ));
+    }
+    let new_size_bytes = boundary.saturating_sub(part.start_sector) * sector;
+    if new_size_bytes < candidate.min_size_bytes {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            format!(
+                "the {} filesystem on '{}' can shrink to {}MB",
+                candidate.filesystem,
+                part.device,
+                new_size_bytes / MB
+            ),
+            format!(
+                "it needs at least {}MB",
+                candidate.min_size_bytes.div_ceil(MB)
+            ),
+            "Free space inside the filesystem, pass a larger --shrink-size-mb, or shrink the layout.",
+        ));
+    }
+
+    Ok(PartitionShrink {
+        device: part.device.clone(),
+        index: part.index,
+        filesystem: candidate.filesystem.clone(),
+        start_sector: part.start_sector,
+        end_sector: part.end_sector,
+        new_end_sector: boundary - 1,
+        min_size_bytes: candidate.min_size_bytes,
+        new_size_bytes,
+    })
+}
+
+// New partitions take the lowest partition numbers the existing table does
+// not use, in profile order.
+fn renumber_around(partitions: &mut [PartitionTemplate], disk: &Path, table: &PartitionTable) {
+    let used = table
+        .partitions
+        .iter()
+        .map(|p| p.index)
+        .collect::<HashSet<_>>();
+    let mut free = (1..=u8::MAX).filter(|index| !used.contains(index));
+    for part in partitions
+        .iter_mut()
+        .filter(|p| p.disk == disk && p.volume_group.is_none() && !p.reused)
+    {
+        part.index = free.next().unwrap_or(u8::MAX);
+    }
+}
+
+// The existing partition `selector` (a device or PARTUUID) names, which must
+// be an ESP on the disk a free-space plan keeps.
+fn select_reused_esp(
+    existing: Option<&PartitionTable>,
+    disk: &DiskTarget,
+    firmware: Firmware,
+    selector: &str,
+) -> Result<ExistingPartition> {
+    if firmware != Firmware::Uefi {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "an existing ESP is only reused for uefi firmware",
+            format!("reuse_esp is set with firmware {firmware}"),
+            "Drop --reuse-esp for BIOS firmware.",
+        ));
+    }
+    let Some(table) = existing else {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "an existing ESP is only reused by free-space plans",
+            "reuse_esp is set for a plan that repartitions the whole disk",
+            "Add --use-free-space; whole-disk plans wipe the existing ESP.",
+        ));
+    };
+
+    let part = existing_partition(table, disk, selector, "--reuse-esp")?;
+    if !is_esp_type(&part.gpt_type) {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            "the reused partition has the EFI System Partition type",
+            format!("'{}' has type {}", part.device, part.gpt_type),
+            "Pass the ESP (type C12A7328-F81F-11D2-BA4B-00A0C93EC93B) to --reuse-esp.",
+        ));
+    }
+    Ok(part.clone())
+}
+
+fn existing_partition<'a>(
+    table: &'a PartitionTable,
+    disk: &DiskTarget,
+    selector: &str,
+    flag: &str,
+) -> Result<&'a ExistingPartition> {
+    find_partition(table, selector).ok_or_else(|| {
+        RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "plan",
+            format!(
+                "'{selector}' is a partition device or PARTUUID on '{}'",
+                disk.path.display()
+            ),
+            format!(
+                "existing partitions: {}",
+                table
+                    .partitions
+                    .iter()
+                    .map(|p| format!("{} ({})", p.device, p.partuuid.as_deref().unwrap_or("-")))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!("Pass {flag} with one of the listed devices or PARTUUIDs."),
+        )
+    })
+}
+
+// Points the profile's ESP at the existing partition and returns its size.
+fn reuse_esp(
+    partitions: &mut [PartitionTemplate],
+    esp: &ExistingPartition,
+    disk: &DiskTarget,
+) -> u32 {
+    let sector = u64::from(disk.logical_sector_bytes.max(1));
+    let size_mb = ((esp.end_sector - esp.start_sector + 1) * sector / MB) as u32;
+    if let Some(part) = partitions
+        .iter_mut()
+        .find(|p| p.disk == disk.path && is_esp_type(&p.gpt_type))
+    {
+        part.index = esp.index;
+        part.gpt_type = esp.gpt_type.clone();
+        part.partlabel = esp.partlabel.clone();
+        part.attributes.clear();
+        part.size_mb = Some(size_mb);
+        part.start_sector = Some(esp.start_sector);
+        part.end_sector = Some(esp.end_sector);
+        part.partuuid = esp.partuuid.as_deref().map(str::to_ascii_lowercase);
+        part.reused = true;
+    }
+    size_mb
+}
+
+// Records each partition's PARTUUID and the UUID its filesystem is created
+// with. Partitions without a filesystem (LVM PVs, RAID members, LUKS
+// containers, BIOS boot) only get a PARTUUID.
+fn assign_uuids(partitions: &mut [PartitionTemplate], seed: &str, firmware: Firmware) {
+    for part in partitions.iter_mut().filter(|p| !p.reused) {
+        if part.volume_group.is_none() {
+            part.partuuid = Some(partition_uuid(
+                seed, firmware, &part.disk, part.index, &part.name,
+            ));
+        }
+        part.fs_uuid = filesystem_spec(&part.filesystem)
+            .map(|spec| filesystem_uuid(seed, spec, &template_device(part)));
+    }
+}
+
+// Moves the requested partitions to their data disk and renumbers each
+// disk's partitions in profile order.
+fn assign_disks(
+    mut partitions: Vec<PartitionTemplate>,
+    placement: &BTreeMap<String, PathBuf>,
+    disk: &DiskTarget,
+    data_disks: &[DiskTarget],
+) -> Result<Vec<PartitionTemplate>> {
+    for (name, path) in placement {
+        let Some(part) = partitions.iter_mut().find(|part| part.name == *name) else {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "partitions placed on other disks are part of the plan",
+                format!("partition '{name}' is not in the plan"),
+                "Place state (ab) or root/home/swap (mutable); home needs --home-size.",
+            ));
         };
-        lines.push(line);
+        if is_boot_partition(part) {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "boot partitions stay on the primary disk",
+                format!("partition '{name}' is {}", part.filesystem),
+                "Keep the ESP on the disk passed with --disk.",
+            ));
+        }
+        if *path != disk.path && !data_disks.iter().any(|d| d.path == *path) {
+            return Err(RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "plan",
+                "partitions are placed on one of the plan's target disks",
+                format!("partition '{name}' names disk '{}'", path.display()),
+                "Pass the disk to the plan before placing partitions on it.",
+            ));
+        }
+        part.disk = path.clone();
+    }
+
+    if let Some(unused) = data_disks
+        .iter()
+        .find(|d| !partitions.iter().any(|p| p.disk == d.path))
+    {
+        return Err(unused_disk_error(&unused.path));
+    }
+
+    let mut counts = HashMap::<PathBuf, u8>::new();
+    for part in &mut partitions {
+        let index = counts.entry(part.disk.clone()).or_default();
+        *index += 1;
+        part.index = *index;
+    }
+    Ok(partitions)
+}
+
+// A data disk without partitions would be wiped for nothing.
+fn unused_disk_error(disk: &Path) -> RecpartError {
+    RecpartError::new(
+        ErrorCode::InvalidTargetDisk,
+        "plan",
+        "every data disk receives at least one partition",
+        format!("no partition is placed on '{}'", disk.display()),
+        "Place a partition on the disk with --partition-disk NAME=DISK or drop it.",
+    )
+}
+
+// Partition lines use sfdisk's named fields so they can carry a PARTUUID.
+pub fn build_sfdisk_script(
+    partitions: &[PartitionTemplate],
+    firmware: Firmware,
+    label_id: Option<&str>,
+) -> String {
+    let mut lines = Vec::with_capacity(partitions.len() + 3);
+    lines.push(match firmware {
+        Firmware::BiosMbr => "label: dos".to_string(),
+        Firmware::Uefi | Firmware::BiosGpt => "label: gpt".to_string(),
+    });
+    if let Some(label_id) = label_id {
+        lines.push(format!("label-id: {label_id}"));
     }
+    lines.push("unit: sectors".to_string());
+    lines.extend(
+        partition_lines(partitions, firmware, true)
+            .into_iter()
+            .map(|(_, line)| line),
+    );
 
     let mut script = lines.join("\n");
     script.push('\n');
     script
 }
 
+// Script for `sfdisk --append`: the label and its id stay as they are on
+// disk, and each line names its device so partitions get the planned
+// numbers. Existing boot flags are left alone.
+pub fn build_append_script(partitions: &[PartitionTemplate], firmware: Firmware) -> String {
+    let mut lines = vec!["unit: sectors".to_string()];
+    lines.extend(
+        partition_lines(partitions, firmware, false)
+            .into_iter()
+            .map(|(part, line)| format!("{} : {line}", partition_device(&part.disk, part.index))),
+    );
+
+    let mut script = lines.join("\n");
+    script.push('\n');
+    script
+}
+
+fn partition_lines(
+    partitions: &[PartitionTemplate],
+    firmware: Firmware,
+    mark_bootable: bool,
+) -> Vec<(&PartitionTemplate, String)> {
+    let disk_parts = partitions
+        .iter()
+        .filter(|p| p.volume_group.is_none() && !p.reused)
+        .collect::<Vec<_>>();
+    // MBR firmware boots the partition holding /boot, or / without one.
+    let bootable = match firmware {
+        Firmware::Uefi => disk_parts
+            .iter()
+            .find(|p| p.index == 1 && is_esp_type(&p.gpt_type)),
+        Firmware::BiosGpt => None,
+        Firmware::BiosMbr => disk_parts
+            .iter()
+            .find(|p| p.mountpoint == "/boot")
+            .or_else(|| disk_parts.iter().find(|p| p.mountpoint == "/")),
+    }
+    .map(|p| p.index)
+    .filter(|_| mark_bootable);
+
+    let mut lines = Vec::with_capacity(disk_parts.len());
+    for part in disk_parts {
+        let mut fields = match (part.start_sector, part.end_sector, part.size_mb) {
+            (Some(start), Some(end), _) => {
+                vec![
+                    format!("start={start}"),
+                    format!("size={}", end - start + 1),
+                ]
+            }
+            (_, _, Some(size_mb)) => vec![format!("size={size_mb}M")],
+            _ => vec![],
+        };
+        fields.push(format!("type={}", part.gpt_type));
+        // MBR has no PARTUUIDs (they follow from the label id and partition
+        // number), partition names or attribute bits.
+        if firmware != Firmware::BiosMbr {
+            if let Some(uuid) = &part.partuuid {
+                fields.push(format!("uuid={uuid}"));
+            }
+            if let Some(partlabel) = &part.partlabel {
+                fields.push(format!("name=\"{partlabel}\""));
+            }
+            if !part.attributes.is_empty() {
+                fields.push(format!("attrs=\"{}\"", gpt_attrs(part)));
+            }
+        }
+        if Some(part.index) == bootable {
+            fields.push("bootable".to_string());
+        }
+        lines.push((part, fields.join(", ")));
+    }
+    lines
+}
+
 pub fn partition_device(disk: &Path, index: u8) -> String {
     let base = disk.to_string_lossy();
     let needs_p = base
@@ -78,6 +1209,334 @@ pub fn partition_device(disk: &Path, index: u8) -> String {
     }
 }
 
+pub fn luks_mapper_name(part: &PartitionTemplate) -> String {
+    format!("luks-{}", part.name.replace('_', "-"))
+}
+
+pub fn target_disks(plan: &PartitionPlan) -> Vec<&Path> {
+    plan.sfdisk_scripts
+        .iter()
+        .map(|script| script.disk.as_path())
+        .collect()
+}
+
+// Every partition device created for a template: one per disk in mirrored
+// plans, otherwise the single partition on the template's disk.
+pub fn partition_copies(plan: &PartitionPlan, part: &PartitionTemplate) -> Vec<String> {
+    match &plan.mirror_disk {
+        Some(mirror) => vec![
+            partition_device(&part.disk, part.index),
+            partition_device(&mirror.path, part.index),
+        ],
+        None => vec![partition_device(&part.disk, part.index)],
+    }
+}
+
+// The partition, RAID array or logical volume backing a template.
+pub fn block_device(part: &PartitionTemplate) -> String {
+    match &part.volume_group {
+        Some(vg) => format!("/dev/{vg}/{}", part.name),
+        None if part.mirrored => format!("/dev/md/{}", part.name),
+        None => partition_device(&part.disk, part.index),
+    }
+}
+
+// Partitions firmware or the boot loader reads directly (including an
+// XBOOTLDR or per-slot /boot); they never move into LVM, RAID, LUKS or onto
+// data disks.
+fn is_boot_partition(part: &PartitionTemplate) -> bool {
+    part.filesystem == "vfat"
+        || part.filesystem == BIOS_BOOT_FILESYSTEM
+        || is_boot_mountpoint(&part.mountpoint)
+}
+
+// BIOS-on-GPT needs a BIOS boot partition for GRUB's core image; without
+// UEFI the ESP is retyped as an ordinary /boot partition.
+fn apply_firmware(partitions: &mut Vec<PartitionTemplate>, firmware: Firmware, disk: &Path) {
+    let boot_type = match firmware {
+        Firmware::Uefi => return,
+        Firmware::BiosGpt => "L",
+        Firmware::BiosMbr => "c",
+    };
+    for part in partitions.iter_mut().filter(|p| p.gpt_type == "U") {
+        part.gpt_type = boot_type.to_string();
+    }
+
+    if firmware == Firmware::BiosGpt {
+        partitions.insert(
+            0,
+            PartitionTemplate {
+                disk: disk.to_path_buf(),
+                index: 0,
+                name: "bios_boot".to_string(),
+                filesystem: BIOS_BOOT_FILESYSTEM.to_string(),
+                label: "BIOS_BOOT".to_string(),
+                gpt_type: BIOS_BOOT_GPT_TYPE.to_string(),
+                partlabel: Some("bios_boot".to_string()),
+                attributes: vec![],
+                installed_attrs: None,
+                size_mb: Some(1),
+                start_sector: None,
+                end_sector: None,
+                partuuid: None,
+                fs_uuid: None,
+                mountpoint: "none".to_string(),
+                subvolumes: vec![],
+                encrypted: false,
+                volume_group: None,
+                mirrored: false,
+                reused: false,
+            },
+        );
+        for (pos, part) in partitions.iter_mut().enumerate() {
+            part.index = (pos + 1) as u8;
+        }
+    }
+}
+
+fn validate_mbr_disk(
+    target: &DiskTarget,
+    disk_parts: &[PartitionTemplate],
+    preserved: &[ExistingPartition],
+) -> Result<()> {
+    let sectors = target.size_bytes / u64::from(target.logical_sector_bytes.max(1));
+    if sectors > u64::from(u32::MAX) {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "MBR disks address at most 2^32 sectors (2TiB with 512-byte sectors)",
+            format!(
+                "disk '{}' has {sectors} sectors of {} bytes",
+                target.path.display(),
+                target.logical_sector_bytes
+            ),
+            "Use --firmware bios-gpt or uefi for this disk.",
+        ));
+    }
+
+    if preserved.len() + disk_parts.len() > 4 {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "MBR disks hold at most 4 primary partitions",
+            format!(
+                "disk '{}' needs {} partitions: {}",
+                target.path.display(),
+                preserved.len() + disk_parts.len(),
+                preserved
+                    .iter()
+                    .map(|p| p.device.as_str())
+                    .chain(disk_parts.iter().map(|p| p.name.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            "Drop swap, use --lvm-vg, or use --firmware bios-gpt.",
+        ));
+    }
+
+    let mbr_type = |gpt_type: &str| {
+        MBR_TYPE_SHORTCUTS.contains(&gpt_type)
+            || (gpt_type.len() <= 2 && u8::from_str_radix(gpt_type, 16).is_ok())
+    };
+    if let Some(part) = disk_parts.iter().find(|p| !mbr_type(&p.gpt_type)) {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "MBR partition types are sfdisk shortcuts or one-byte hex codes",
+            format!("partition '{}' has type '{}'", part.name, part.gpt_type),
+            "Use a GPT firmware target or set an MBR type such as L or 83 in the profile.",
+        ));
+    }
+    Ok(())
+}
+
+// ESPs stay plain partitions (one per disk) so firmware can read either;
+// everything else on disk becomes a Linux RAID member.
+fn mark_mirrored(partitions: &mut [PartitionTemplate]) {
+    for part in partitions
+        .iter_mut()
+        .filter(|p| p.volume_group.is_none() && !is_boot_partition(p))
+    {
+        part.mirrored = true;
+        part.gpt_type = "R".to_string();
+    }
+}
+
+// GPT attribute bits in sfdisk's `GUID:59,63` form; empty without any.
+pub fn gpt_attrs(part: &PartitionTemplate) -> String {
+    let mut bits = part.attributes.iter().map(|a| a.bit()).collect::<Vec<_>>();
+    bits.sort_unstable();
+    bits.dedup();
+    if bits.is_empty() {
+        return String::new();
+    }
+    let bits = bits.iter().map(u8::to_string).collect::<Vec<_>>();
+    format!("GUID:{}", bits.join(","))
+}
+
+// The attribute string a slot reinstall writes for `part`: the bits found on
+// disk with only no-auto (bit 63) set or cleared to match the plan. None when
+// that leaves them unchanged.
+pub fn reinstall_attrs(part: &PartitionTemplate) -> Option<String> {
+    let installed = part.installed_attrs.as_deref()?;
+    let mut names = Vec::new();
+    let mut bits = Vec::new();
+    for token in installed.split_whitespace() {
+        match token.strip_prefix("GUID:") {
+            Some(list) => bits.extend(list.split(',').filter_map(|bit| bit.parse::<u8>().ok())),
+            None => names.push(token.to_string()),
+        }
+    }
+    let no_auto = GptAttribute::NoAuto.bit();
+    let wanted = part.attributes.contains(&GptAttribute::NoAuto);
+    if bits.contains(&no_auto) == wanted {
+        return None;
+    }
+    if wanted {
+        bits.push(no_auto);
+    } else {
+        bits.retain(|bit| *bit != no_auto);
+    }
+    bits.sort_unstable();
+    bits.dedup();
+    if !bits.is_empty() {
+        let bits = bits.iter().map(u8::to_string).collect::<Vec<_>>();
+        names.push(format!("GUID:{}", bits.join(",")));
+    }
+    Some(names.join(" "))
+}
+
+// Keeps DPS tooling from auto-mounting the slot the install does not target,
+// along with that slot's /boot.
+fn mark_inactive_slot(partitions: &mut [PartitionTemplate], slot: AbSlot) {
+    let target = format!("/slots/{slot}");
+    for part in partitions.iter_mut().filter(|p| {
+        p.volume_group.is_none()
+            && p.mountpoint.starts_with("/slots/")
+            && !Path::new(&p.mountpoint).starts_with(&target)
+    }) {
+        if !part.attributes.contains(&GptAttribute::NoAuto) {
+            part.attributes.push(GptAttribute::NoAuto);
+        }
+    }
+}
+
+// The block device a template's filesystem lives on: the LUKS mapper for
+// encrypted templates, the partition or logical volume otherwise.
+pub fn template_device(part: &PartitionTemplate) -> String {
+    if part.encrypted {
+        format!("/dev/mapper/{}", luks_mapper_name(part))
+    } else {
+        block_device(part)
+    }
+}
+
+// Everything except boot partitions, A/B slots and their verity hashes
+// becomes a logical volume on a single physical volume that takes the rest of
+// the disk. Slots keep their GPT entries for the DPS types and no-auto bit.
+fn move_to_lvm(partitions: Vec<PartitionTemplate>, vg: &str) -> Vec<PartitionTemplate> {
+    let (mut disk_parts, volumes): (Vec<_>, Vec<_>) = partitions.into_iter().partition(|part| {
+        is_boot_partition(part)
+            || part.mountpoint.starts_with("/slots/")
+            || part.gpt_type == "root-verity"
+    });
+    let disk = volumes
+        .first()
+        .map(|lv| lv.disk.clone())
+        .unwrap_or_default();
+
+    disk_parts.push(PartitionTemplate {
+        disk,
+        index: 0,
+        name: "lvm".to_string(),
+        filesystem: LVM_PV_FILESYSTEM.to_string(),
+        label: "LVM".to_string(),
+        gpt_type: "V".to_string(),
+        partlabel: Some("lvm".to_string()),
+        attributes: vec![],
+        installed_attrs: None,
+        size_mb: None,
+        start_sector: None,
+        end_sector: None,
+        partuuid: None,
+        fs_uuid: None,
+        mountpoint: "none".to_string(),
+        subvolumes: vec![],
+        encrypted: false,
+        volume_group: None,
+        mirrored: false,
+        reused: false,
+    });
+    for (pos, part) in disk_parts.iter_mut().enumerate() {
+        part.index = (pos + 1) as u8;
+    }
+
+    disk_parts.extend(volumes.into_iter().enumerate().map(|(pos, mut lv)| {
+        lv.index = (pos + 1) as u8;
+        lv.volume_group = Some(vg.to_string());
+        lv.partlabel = None;
+        lv.attributes.clear();
+        lv
+    }));
+    disk_parts
+}
+
+fn validate_volume_group_name(vg: &str) -> Result<()> {
+    let valid = !vg.is_empty()
+        && vg.len() <= 127
+        && !vg.starts_with('-')
+        && vg != "."
+        && vg != ".."
+        && vg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '_' | '.' | '-'));
+    if valid {
+        return Ok(());
+    }
+
+    Err(RecpartError::new(
+        ErrorCode::PlanGeneration,
+        "plan",
+        "LVM volume group name uses only [A-Za-z0-9+_.-] and does not start with '-'",
+        format!("lvm_volume_group='{vg}'"),
+        "Choose a volume group name such as recvg.",
+    ))
+}
+
+fn mark_encrypted(partitions: &mut [PartitionTemplate], names: &[String]) -> Result<()> {
+    for name in names {
+        let Some(part) = partitions.iter_mut().find(|part| part.name == *name) else {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "encrypted partitions are part of the plan",
+                format!("partition '{name}' is not in the plan"),
+                "Encrypt state (ab) or root/home (mutable); home needs --home-size.",
+            ));
+        };
+
+        let data = filesystem_spec(&part.filesystem).is_some_and(|spec| spec.data);
+        if !data
+            || !part.mountpoint.starts_with('/')
+            || part.mountpoint.starts_with("/slots/")
+            || is_boot_partition(part)
+        {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                "only writable data partitions are encrypted",
+                format!(
+                    "partition '{name}' is {} mounted at {}",
+                    part.filesystem, part.mountpoint
+                ),
+                "Encrypt state (ab) or root/home (mutable); ESP, /boot, swap, recovery and A/B slots stay plain.",
+            ));
+        }
+        part.encrypted = true;
+    }
+    Ok(())
+}
+
 fn validate_partition_fit(
     partitions: &[PartitionTemplate],
     disk_mb: u64,
@@ -89,6 +1548,23 @@ fn validate_partition_fit(
         .sum::<u64>();
     let has_remaining = partitions.iter().any(|p| p.size_mb.is_none());
 
+    if let Some(pos) = partitions
+        .iter()
+        .position(|p| p.size_mb.is_none())
+        .filter(|pos| *pos + 1 != partitions.len())
+    {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "plan",
+            "only the last partition takes the remaining disk space",
+            format!(
+                "partition '{}' has no resolved size but is not last",
+                partitions[pos].name
+            ),
+            "Set an explicit size for the partition or move it to the end of the profile.",
+        ));
+    }
+
     if fixed_mb > disk_mb {
         return Err(RecpartError::new(
             ErrorCode::PlanGeneration,
@@ -109,5 +1585,30 @@ fn validate_partition_fit(
         ));
     }
 
+    for part in partitions {
+        let Some(spec) = filesystem_spec(&part.filesystem) else {
+            continue;
+        };
+        let size_mb = part
+            .size_mb
+            .map(u64::from)
+            .unwrap_or(disk_mb.saturating_sub(fixed_mb));
+        if size_mb < u64::from(spec.min_size_mb) {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "plan",
+                format!(
+                    "{} partitions are at least {}MB",
+                    spec.name, spec.min_size_mb
+                ),
+                format!("partition '{}' is {size_mb}MB", part.name),
+                format!(
+                    "Grow partition '{}' or choose another filesystem for it.",
+                    part.name
+                ),
+            ));
+        }
+    }
+
     Ok(())
 }
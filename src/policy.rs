@@ -1,11 +1,31 @@
 use crate::error::{ErrorCode, RecpartError, Result};
-use crate::types::{InstallMode, LayoutRequest, PartitionTemplate, ResolvedLayout};
+use crate::profile::{builtin_profile, templates_from_profile};
+use crate::size::{SizeBase, SizeExpr};
+use crate::types::{AbSlot, InstallMode, LayoutRequest, PartitionTemplate, ResolvedLayout};
+use std::path::Path;
 
-pub const DEFAULT_AB_TARGET_SLOT: &str = "A";
+pub const DEFAULT_AB_TARGET_SLOT: AbSlot = AbSlot::A;
 pub const DEFAULT_EFI_SIZE_MB: u32 = 1024;
+// With XBOOTLDR holding kernels, the ESP only carries boot loaders.
+pub const DEFAULT_XBOOTLDR_EFI_SIZE_MB: u32 = 256;
+// Free space a reused ESP needs for our boot loader at /efi, or for boot
+// loader, kernels and initramfs when it is also /boot.
+pub const REUSED_ESP_EFI_MIN_FREE_MB: u32 = 64;
+pub const REUSED_ESP_BOOT_MIN_FREE_MB: u32 = 512;
 pub const DEFAULT_AB_ROOT_SIZE_MB: u32 = 20 * 1024;
 pub const DEFAULT_AB_MIN_STATE_MB: u32 = 4 * 1024;
+// The recovery system is a small, rarely updated image: a kernel, an
+// initramfs and a minimal root.
+pub const DEFAULT_AB_RECOVERY_SIZE_MB: u32 = 2 * 1024;
+pub const DEFAULT_AB_MIN_RECOVERY_MB: u32 = 1024;
+// Each slot's /boot holds one kernel and initramfs set plus its entries.
+pub const DEFAULT_AB_SLOT_BOOT_SIZE_MB: u32 = 1024;
+// A SHA-256 verity tree over 4K blocks takes about 1/127 of its data; a
+// verity partition gets 1/64 of its slot, rounded up to whole MB.
+pub const VERITY_HASH_DIVISOR: u32 = 64;
 pub const DEFAULT_MUTABLE_MIN_ROOT_MB: u32 = 8 * 1024;
+pub const DEFAULT_MUTABLE_HOME_ROOT_MB: u32 = 40 * 1024;
+pub const DEFAULT_MUTABLE_MIN_HOME_MB: u32 = 1024;
 
 pub fn policy_defaults(mode: InstallMode) -> ResolvedLayout {
     match mode {
@@ -16,6 +36,14 @@ pub fn policy_defaults(mode: InstallMode) -> ResolvedLayout {
             root_a_size_mb: Some(DEFAULT_AB_ROOT_SIZE_MB),
             root_b_size_mb: Some(DEFAULT_AB_ROOT_SIZE_MB),
             state_size_mb: None,
+            swap_size_mb: None,
+            separate_home: false,
+            home_size_mb: None,
+            xbootldr_size_mb: None,
+            recovery_size_mb: None,
+            slot_boot_size_mb: None,
+            root_a_verity_size_mb: None,
+            root_b_verity_size_mb: None,
         },
         InstallMode::Mutable => ResolvedLayout {
             mode,
@@ -24,6 +52,14 @@ pub fn policy_defaults(mode: InstallMode) -> ResolvedLayout {
             root_a_size_mb: None,
             root_b_size_mb: None,
             state_size_mb: None,
+            swap_size_mb: None,
+            separate_home: false,
+            home_size_mb: None,
+            xbootldr_size_mb: None,
+            recovery_size_mb: None,
+            slot_boot_size_mb: None,
+            root_a_verity_size_mb: None,
+            root_b_verity_size_mb: None,
         },
     }
 }
@@ -31,25 +67,78 @@ pub fn policy_defaults(mode: InstallMode) -> ResolvedLayout {
 pub fn resolve_layout_request(
     mode: InstallMode,
     request: &LayoutRequest,
+    disk_mb: u64,
 ) -> Result<ResolvedLayout> {
     validate_request_fields(mode, request)?;
     let defaults = policy_defaults(mode);
 
     let mut resolved = defaults;
-    if let Some(efi) = request.efi_size_mb {
-        resolved.efi_size_mb = validate_positive("efi_size_mb", efi)?;
+    resolved.xbootldr_size_mb = resolve_xbootldr(request, disk_mb)?;
+    if mode == InstallMode::Ab {
+        resolved.slot_boot_size_mb = resolve_slot_boot(request, disk_mb)?;
     }
+    if resolved.xbootldr_size_mb.is_some() && resolved.slot_boot_size_mb.is_some() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            "/boot is either one XBOOTLDR partition or per-slot boot partitions",
+            "xbootldr_size and slot_boot are both set".to_string(),
+            "Drop --xbootldr-size or --slot-boot.",
+        ));
+    }
+    if resolved.xbootldr_size_mb.is_some() || resolved.slot_boot_size_mb.is_some() {
+        resolved.efi_size_mb = DEFAULT_XBOOTLDR_EFI_SIZE_MB;
+    }
+    if let Some(efi) = resolve_size(
+        "efi_size",
+        request.efi_size_mb,
+        request.efi_size.as_ref(),
+        Some(resolved.efi_size_mb),
+        disk_mb,
+    )? {
+        resolved.efi_size_mb = efi;
+    }
+    resolved.swap_size_mb = resolve_swap(request, disk_mb)?;
 
     match mode {
         InstallMode::Ab => {
-            if let Some(root_a) = request.root_a_size_mb {
-                resolved.root_a_size_mb = Some(validate_positive("root_a_size_mb", root_a)?);
+            resolved.recovery_size_mb = resolve_recovery(request, disk_mb)?;
+            if let Some(root_a) = resolve_size(
+                "root_a_size",
+                request.root_a_size_mb,
+                request.root_a_size.as_ref(),
+                resolved.root_a_size_mb,
+                disk_mb,
+            )? {
+                resolved.root_a_size_mb = Some(root_a);
+            }
+            if let Some(root_b) = resolve_size(
+                "root_b_size",
+                request.root_b_size_mb,
+                request.root_b_size.as_ref(),
+                resolved.root_b_size_mb,
+                disk_mb,
+            )? {
+                resolved.root_b_size_mb = Some(root_b);
             }
-            if let Some(root_b) = request.root_b_size_mb {
-                resolved.root_b_size_mb = Some(validate_positive("root_b_size_mb", root_b)?);
+            if request.verity {
+                resolved.root_a_verity_size_mb = resolved.root_a_size_mb.map(verity_size_mb);
+                resolved.root_b_verity_size_mb = resolved.root_b_size_mb.map(verity_size_mb);
             }
-            if let Some(state) = request.state_size_mb {
-                let state = validate_positive("state_size_mb", state)?;
+            let state = resolve_size(
+                "state_size",
+                request.state_size_mb,
+                request.state_size.as_ref(),
+                None,
+                disk_mb,
+            )?
+            .or(resolve_remaining(
+                "state_size",
+                request.state_size.as_ref(),
+                &resolved,
+                disk_mb,
+            )?);
+            if let Some(state) = state {
                 if state < DEFAULT_AB_MIN_STATE_MB {
                     return Err(RecpartError::new(
                         ErrorCode::PlanGeneration,
@@ -66,8 +155,24 @@ pub fn resolve_layout_request(
             }
         }
         InstallMode::Mutable => {
-            if let Some(root) = request.root_size_mb {
-                let root = validate_positive("root_size_mb", root)?;
+            // With a separate /home, root gets a fixed default and /home takes
+            // the rest of the disk instead.
+            let separate_home = request.home_size_mb.is_some() || request.home_size.is_some();
+            let root_default = separate_home.then_some(DEFAULT_MUTABLE_HOME_ROOT_MB);
+            let root = match resolve_size(
+                "root_size",
+                request.root_size_mb,
+                request.root_size.as_ref(),
+                root_default,
+                disk_mb,
+            )? {
+                Some(root) => Some(root),
+                None if separate_home => root_default,
+                None => {
+                    resolve_remaining("root_size", request.root_size.as_ref(), &resolved, disk_mb)?
+                }
+            };
+            if let Some(root) = root {
                 if root < DEFAULT_MUTABLE_MIN_ROOT_MB {
                     return Err(RecpartError::new(
                         ErrorCode::PlanGeneration,
@@ -82,88 +187,295 @@ pub fn resolve_layout_request(
                 }
                 resolved.root_size_mb = Some(root);
             }
+            if separate_home {
+                resolved.separate_home = true;
+                resolved.home_size_mb = resolve_size(
+                    "home_size",
+                    request.home_size_mb,
+                    request.home_size.as_ref(),
+                    None,
+                    disk_mb,
+                )?
+                .or(resolve_remaining(
+                    "home_size",
+                    request.home_size.as_ref(),
+                    &resolved,
+                    disk_mb,
+                )?);
+            }
         }
     }
 
     Ok(resolved)
 }
 
+// Hibernation needs room for all of RAM plus headroom; this follows the common
+// RAM + sqrt(RAM) guidance, computed in whole GiB so the result is stable.
+pub fn hibernation_swap_mb(memory_mb: u32) -> u32 {
+    let ram_gib = memory_mb.div_ceil(1024).max(1);
+    let mut sqrt_gib = 1;
+    while sqrt_gib * sqrt_gib < ram_gib {
+        sqrt_gib += 1;
+    }
+    (ram_gib + sqrt_gib).saturating_mul(1024)
+}
+
+pub fn reused_esp_min_free_mb(mountpoint: &str) -> u32 {
+    match mountpoint {
+        "/efi" => REUSED_ESP_EFI_MIN_FREE_MB,
+        _ => REUSED_ESP_BOOT_MIN_FREE_MB,
+    }
+}
+
 pub fn required_min_mb(layout: &ResolvedLayout) -> u32 {
+    layout.swap_size_mb.unwrap_or(0) + layout.xbootldr_size_mb.unwrap_or(0) + mode_min_mb(layout)
+}
+
+fn mode_min_mb(layout: &ResolvedLayout) -> u32 {
     match layout.mode {
         InstallMode::Ab => {
             let root_a = layout.root_a_size_mb.unwrap_or(DEFAULT_AB_ROOT_SIZE_MB);
             let root_b = layout.root_b_size_mb.unwrap_or(DEFAULT_AB_ROOT_SIZE_MB);
             let state = layout.state_size_mb.unwrap_or(DEFAULT_AB_MIN_STATE_MB);
-            layout.efi_size_mb + root_a + root_b + state
+            let recovery = layout.recovery_size_mb.unwrap_or(0);
+            let slot_boot = layout.slot_boot_size_mb.unwrap_or(0) * 2;
+            let verity = layout.root_a_verity_size_mb.unwrap_or(0)
+                + layout.root_b_verity_size_mb.unwrap_or(0);
+            layout.efi_size_mb + slot_boot + root_a + root_b + verity + recovery + state
         }
         InstallMode::Mutable => {
             let root = layout.root_size_mb.unwrap_or(DEFAULT_MUTABLE_MIN_ROOT_MB);
-            layout.efi_size_mb + root
+            let home = match layout.separate_home {
+                true => layout.home_size_mb.unwrap_or(DEFAULT_MUTABLE_MIN_HOME_MB),
+                false => 0,
+            };
+            layout.efi_size_mb + root + home
         }
     }
 }
 
-pub fn build_templates(layout: &ResolvedLayout) -> Vec<PartitionTemplate> {
-    match layout.mode {
-        InstallMode::Ab => vec![
-            PartitionTemplate {
-                index: 1,
-                name: "efi".to_string(),
-                filesystem: "vfat".to_string(),
-                label: "EFI".to_string(),
-                gpt_type: "U".to_string(),
-                size_mb: Some(layout.efi_size_mb),
-                mountpoint: "/boot".to_string(),
-            },
-            PartitionTemplate {
-                index: 2,
-                name: "root_a".to_string(),
-                filesystem: "ext4".to_string(),
-                label: "ROOT_A".to_string(),
-                gpt_type: "L".to_string(),
-                size_mb: layout.root_a_size_mb,
-                mountpoint: "/slots/A".to_string(),
-            },
-            PartitionTemplate {
-                index: 3,
-                name: "root_b".to_string(),
-                filesystem: "ext4".to_string(),
-                label: "ROOT_B".to_string(),
-                gpt_type: "L".to_string(),
-                size_mb: layout.root_b_size_mb,
-                mountpoint: "/slots/B".to_string(),
-            },
-            PartitionTemplate {
-                index: 4,
-                name: "state".to_string(),
-                filesystem: "ext4".to_string(),
-                label: "STATE".to_string(),
-                gpt_type: "L".to_string(),
-                size_mb: layout.state_size_mb,
-                mountpoint: "/state".to_string(),
-            },
-        ],
-        InstallMode::Mutable => vec![
-            PartitionTemplate {
-                index: 1,
-                name: "efi".to_string(),
-                filesystem: "vfat".to_string(),
-                label: "EFI".to_string(),
-                gpt_type: "U".to_string(),
-                size_mb: Some(layout.efi_size_mb),
-                mountpoint: "/boot".to_string(),
-            },
-            PartitionTemplate {
-                index: 2,
-                name: "root".to_string(),
-                filesystem: "ext4".to_string(),
-                label: "ROOT".to_string(),
-                gpt_type: "L".to_string(),
-                size_mb: layout.root_size_mb,
-                mountpoint: "/".to_string(),
-            },
-        ],
+pub fn build_templates(layout: &ResolvedLayout, disk: &Path) -> Result<Vec<PartitionTemplate>> {
+    let profile = builtin_profile(layout.mode)?;
+    Ok(templates_from_profile(&profile, layout, disk))
+}
+
+// Resolves one size field from either its MB value or its size expression.
+// Bounds-only expressions clamp `default`; with no default they are left for
+// `resolve_remaining`.
+fn resolve_size(
+    field: &str,
+    mb: Option<u32>,
+    expr: Option<&SizeExpr>,
+    default: Option<u32>,
+    disk_mb: u64,
+) -> Result<Option<u32>> {
+    match (mb, expr) {
+        (Some(_), Some(_)) => Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            format!("only one of {field}_mb and {field} is set"),
+            format!("both {field}_mb and {field} provided"),
+            format!(
+                "Use either --{}-mb or --{}.",
+                field.replace('_', "-"),
+                field.replace('_', "-")
+            ),
+        )),
+        (Some(mb), None) => Ok(Some(validate_positive(&format!("{field}_mb"), mb)?)),
+        (None, Some(expr)) if expr.base == Some(SizeBase::Remaining) && default.is_some() => {
+            Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "policy",
+                format!("{field} is a fixed size or percentage"),
+                format!("{field}=remaining"),
+                format!(
+                    "Only the last partition takes the remaining space; give --{} a size.",
+                    field.replace('_', "-")
+                ),
+            ))
+        }
+        (None, Some(expr)) => {
+            let Some(base) = expr.base_mb(disk_mb).or(default.map(u64::from)) else {
+                return Ok(None);
+            };
+            Ok(Some(clamped_size(field, expr, base)?))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+fn resolve_xbootldr(request: &LayoutRequest, disk_mb: u64) -> Result<Option<u32>> {
+    if request
+        .xbootldr_size
+        .as_ref()
+        .is_some_and(SizeExpr::takes_remaining)
+    {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            "xbootldr_size has a base size or percentage",
+            "xbootldr_size does not set a fixed size".to_string(),
+            "Use --xbootldr-size like 1G or 1G,max:2G.",
+        ));
     }
+    resolve_size(
+        "xbootldr_size",
+        request.xbootldr_size_mb,
+        request.xbootldr_size.as_ref(),
+        None,
+        disk_mb,
+    )
+}
+
+fn resolve_recovery(request: &LayoutRequest, disk_mb: u64) -> Result<Option<u32>> {
+    if !request.recovery && request.recovery_size_mb.is_none() && request.recovery_size.is_none() {
+        return Ok(None);
+    }
+    let recovery = resolve_size(
+        "recovery_size",
+        request.recovery_size_mb,
+        request.recovery_size.as_ref(),
+        Some(DEFAULT_AB_RECOVERY_SIZE_MB),
+        disk_mb,
+    )?
+    .unwrap_or(DEFAULT_AB_RECOVERY_SIZE_MB);
+    if recovery < DEFAULT_AB_MIN_RECOVERY_MB {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            format!("recovery_size_mb is at least {DEFAULT_AB_MIN_RECOVERY_MB}"),
+            format!("recovery_size_mb={recovery}"),
+            format!("Use --recovery-size-mb >= {DEFAULT_AB_MIN_RECOVERY_MB}."),
+        ));
+    }
+    Ok(Some(recovery))
+}
+
+fn resolve_slot_boot(request: &LayoutRequest, disk_mb: u64) -> Result<Option<u32>> {
+    if !request.slot_boot && request.slot_boot_size_mb.is_none() && request.slot_boot_size.is_none()
+    {
+        return Ok(None);
+    }
+    Ok(Some(
+        resolve_size(
+            "slot_boot_size",
+            request.slot_boot_size_mb,
+            request.slot_boot_size.as_ref(),
+            Some(DEFAULT_AB_SLOT_BOOT_SIZE_MB),
+            disk_mb,
+        )?
+        .unwrap_or(DEFAULT_AB_SLOT_BOOT_SIZE_MB),
+    ))
+}
+
+pub fn verity_size_mb(slot_mb: u32) -> u32 {
+    slot_mb.div_ceil(VERITY_HASH_DIVISOR)
+}
+
+fn resolve_swap(request: &LayoutRequest, disk_mb: u64) -> Result<Option<u32>> {
+    let Some(memory_mb) = request.swap_hibernate_memory_mb else {
+        if request
+            .swap_size
+            .as_ref()
+            .is_some_and(SizeExpr::takes_remaining)
+        {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "policy",
+                "swap_size has a base size or percentage",
+                "swap_size does not set a fixed size".to_string(),
+                "Use --swap-size like 8G, 5%, or 8G,max:16G, or --swap-size auto.",
+            ));
+        }
+        return resolve_size(
+            "swap_size",
+            request.swap_size_mb,
+            request.swap_size.as_ref(),
+            None,
+            disk_mb,
+        );
+    };
+
+    if request.swap_size_mb.is_some() || request.swap_size.is_some() {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            "automatic hibernation swap is not combined with an explicit swap size",
+            "swap_hibernate_memory_mb provided with swap_size_mb/swap_size".to_string(),
+            "Use either --swap-size auto or an explicit swap size.",
+        ));
+    }
+
+    let memory_mb = validate_positive("swap_hibernate_memory_mb", memory_mb)?;
+    Ok(Some(hibernation_swap_mb(memory_mb)))
+}
+
+// Bounds on a remaining-space partition apply to whatever the other resolved
+// partitions leave free. Unless `max` caps it, the partition keeps taking the
+// rest of the disk so sfdisk can account for alignment and the backup GPT.
+fn resolve_remaining(
+    field: &str,
+    expr: Option<&SizeExpr>,
+    resolved: &ResolvedLayout,
+    disk_mb: u64,
+) -> Result<Option<u32>> {
+    let Some(expr) = expr.filter(|expr| expr.takes_remaining()) else {
+        return Ok(None);
+    };
+
+    let remaining = disk_mb.saturating_sub(allocated_mb(resolved));
+    if let Some(min) = expr.min_mb.filter(|min| u64::from(*min) > remaining) {
+        return Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            format!("{field} minimum fits in the remaining disk space"),
+            format!("{field} needs at least {min}MB, {remaining}MB remain"),
+            "Lower the minimum, shrink other partitions, or select a larger disk.",
+        ));
+    }
+
+    match expr.max_mb.filter(|max| u64::from(*max) < remaining) {
+        Some(max) => Ok(Some(max)),
+        None => Ok(None),
+    }
+}
+
+fn clamped_size(field: &str, expr: &SizeExpr, base_mb: u64) -> Result<u32> {
+    let value = expr.clamp(base_mb);
+    match u32::try_from(value) {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "policy",
+            format!("{field} resolves to a size between 1MB and {}MB", u32::MAX),
+            format!("{field}={expr} resolves to {value}MB"),
+            format!(
+                "Adjust --{} so it resolves to a usable size on this disk.",
+                field.replace('_', "-")
+            ),
+        )),
+    }
+}
+
+fn allocated_mb(layout: &ResolvedLayout) -> u64 {
+    [
+        Some(layout.efi_size_mb),
+        layout.root_size_mb,
+        layout.root_a_size_mb,
+        layout.root_b_size_mb,
+        layout.state_size_mb,
+        layout.swap_size_mb,
+        layout.home_size_mb,
+        layout.xbootldr_size_mb,
+        layout.recovery_size_mb,
+        layout.slot_boot_size_mb.map(|mb| mb * 2),
+        layout.root_a_verity_size_mb,
+        layout.root_b_verity_size_mb,
+    ]
+    .into_iter()
+    .flatten()
+    .map(u64::from)
+    .sum()
 }
 
 fn validate_positive(field: &str, value: u32) -> Result<u32> {
@@ -186,24 +498,45 @@ fn validate_positive(field: &str, value: u32) -> Result<u32> {
 fn validate_request_fields(mode: InstallMode, request: &LayoutRequest) -> Result<()> {
     match mode {
         InstallMode::Ab => {
-            if request.root_size_mb.is_some() {
+            let invalid = [
+                ("root_size_mb", request.root_size_mb.is_some()),
+                ("root_size", request.root_size.is_some()),
+                ("home_size_mb", request.home_size_mb.is_some()),
+                ("home_size", request.home_size.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(k, set)| set.then_some(k))
+            .collect::<Vec<_>>();
+
+            if !invalid.is_empty() {
                 return Err(RecpartError::new(
                     ErrorCode::PlanGeneration,
                     "policy",
-                    "mutable-only root_size_mb is not set for ab mode",
-                    "root_size_mb provided with mode=ab".to_string(),
+                    "mutable-only fields are not set for ab mode",
+                    format!("invalid fields for mode=ab: {}", invalid.join(", ")),
                     "Use --root-a-size-mb/--root-b-size-mb/--state-size-mb for ab mode.",
                 ));
             }
         }
         InstallMode::Mutable => {
             let invalid = [
-                ("root_a_size_mb", request.root_a_size_mb),
-                ("root_b_size_mb", request.root_b_size_mb),
-                ("state_size_mb", request.state_size_mb),
+                ("root_a_size_mb", request.root_a_size_mb.is_some()),
+                ("root_b_size_mb", request.root_b_size_mb.is_some()),
+                ("state_size_mb", request.state_size_mb.is_some()),
+                ("root_a_size", request.root_a_size.is_some()),
+                ("root_b_size", request.root_b_size.is_some()),
+                ("state_size", request.state_size.is_some()),
+                ("install_slot", request.install_slot.is_some()),
+                ("recovery", request.recovery),
+                ("recovery_size_mb", request.recovery_size_mb.is_some()),
+                ("recovery_size", request.recovery_size.is_some()),
+                ("slot_boot", request.slot_boot),
+                ("slot_boot_size_mb", request.slot_boot_size_mb.is_some()),
+                ("slot_boot_size", request.slot_boot_size.is_some()),
+                ("verity", request.verity),
             ]
             .into_iter()
-            .filter_map(|(k, v)| v.map(|_| k))
+            .filter_map(|(k, set)| set.then_some(k))
             .collect::<Vec<_>>();
 
             if !invalid.is_empty() {
@@ -212,7 +545,7 @@ fn validate_request_fields(mode: InstallMode, request: &LayoutRequest) -> Result
                     "policy",
                     "ab-only fields are not set for mutable mode",
                     format!("invalid fields for mode=mutable: {}", invalid.join(", ")),
-                    "Use only --efi-size-mb and --root-size-mb for mutable mode.",
+                    "Use only EFI, root, home and swap size flags (no install slot, recovery, slot boot or verity) for mutable mode.",
                 ));
             }
         }
@@ -1,7 +1,15 @@
 use crate::error::{ErrorCode, RecpartError, Result};
-use crate::types::{DiskInventory, DiskListResult, DiskTarget, DISK_LIST_SCHEMA_VERSION};
+use crate::fat::fat_free_bytes;
+use crate::filesystem::filesystem_spec;
+use crate::resize::{ext4_min_size_bytes, ntfs_min_size_bytes};
+use crate::table::{inspect_table, parse_blkid_export, parse_sfdisk_json};
+use crate::types::{
+    DiskInspectResult, DiskInventory, DiskListResult, DiskTarget, PartitionPlan, PartitionTable,
+    ShrinkCandidate, DISK_LIST_SCHEMA_VERSION,
+};
 use distro_spec::shared::{is_protected_path, is_root};
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fs;
 use std::os::unix::fs::FileTypeExt;
@@ -49,16 +57,45 @@ impl LsblkReadOnly {
     }
 }
 
-pub const REQUIRED_TOOLS: &[&str] = &[
-    "lsblk",
-    "sfdisk",
-    "wipefs",
-    "mkfs.vfat",
-    "mkfs.ext4",
-    "mount",
-    "udevadm",
-    "blkid",
-];
+pub const BASE_TOOLS: &[&str] = &["lsblk", "sfdisk", "mount", "udevadm", "blkid"];
+
+// Only the mkfs tools for filesystems the plan actually formats are required,
+// and wipefs only when a disk is wiped (not for append or reinstall plans).
+pub fn required_tools(plan: &PartitionPlan) -> Vec<&'static str> {
+    let mut tools = BASE_TOOLS.to_vec();
+    if plan.sfdisk_scripts.iter().any(|script| !script.append) {
+        tools.push("wipefs");
+    }
+    match plan
+        .shrink
+        .as_ref()
+        .map(|shrink| shrink.filesystem.as_str())
+    {
+        Some("ext4") => tools.extend(["e2fsck", "resize2fs"]),
+        Some("ntfs") => tools.push("ntfsresize"),
+        _ => {}
+    }
+    for part in &plan.partitions {
+        if let Some(spec) = filesystem_spec(&part.filesystem).filter(|_| !part.reused) {
+            tools.push(spec.mkfs);
+        }
+        if !part.subvolumes.is_empty() {
+            tools.extend(["btrfs", "umount"]);
+        }
+        if part.encrypted {
+            tools.push("cryptsetup");
+        }
+        if part.volume_group.is_some() {
+            tools.extend(["pvcreate", "vgcreate", "lvcreate"]);
+        }
+        if part.mirrored {
+            tools.push("mdadm");
+        }
+    }
+    let mut seen = HashSet::new();
+    tools.retain(|tool| seen.insert(*tool));
+    tools
+}
 
 pub fn tool_in_path(tool: &str) -> bool {
     let Some(paths) = env::var_os("PATH") else {
@@ -77,8 +114,12 @@ pub fn tool_in_path(tool: &str) -> bool {
     })
 }
 
-pub fn ensure_required_tools() -> Result<()> {
-    for tool in REQUIRED_TOOLS {
+pub fn ensure_required_tools(plan: &PartitionPlan) -> Result<()> {
+    ensure_tools(&required_tools(plan))
+}
+
+pub fn ensure_tools(tools: &[&str]) -> Result<()> {
+    for tool in tools {
         if !tool_in_path(tool) {
             return Err(RecpartError::new(
                 ErrorCode::MissingTool,
@@ -253,6 +294,104 @@ pub fn ensure_disk_not_mounted(path: &Path) -> Result<()> {
     ))
 }
 
+// A slot reinstall leaves the kept ESP and STATE mounted if they are, so
+// only the partition being reformatted is checked.
+pub fn ensure_partition_not_mounted(device: &str) -> Result<()> {
+    let mounts = fs::read_to_string("/proc/self/mounts").map_err(|err| {
+        RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            "/proc/self/mounts is readable",
+            err.to_string(),
+            "Run apply on a Linux system with /proc mounted.",
+        )
+    })?;
+    let canonical = fs::canonicalize(device)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| device.to_string());
+    check_partition_unmounted(&canonical, &mounts)
+}
+
+// `mounts` is in /proc/self/mounts format; a mount at / means `device` holds
+// the running system.
+pub fn check_partition_unmounted(device: &str, mounts: &str) -> Result<()> {
+    let mountpoints = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let target = fields.next()?;
+            (source == device).then(|| target.replace("\\040", " "))
+        })
+        .collect::<Vec<_>>();
+    if mountpoints.is_empty() {
+        return Ok(());
+    }
+
+    let running = mountpoints.iter().any(|target| target == "/");
+    Err(RecpartError::new(
+        ErrorCode::InvalidTargetDisk,
+        "preflight",
+        format!("reformatted partition '{device}' is not mounted or the running root"),
+        format!("'{device}' is mounted at {}", mountpoints.join(", ")),
+        if running {
+            "Boot the other slot (or a live system) before reinstalling this one."
+        } else {
+            "Unmount the slot before reinstalling it."
+        },
+    ))
+}
+
+// A shared ESP is mounted as it is, so it must already be vfat and have room
+// for what the install adds.
+pub fn ensure_reusable_esp(device: &str, min_free_mb: u32) -> Result<()> {
+    let output = Command::new("blkid")
+        .args(["-o", "value", "-s", "TYPE", device])
+        .output()
+        .map_err(|err| {
+            RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "preflight",
+                "blkid can identify the reused ESP filesystem",
+                format!("failed to execute blkid: {err}"),
+                "Ensure util-linux is installed and blkid is available.",
+            )
+        })?;
+    let fs_type = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if fs_type != "vfat" {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("reused ESP '{device}' holds a vfat filesystem"),
+            format!("blkid reports type '{fs_type}'"),
+            "Reuse a formatted ESP, or drop --reuse-esp to create a new one.",
+        ));
+    }
+
+    let free_bytes = fs::File::open(device)
+        .and_then(|mut file| fat_free_bytes(&mut file))
+        .map_err(|err| {
+            RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "preflight",
+                format!("reused ESP '{device}' has a readable FAT"),
+                err.to_string(),
+                "Check the ESP with fsck.vfat before reusing it.",
+            )
+        })?;
+    let free_mb = free_bytes / (1024 * 1024);
+    if free_mb < u64::from(min_free_mb) {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("reused ESP '{device}' has at least {min_free_mb}MB free"),
+            format!("{free_mb}MB free"),
+            "Free space on the ESP, or add --xbootldr-size so kernels live on a separate /boot.",
+        ));
+    }
+    Ok(())
+}
+
 pub fn ensure_disk_writable(path: &Path) -> Result<()> {
     let output = Command::new("lsblk")
         .args(["-dn", "-o", "RO", &path.to_string_lossy()])
@@ -396,14 +535,205 @@ pub fn query_disk_target(path: &Path) -> Result<DiskTarget> {
             )
         })?;
 
+    let (optimal_io_bytes, alignment_offset_bytes) = query_io_topology(&canonical);
     Ok(DiskTarget {
         path: canonical,
         size_bytes,
         logical_sector_bytes,
         physical_sector_bytes,
+        optimal_io_bytes,
+        alignment_offset_bytes,
+    })
+}
+
+// Optional I/O hints; missing sysfs attributes (loop devices, containers)
+// fall back to 0, which leaves alignment at 1MiB and the physical sector.
+fn query_io_topology(disk: &Path) -> (u32, u32) {
+    let Some(name) = disk.file_name() else {
+        return (0, 0);
+    };
+    let sysfs = Path::new("/sys/class/block").join(name);
+    let read = |attr: &str| {
+        fs::read_to_string(sysfs.join(attr))
+            .ok()
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+    (read("queue/optimal_io_size"), read("alignment_offset"))
+}
+
+// The partition table currently on `disk`, for plans that keep it.
+pub fn read_partition_table(disk: &Path) -> Result<PartitionTable> {
+    let output = Command::new("sfdisk")
+        .args(["--json", &disk.to_string_lossy()])
+        .output()
+        .map_err(|err| {
+            RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "preflight",
+                "sfdisk can read the disk's partition table",
+                format!("failed to execute sfdisk: {err}"),
+                "Ensure util-linux is installed and sfdisk is available.",
+            )
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("disk '{}' has a partition table", disk.display()),
+            stderr.trim().to_string(),
+            "Free-space installs need an existing gpt or dos table; partition the whole disk instead.",
+        ));
+    }
+    parse_sfdisk_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+// What is on `disk` now, for `recpart inspect`; nothing is written.
+pub fn inspect_disk(path: &Path) -> Result<DiskInspectResult> {
+    let disk = query_disk_target(path)?;
+    let disk_path = disk.path.to_string_lossy().to_string();
+    let pttype = probe_blkid(&["-p", "-o", "value", "-s", "PTTYPE", &disk_path])?;
+    let table = if pttype.trim().is_empty() {
+        None
+    } else {
+        Some(read_partition_table(&disk.path)?)
+    };
+
+    let mut args = vec!["-p", "-o", "export"];
+    args.extend(
+        table
+            .iter()
+            .flat_map(|table| &table.partitions)
+            .map(|part| part.device.as_str()),
+    );
+    let probes = if args.len() > 3 {
+        parse_blkid_export(&probe_blkid(&args)?)
+    } else {
+        BTreeMap::new()
+    };
+    Ok(inspect_table(disk, table, &probes))
+}
+
+// blkid output, empty when it finds nothing (exit status 2).
+fn probe_blkid(args: &[&str]) -> Result<String> {
+    let output = Command::new("blkid").args(args).output().map_err(|err| {
+        RecpartError::new(
+            ErrorCode::MissingTool,
+            "preflight",
+            "blkid can be executed",
+            format!("failed to execute blkid: {err}"),
+            "Ensure util-linux is installed and blkid is available.",
+        )
+    })?;
+    match output.status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        Some(2) => Ok(String::new()),
+        _ => Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("blkid {} succeeds", args.join(" ")),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            "Verify the disk exists and is readable.",
+        )),
+    }
+}
+
+// How far the filesystem on `device` can shrink, for shrink plans.
+pub fn query_shrink_candidate(device: &str) -> Result<ShrinkCandidate> {
+    let fs_type = query_output("blkid", &["-o", "value", "-s", "TYPE", device])?;
+    let filesystem = fs_type.trim().to_string();
+    let min_size_bytes = match filesystem.as_str() {
+        "ext4" => ext4_min_size_bytes(
+            &query_output("resize2fs", &["-P", device])?,
+            &query_output("tune2fs", &["-l", device])?,
+        ),
+        "ntfs" => ntfs_min_size_bytes(&query_output(
+            "ntfsresize",
+            &["--info", "--no-progress-bar", device],
+        )?),
+        _ => {
+            return Err(RecpartError::new(
+                ErrorCode::InvalidTargetDisk,
+                "preflight",
+                format!("'{device}' holds an ext4 or ntfs filesystem"),
+                format!("blkid reports type '{filesystem}'"),
+                "Shrink an ext4 or NTFS partition, or resize this one with its own tools first.",
+            ));
+        }
+    };
+    let Some(min_size_bytes) = min_size_bytes else {
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("the {filesystem} filesystem on '{device}' reports a minimum size"),
+            "no minimum size in the resize tool output",
+            "Check the filesystem (e2fsck -f, or chkdsk from Windows) and retry.",
+        ));
+    };
+    Ok(ShrinkCandidate {
+        device: device.to_string(),
+        filesystem,
+        min_size_bytes,
+    })
+}
+
+fn query_output(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program).args(args).output().map_err(|err| {
+        RecpartError::new(
+            ErrorCode::MissingTool,
+            "preflight",
+            format!("{program} can be executed"),
+            format!("failed to execute {program}: {err}"),
+            format!("Install {program} to plan this change."),
+        )
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(RecpartError::new(
+            ErrorCode::InvalidTargetDisk,
+            "preflight",
+            format!("{program} {} succeeds", args.join(" ")),
+            stderr.trim().to_string(),
+            "Verify the partition exists and is not mounted.",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub fn query_memory_mb() -> Result<u32> {
+    let meminfo = fs::read_to_string("/proc/meminfo").map_err(|err| {
+        RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "preflight",
+            "/proc/meminfo is readable",
+            err.to_string(),
+            "Mount /proc or pass an explicit --swap-size instead of auto.",
+        )
+    })?;
+
+    parse_mem_total_mb(&meminfo).ok_or_else(|| {
+        RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "preflight",
+            "/proc/meminfo contains a MemTotal line in kB",
+            meminfo.lines().next().unwrap_or_default().to_string(),
+            "Pass an explicit --swap-size instead of auto.",
+        )
     })
 }
 
+pub fn parse_mem_total_mb(meminfo: &str) -> Option<u32> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let mut fields = line.split_whitespace().skip(1);
+    let kib = fields.next()?.parse::<u64>().ok()?;
+    if fields.next() != Some("kB") {
+        return None;
+    }
+    u32::try_from(kib.div_ceil(1024)).ok()
+}
+
 pub fn list_candidate_disks() -> Result<Vec<DiskTarget>> {
     Ok(list_disk_inventory()?
         .disks
@@ -413,6 +743,8 @@ pub fn list_candidate_disks() -> Result<Vec<DiskTarget>> {
             size_bytes: disk.size_bytes,
             logical_sector_bytes: disk.logical_sector_bytes,
             physical_sector_bytes: disk.physical_sector_bytes,
+            optimal_io_bytes: disk.optimal_io_bytes,
+            alignment_offset_bytes: disk.alignment_offset_bytes,
         })
         .collect())
 }
@@ -477,6 +809,8 @@ pub fn list_disk_inventory() -> Result<DiskListResult> {
             size_bytes: target.size_bytes,
             logical_sector_bytes: target.logical_sector_bytes,
             physical_sector_bytes: target.physical_sector_bytes,
+            optimal_io_bytes: target.optimal_io_bytes,
+            alignment_offset_bytes: target.alignment_offset_bytes,
             model: row
                 .model
                 .as_deref()
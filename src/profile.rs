@@ -0,0 +1,510 @@
+use crate::dps::{is_boot_mountpoint, is_esp_type};
+use crate::error::{ErrorCode, RecpartError, Result};
+use crate::filesystem::{filesystem_names, filesystem_spec, UNFORMATTED_FILESYSTEM};
+use crate::types::{
+    InstallMode, LayoutProfile, LayoutSizeField, PartitionTemplate, ProfilePartition,
+    ResolvedLayout, SizeRule,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const AB_PROFILE: &str = include_str!("../profiles/ab.toml");
+const MUTABLE_PROFILE: &str = include_str!("../profiles/mutable.toml");
+// Filesystems an XBOOTLDR /boot partition may use.
+const BOOT_FILESYSTEMS: &[&str] = &["vfat", "ext4"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Toml,
+    Json,
+}
+
+pub fn builtin_profile(mode: InstallMode) -> Result<LayoutProfile> {
+    let source = match mode {
+        InstallMode::Ab => AB_PROFILE,
+        InstallMode::Mutable => MUTABLE_PROFILE,
+    };
+
+    parse_profile(source, ProfileFormat::Toml).map_err(|err| {
+        RecpartError::new(
+            ErrorCode::Internal,
+            "profile",
+            format!("built-in {mode} profile parses"),
+            err.observed,
+            "Rebuild recpart; the embedded profile definitions are corrupt.",
+        )
+    })
+}
+
+pub fn parse_profile(source: &str, format: ProfileFormat) -> Result<LayoutProfile> {
+    let parsed = match format {
+        ProfileFormat::Toml => {
+            toml::from_str::<LayoutProfile>(source).map_err(|err| err.to_string())
+        }
+        ProfileFormat::Json => {
+            serde_json::from_str::<LayoutProfile>(source).map_err(|err| err.to_string())
+        }
+    };
+
+    parsed.map_err(|err| {
+        RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "profile",
+            "layout profile matches the profile schema",
+            err,
+            "Fix the layout profile syntax (see profiles/ab.toml for a reference profile).",
+        )
+    })
+}
+
+pub fn load_profile(path: &Path) -> Result<LayoutProfile> {
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => ProfileFormat::Toml,
+        Some("json") => ProfileFormat::Json,
+        _ => {
+            return Err(RecpartError::new(
+                ErrorCode::PlanGeneration,
+                "profile",
+                "layout profile path ends in .toml or .json",
+                format!("unsupported profile path '{}'", path.display()),
+                "Rename the profile file with a .toml or .json extension.",
+            ));
+        }
+    };
+
+    let source = fs::read_to_string(path).map_err(|err| {
+        RecpartError::new(
+            ErrorCode::PlanGeneration,
+            "profile",
+            format!("layout profile '{}' is readable", path.display()),
+            err.to_string(),
+            "Check the --profile path and file permissions.",
+        )
+    })?;
+
+    parse_profile(&source, format)
+}
+
+pub fn apply_filesystem_overrides(
+    profile: &LayoutProfile,
+    overrides: &BTreeMap<String, String>,
+) -> Result<LayoutProfile> {
+    let mut profile = profile.clone();
+    for (name, filesystem) in overrides {
+        let Some(part) = profile
+            .partitions
+            .iter_mut()
+            .find(|part| part.name == *name)
+        else {
+            return Err(profile_error(
+                "filesystem overrides name a partition in the profile",
+                format!("profile '{}' has no partition '{name}'", profile.name),
+                "Use a partition name from the selected profile (for example root or state).",
+            ));
+        };
+
+        // Boot loaders read /boot, so it only switches between vfat and ext4.
+        let is_data = |name: &str| filesystem_spec(name).is_some_and(|spec| spec.data);
+        let allowed = match part.mountpoint.as_str() {
+            boot if is_boot_mountpoint(boot) && !is_esp_type(&part.gpt_type) => {
+                BOOT_FILESYSTEMS.contains(&filesystem.as_str())
+            }
+            _ => is_data(&part.filesystem) && is_data(filesystem),
+        };
+        if !allowed {
+            return Err(profile_error(
+                format!(
+                    "filesystem overrides switch data partitions between {} and /boot between {}",
+                    filesystem_names(true).join(", "),
+                    BOOT_FILESYSTEMS.join(", ")
+                ),
+                format!(
+                    "partition '{name}' is {}, requested {filesystem}",
+                    part.filesystem
+                ),
+                "Override only data partitions, with a supported data filesystem.",
+            ));
+        }
+        part.filesystem = filesystem.clone();
+    }
+    Ok(profile)
+}
+
+pub fn validate_profile(profile: &LayoutProfile) -> Result<()> {
+    if profile.name.trim().is_empty() {
+        return Err(profile_error(
+            "profile name is not empty",
+            "name is empty".to_string(),
+            "Set a non-empty name in the layout profile.",
+        ));
+    }
+
+    if profile.partitions.is_empty() {
+        return Err(profile_error(
+            "profile declares at least one partition",
+            format!("profile '{}' has no partitions", profile.name),
+            "Add [[partitions]] entries to the layout profile.",
+        ));
+    }
+
+    let mut names = HashSet::new();
+    let mut labels = HashSet::new();
+    let mut partlabels = HashSet::new();
+    let last = profile.partitions.len() - 1;
+
+    for (pos, part) in profile.partitions.iter().enumerate() {
+        if part.name.trim().is_empty() || !names.insert(part.name.as_str()) {
+            return Err(profile_error(
+                "partition names are non-empty and unique",
+                format!("invalid or duplicate partition name '{}'", part.name),
+                "Give every profile partition a distinct name.",
+            ));
+        }
+
+        if part.label.trim().is_empty() || !labels.insert(part.label.as_str()) {
+            return Err(profile_error(
+                "partition labels are non-empty and unique",
+                format!("invalid or duplicate label '{}'", part.label),
+                "Give every profile partition a distinct label.",
+            ));
+        }
+
+        // GPT names hold 36 UTF-16 code units; sfdisk scripts quote them.
+        let partlabel = part.partlabel.as_deref().unwrap_or(&part.name);
+        if partlabel.encode_utf16().count() > 36
+            || partlabel.chars().any(|c| c == '"' || c.is_control())
+            || !partlabels.insert(partlabel)
+        {
+            return Err(profile_error(
+                "GPT partition names are unique, at most 36 characters and free of quotes",
+                format!("invalid or duplicate partlabel '{partlabel}'"),
+                "Set a shorter, distinct partlabel for the partition.",
+            ));
+        }
+
+        let spec = filesystem_spec(&part.filesystem);
+        if spec.is_none() && part.filesystem != UNFORMATTED_FILESYSTEM {
+            return Err(profile_error(
+                format!(
+                    "filesystem is one of: {}, or {UNFORMATTED_FILESYSTEM} to leave it unformatted",
+                    filesystem_names(false).join(", ")
+                ),
+                format!("partition '{}' uses '{}'", part.name, part.filesystem),
+                "Choose a supported filesystem for the partition.",
+            ));
+        }
+
+        if let Some(spec) = spec.filter(|spec| part.label.len() > spec.max_label_bytes) {
+            return Err(profile_error(
+                format!(
+                    "{} labels are at most {} bytes",
+                    part.filesystem, spec.max_label_bytes
+                ),
+                format!("label '{}' is {} bytes", part.label, part.label.len()),
+                "Shorten the partition label.",
+            ));
+        }
+
+        if part.gpt_type.trim().is_empty() {
+            return Err(profile_error(
+                "partition gpt_type is not empty",
+                format!("partition '{}' has no gpt_type", part.name),
+                "Set gpt_type to an sfdisk type shortcut or GUID.",
+            ));
+        }
+
+        if part.filesystem == "swap" || part.filesystem == UNFORMATTED_FILESYSTEM {
+            if part.mountpoint != "none" {
+                return Err(profile_error(
+                    "swap and unformatted partitions use mountpoint \"none\"",
+                    format!("partition '{}' mountpoint '{}'", part.name, part.mountpoint),
+                    "Set mountpoint = \"none\" for swap and unformatted partitions.",
+                ));
+            }
+        } else if part.mountpoint != "none" && !part.mountpoint.starts_with('/') {
+            return Err(profile_error(
+                "partition mountpoint is absolute or \"none\"",
+                format!("partition '{}' mountpoint '{}'", part.name, part.mountpoint),
+                "Use an absolute mountpoint such as /boot or /state, or \"none\" to leave it unmounted.",
+            ));
+        }
+
+        validate_subvolumes(part)?;
+
+        if part.optional && !matches!(part.size, SizeRule::Layout(_)) {
+            return Err(profile_error(
+                "optional partitions take their size from a layout field",
+                format!(
+                    "optional partition '{}' has a {:?} size",
+                    part.name, part.size
+                ),
+                "Use size = { layout = \"...\" } for optional partitions.",
+            ));
+        }
+
+        match part.size {
+            SizeRule::FixedMb(0) => {
+                return Err(profile_error(
+                    "fixed_mb sizes are greater than 0",
+                    format!("partition '{}' has fixed_mb = 0", part.name),
+                    "Use a positive fixed_mb or the \"remaining\" size rule.",
+                ));
+            }
+            SizeRule::Remaining if pos != last => {
+                return Err(profile_error(
+                    "only the last partition uses the remaining size rule",
+                    format!(
+                        "partition '{}' at position {} is remaining",
+                        part.name,
+                        pos + 1
+                    ),
+                    "Move the remaining-size partition to the end of the profile.",
+                ));
+            }
+            SizeRule::Layout(field) if !field_allowed(profile.mode, field) => {
+                return Err(profile_error(
+                    format!("layout size fields match profile mode {}", profile.mode),
+                    format!("partition '{}' references {field}", part.name),
+                    "Reference only layout fields valid for the profile mode.",
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let required_mounts: &[&str] = match profile.mode {
+        InstallMode::Ab => &["/slots/A", "/slots/B"],
+        InstallMode::Mutable => &["/"],
+    };
+    for mountpoint in required_mounts {
+        if !profile
+            .partitions
+            .iter()
+            .any(|part| part.mountpoint == *mountpoint)
+        {
+            return Err(profile_error(
+                format!(
+                    "{} profile has a partition mounted at {mountpoint}",
+                    profile.mode
+                ),
+                format!("profile '{}' has no {mountpoint} partition", profile.name),
+                "Add the missing root partition to the layout profile.",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn templates_from_profile(
+    profile: &LayoutProfile,
+    layout: &ResolvedLayout,
+    disk: &Path,
+) -> Vec<PartitionTemplate> {
+    let mut templates = Vec::with_capacity(profile.partitions.len());
+    for part in &profile.partitions {
+        let size_mb = match part.size {
+            SizeRule::FixedMb(mb) => Some(mb),
+            SizeRule::Layout(field) => layout_size(layout, field),
+            SizeRule::Remaining => None,
+        };
+        // Optional partitions exist only when the layout enables their field.
+        if let (true, SizeRule::Layout(field)) = (part.optional, part.size) {
+            if !layout_enabled(layout, field) {
+                continue;
+            }
+        }
+
+        templates.push(PartitionTemplate {
+            disk: disk.to_path_buf(),
+            index: (templates.len() + 1) as u8,
+            name: part.name.clone(),
+            filesystem: part.filesystem.clone(),
+            label: part.label.clone(),
+            gpt_type: part.gpt_type.clone(),
+            partlabel: Some(part.partlabel.clone().unwrap_or_else(|| part.name.clone())),
+            attributes: part.attributes.clone(),
+            installed_attrs: None,
+            size_mb,
+            start_sector: None,
+            end_sector: None,
+            partuuid: None,
+            fs_uuid: None,
+            mountpoint: part.mountpoint.clone(),
+            subvolumes: vec![],
+            encrypted: false,
+            volume_group: None,
+            mirrored: false,
+            reused: false,
+        });
+    }
+
+    // With a separate XBOOTLDR partition (or per-slot partitions) at /boot,
+    // the ESP moves to /efi as the Boot Loader Specification lays out.
+    if templates
+        .iter()
+        .any(|t| is_boot_mountpoint(&t.mountpoint) && !is_esp_type(&t.gpt_type))
+    {
+        for template in templates
+            .iter_mut()
+            .filter(|t| t.mountpoint == "/boot" && is_esp_type(&t.gpt_type))
+        {
+            template.mountpoint = "/efi".to_string();
+        }
+    }
+
+    // A dedicated partition wins over a subvolume for the same mountpoint
+    // (for example a separate /home next to a btrfs root).
+    let mountpoints = templates
+        .iter()
+        .map(|part| part.mountpoint.clone())
+        .collect::<HashSet<_>>();
+    for template in &mut templates {
+        if template.filesystem != "btrfs" {
+            continue;
+        }
+        let Some(part) = profile.partitions.iter().find(|p| p.name == template.name) else {
+            continue;
+        };
+        template.subvolumes = part
+            .subvolumes
+            .iter()
+            .filter(|sub| {
+                sub.mountpoint == template.mountpoint || !mountpoints.contains(&sub.mountpoint)
+            })
+            .cloned()
+            .collect();
+    }
+    templates
+}
+
+fn validate_subvolumes(part: &ProfilePartition) -> Result<()> {
+    if part.subvolumes.is_empty() {
+        return Ok(());
+    }
+
+    let mut names = HashSet::new();
+    let mut mountpoints = HashSet::new();
+    for sub in &part.subvolumes {
+        if !sub.name.starts_with('@') || sub.name.contains('/') || !names.insert(&sub.name) {
+            return Err(profile_error(
+                "subvolume names start with @, have no '/', and are unique",
+                format!("partition '{}' subvolume '{}'", part.name, sub.name),
+                "Name subvolumes like @, @home, or @var.",
+            ));
+        }
+        if !sub.mountpoint.starts_with('/') || !mountpoints.insert(&sub.mountpoint) {
+            return Err(profile_error(
+                "subvolume mountpoints are absolute and unique",
+                format!(
+                    "partition '{}' subvolume '{}' mountpoint '{}'",
+                    part.name, sub.name, sub.mountpoint
+                ),
+                "Give every subvolume a distinct absolute mountpoint.",
+            ));
+        }
+    }
+
+    if !mountpoints.contains(&part.mountpoint) {
+        return Err(profile_error(
+            "one subvolume is mounted at the partition mountpoint",
+            format!(
+                "partition '{}' has no subvolume at '{}'",
+                part.name, part.mountpoint
+            ),
+            "Add a subvolume (usually @) for the partition mountpoint.",
+        ));
+    }
+
+    Ok(())
+}
+
+// Points the layout's size fields at the sizes `templates` ended up with,
+// for plans that take partitions as they already are on disk.
+pub fn record_layout_sizes(
+    profile: &LayoutProfile,
+    layout: &mut ResolvedLayout,
+    templates: &[PartitionTemplate],
+) {
+    for part in &profile.partitions {
+        let SizeRule::Layout(field) = part.size else {
+            continue;
+        };
+        let Some(size_mb) = templates
+            .iter()
+            .find(|t| t.name == part.name)
+            .and_then(|t| t.size_mb)
+        else {
+            continue;
+        };
+        match field {
+            LayoutSizeField::EfiSizeMb => layout.efi_size_mb = size_mb,
+            LayoutSizeField::RootSizeMb => layout.root_size_mb = Some(size_mb),
+            LayoutSizeField::RootASizeMb => layout.root_a_size_mb = Some(size_mb),
+            LayoutSizeField::RootBSizeMb => layout.root_b_size_mb = Some(size_mb),
+            LayoutSizeField::StateSizeMb => layout.state_size_mb = Some(size_mb),
+            LayoutSizeField::SwapSizeMb => layout.swap_size_mb = Some(size_mb),
+            LayoutSizeField::HomeSizeMb => layout.home_size_mb = Some(size_mb),
+            LayoutSizeField::XbootldrSizeMb => layout.xbootldr_size_mb = Some(size_mb),
+            LayoutSizeField::RecoverySizeMb => layout.recovery_size_mb = Some(size_mb),
+            LayoutSizeField::SlotBootSizeMb => layout.slot_boot_size_mb = Some(size_mb),
+            LayoutSizeField::RootAVeritySizeMb => layout.root_a_verity_size_mb = Some(size_mb),
+            LayoutSizeField::RootBVeritySizeMb => layout.root_b_verity_size_mb = Some(size_mb),
+        }
+    }
+}
+
+fn layout_size(layout: &ResolvedLayout, field: LayoutSizeField) -> Option<u32> {
+    match field {
+        LayoutSizeField::EfiSizeMb => Some(layout.efi_size_mb),
+        LayoutSizeField::RootSizeMb => layout.root_size_mb,
+        LayoutSizeField::RootASizeMb => layout.root_a_size_mb,
+        LayoutSizeField::RootBSizeMb => layout.root_b_size_mb,
+        LayoutSizeField::StateSizeMb => layout.state_size_mb,
+        LayoutSizeField::SwapSizeMb => layout.swap_size_mb,
+        LayoutSizeField::HomeSizeMb => layout.home_size_mb,
+        LayoutSizeField::XbootldrSizeMb => layout.xbootldr_size_mb,
+        LayoutSizeField::RecoverySizeMb => layout.recovery_size_mb,
+        LayoutSizeField::SlotBootSizeMb => layout.slot_boot_size_mb,
+        LayoutSizeField::RootAVeritySizeMb => layout.root_a_verity_size_mb,
+        LayoutSizeField::RootBVeritySizeMb => layout.root_b_verity_size_mb,
+    }
+}
+
+fn layout_enabled(layout: &ResolvedLayout, field: LayoutSizeField) -> bool {
+    match field {
+        LayoutSizeField::HomeSizeMb => layout.separate_home,
+        _ => layout_size(layout, field).is_some(),
+    }
+}
+
+fn field_allowed(mode: InstallMode, field: LayoutSizeField) -> bool {
+    match field {
+        LayoutSizeField::EfiSizeMb
+        | LayoutSizeField::SwapSizeMb
+        | LayoutSizeField::XbootldrSizeMb => true,
+        LayoutSizeField::RootSizeMb | LayoutSizeField::HomeSizeMb => mode == InstallMode::Mutable,
+        LayoutSizeField::RootASizeMb
+        | LayoutSizeField::RootBSizeMb
+        | LayoutSizeField::StateSizeMb
+        | LayoutSizeField::RecoverySizeMb
+        | LayoutSizeField::SlotBootSizeMb
+        | LayoutSizeField::RootAVeritySizeMb
+        | LayoutSizeField::RootBVeritySizeMb => mode == InstallMode::Ab,
+    }
+}
+
+fn profile_error(
+    expectation: impl Into<String>,
+    observed: String,
+    remediation: &str,
+) -> RecpartError {
+    RecpartError::new(
+        ErrorCode::PlanGeneration,
+        "profile",
+        expectation,
+        observed,
+        remediation,
+    )
+}
@@ -0,0 +1,33 @@
+// Minimum filesystem sizes for shrink plans, parsed from the tools that
+// later perform the resize.
+
+// `resize2fs -P` prints the minimum in filesystem blocks; the block size
+// comes from `tune2fs -l`.
+pub fn ext4_min_size_bytes(resize2fs: &str, tune2fs: &str) -> Option<u64> {
+    let blocks = field_value(resize2fs, "Estimated minimum size of the filesystem:")?;
+    let block_size = field_value(tune2fs, "Block size:")?;
+    Some(blocks * block_size)
+}
+
+// `ntfsresize --info` reports "You might resize at N bytes or M MB ...".
+pub fn ntfs_min_size_bytes(ntfsresize: &str) -> Option<u64> {
+    ntfsresize.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("You might resize at ")?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+fn field_value(output: &str, label: &str) -> Option<u64> {
+    output.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix(label)?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    })
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const MIB_PER_GIB: u64 = 1024;
+const MIB_PER_TIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeBase {
+    Mb(u32),
+    Percent(u32),
+    Remaining,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SizeExpr {
+    pub base: Option<SizeBase>,
+    pub min_mb: Option<u32>,
+    pub max_mb: Option<u32>,
+}
+
+impl SizeExpr {
+    pub fn takes_remaining(&self) -> bool {
+        matches!(self.base, None | Some(SizeBase::Remaining))
+    }
+
+    pub fn base_mb(&self, disk_mb: u64) -> Option<u64> {
+        match self.base? {
+            SizeBase::Mb(mb) => Some(u64::from(mb)),
+            SizeBase::Percent(pct) => Some(disk_mb * u64::from(pct) / 100),
+            SizeBase::Remaining => None,
+        }
+    }
+
+    pub fn clamp(&self, value_mb: u64) -> u64 {
+        let mut value = value_mb;
+        if let Some(max) = self.max_mb {
+            value = value.min(u64::from(max));
+        }
+        if let Some(min) = self.min_mb {
+            value = value.max(u64::from(min));
+        }
+        value
+    }
+}
+
+impl FromStr for SizeExpr {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut expr = SizeExpr {
+            base: None,
+            min_mb: None,
+            max_mb: None,
+        };
+
+        for term in input.split(',').map(str::trim) {
+            if term.is_empty() {
+                return Err(format!("empty term in size expression '{input}'"));
+            }
+
+            if let Some(value) = term.strip_prefix("min:") {
+                if expr.min_mb.replace(parse_absolute_mb(value)?).is_some() {
+                    return Err(format!("min given more than once in '{input}'"));
+                }
+            } else if let Some(value) = term.strip_prefix("max:") {
+                if expr.max_mb.replace(parse_absolute_mb(value)?).is_some() {
+                    return Err(format!("max given more than once in '{input}'"));
+                }
+            } else {
+                let base = match term.strip_suffix('%') {
+                    Some(pct) => SizeBase::Percent(parse_percent(pct)?),
+                    None if term == "remaining" => SizeBase::Remaining,
+                    None => SizeBase::Mb(parse_absolute_mb(term)?),
+                };
+                if expr.base.replace(base).is_some() {
+                    return Err(format!("more than one base size in '{input}'"));
+                }
+            }
+        }
+
+        if let (Some(min), Some(max)) = (expr.min_mb, expr.max_mb) {
+            if min > max {
+                return Err(format!("min {min}M exceeds max {max}M in '{input}'"));
+            }
+        }
+
+        Ok(expr)
+    }
+}
+
+impl TryFrom<String> for SizeExpr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SizeExpr> for String {
+    fn from(value: SizeExpr) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for SizeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = Vec::new();
+        match self.base {
+            Some(SizeBase::Mb(mb)) => terms.push(format_mb(mb)),
+            Some(SizeBase::Percent(pct)) => terms.push(format!("{pct}%")),
+            Some(SizeBase::Remaining) => terms.push("remaining".to_string()),
+            None => {}
+        }
+        if let Some(min) = self.min_mb {
+            terms.push(format!("min:{}", format_mb(min)));
+        }
+        if let Some(max) = self.max_mb {
+            terms.push(format!("max:{}", format_mb(max)));
+        }
+        write!(f, "{}", terms.join(","))
+    }
+}
+
+fn format_mb(mb: u32) -> String {
+    let mb = u64::from(mb);
+    if mb % MIB_PER_TIB == 0 {
+        format!("{}T", mb / MIB_PER_TIB)
+    } else if mb % MIB_PER_GIB == 0 {
+        format!("{}G", mb / MIB_PER_GIB)
+    } else {
+        format!("{mb}M")
+    }
+}
+
+fn parse_percent(value: &str) -> Result<u32, String> {
+    let pct = value
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| format!("invalid percentage '{value}%'"))?;
+    if (1..=100).contains(&pct) {
+        Ok(pct)
+    } else {
+        Err(format!("percentage {pct}% is outside 1-100"))
+    }
+}
+
+// Units are binary like sfdisk: M, MB and MiB all mean MiB; a bare number is MiB.
+fn parse_absolute_mb(value: &str) -> Result<u32, String> {
+    let value = value.trim();
+    let digits_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits_end);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{value}'"))?;
+
+    let mb = match unit.trim().to_ascii_lowercase().as_str() {
+        "k" | "kb" | "kib" => number / 1024,
+        "" | "m" | "mb" | "mib" => number,
+        "g" | "gb" | "gib" => number.saturating_mul(MIB_PER_GIB),
+        "t" | "tb" | "tib" => number.saturating_mul(MIB_PER_TIB),
+        other => return Err(format!("unknown size unit '{other}' in '{value}'")),
+    };
+
+    if mb == 0 {
+        return Err(format!("size '{value}' is smaller than 1MiB"));
+    }
+    u32::try_from(mb).map_err(|_| format!("size '{value}' is too large"))
+}
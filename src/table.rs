@@ -0,0 +1,197 @@
+// Reads the partition table already on a disk from `sfdisk --json` output.
+use crate::error::{ErrorCode, RecpartError, Result};
+use crate::geometry::free_gaps;
+use crate::types::{
+    DiskInspectResult, DiskTarget, ExistingPartition, FilesystemProbe, Firmware,
+    InspectedPartition, PartitionTable, INSPECT_SCHEMA_VERSION,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+struct SfdiskDump {
+    partitiontable: SfdiskTable,
+}
+
+#[derive(Deserialize)]
+struct SfdiskTable {
+    label: String,
+    id: Option<String>,
+    firstlba: Option<u64>,
+    lastlba: Option<u64>,
+    #[serde(default)]
+    partitions: Vec<SfdiskPartition>,
+}
+
+#[derive(Deserialize)]
+struct SfdiskPartition {
+    node: String,
+    start: u64,
+    size: u64,
+    #[serde(rename = "type")]
+    part_type: String,
+    uuid: Option<String>,
+    name: Option<String>,
+    attrs: Option<String>,
+}
+
+pub fn parse_sfdisk_json(json: &str) -> Result<PartitionTable> {
+    let dump: SfdiskDump =
+        serde_json::from_str(json).map_err(|err| table_error(err.to_string()))?;
+    let table = dump.partitiontable;
+    if table.label != "gpt" && table.label != "dos" {
+        return Err(table_error(format!("unsupported label '{}'", table.label)));
+    }
+
+    let mut partitions = table
+        .partitions
+        .into_iter()
+        .filter(|part| part.size > 0)
+        .map(|part| {
+            let digits = part.node.len()
+                - part
+                    .node
+                    .chars()
+                    .rev()
+                    .take_while(char::is_ascii_digit)
+                    .count();
+            let index = part.node[digits..].parse::<u8>().map_err(|_| {
+                table_error(format!("partition node '{}' has no number", part.node))
+            })?;
+            Ok(ExistingPartition {
+                device: part.node,
+                index,
+                start_sector: part.start,
+                end_sector: part.start + part.size - 1,
+                gpt_type: part.part_type,
+                partuuid: part.uuid,
+                partlabel: part.name.filter(|name| !name.is_empty()),
+                attrs: part.attrs.filter(|attrs| !attrs.is_empty()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    partitions.sort_by_key(|part| part.start_sector);
+
+    Ok(PartitionTable {
+        label: table.label,
+        id: table.id,
+        first_lba: table.firstlba,
+        last_lba: table.lastlba,
+        partitions,
+    })
+}
+
+// The partition `selector` names: its device, or its PARTUUID with or
+// without a `PARTUUID=` prefix.
+pub fn find_partition<'a>(
+    table: &'a PartitionTable,
+    selector: &str,
+) -> Option<&'a ExistingPartition> {
+    let partuuid = selector.strip_prefix("PARTUUID=").unwrap_or(selector);
+    table.partitions.iter().find(|p| {
+        p.device == selector
+            || p.partuuid
+                .as_deref()
+                .is_some_and(|uuid| uuid.eq_ignore_ascii_case(partuuid))
+    })
+}
+
+// Filesystem signatures by device from `blkid -p -o export` output: one
+// KEY=value block per device, with blanks and specials backslash-escaped.
+pub fn parse_blkid_export(output: &str) -> BTreeMap<String, FilesystemProbe> {
+    let mut probes = BTreeMap::new();
+    for block in output.split("\n\n") {
+        let fields = block
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), unescape_blkid(value)))
+            .collect::<BTreeMap<_, _>>();
+        let (Some(device), Some(filesystem)) = (fields.get("DEVNAME"), fields.get("TYPE")) else {
+            continue;
+        };
+        probes.insert(
+            device.clone(),
+            FilesystemProbe {
+                filesystem: filesystem.clone(),
+                label: fields.get("LABEL").cloned(),
+                fs_uuid: fields.get("UUID").cloned(),
+            },
+        );
+    }
+    probes
+}
+
+fn unescape_blkid(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// What is on `disk` now: its partitions with their filesystems, and the
+// gaps a free-space plan could use. A disk without a table is one gap.
+pub fn inspect_table(
+    disk: DiskTarget,
+    table: Option<PartitionTable>,
+    probes: &BTreeMap<String, FilesystemProbe>,
+) -> DiskInspectResult {
+    let sector = u64::from(disk.logical_sector_bytes.max(1));
+    let table = table.unwrap_or(PartitionTable {
+        label: String::new(),
+        id: None,
+        first_lba: None,
+        last_lba: None,
+        partitions: Vec::new(),
+    });
+    let firmware = if table.label == "dos" {
+        Firmware::BiosMbr
+    } else {
+        Firmware::Uefi
+    };
+    let free_gaps = free_gaps(&table, &disk, firmware);
+    let partitions = table
+        .partitions
+        .into_iter()
+        .map(|part| {
+            let probe = probes.get(&part.device);
+            InspectedPartition {
+                disk: disk.path.clone(),
+                index: part.index,
+                gpt_type: part.gpt_type,
+                partlabel: part.partlabel,
+                attrs: part.attrs,
+                size_mb: (part.end_sector - part.start_sector + 1) * sector / (1024 * 1024),
+                start_sector: part.start_sector,
+                end_sector: part.end_sector,
+                partuuid: part.partuuid.map(|uuid| uuid.to_ascii_lowercase()),
+                filesystem: probe.map(|p| p.filesystem.clone()),
+                label: probe.and_then(|p| p.label.clone()),
+                fs_uuid: probe.and_then(|p| p.fs_uuid.clone()),
+                device: part.device,
+            }
+        })
+        .collect();
+    DiskInspectResult {
+        schema_version: INSPECT_SCHEMA_VERSION,
+        label: Some(table.label).filter(|label| !label.is_empty()),
+        label_id: table.id,
+        partitions,
+        free_gaps,
+        disk,
+    }
+}
+
+fn table_error(observed: String) -> RecpartError {
+    RecpartError::new(
+        ErrorCode::InvalidTargetDisk,
+        "table",
+        "sfdisk --json describes a gpt or dos partition table",
+        observed,
+        "Inspect 'sfdisk --json <disk>'; free-space plans need an existing gpt or dos table.",
+    )
+}
@@ -1,12 +1,15 @@
+use crate::size::SizeExpr;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::path::PathBuf;
 
-pub const PLAN_SCHEMA_VERSION: u32 = 1;
+pub const PLAN_SCHEMA_VERSION: u32 = 2;
 pub const APPLY_SCHEMA_VERSION: u32 = 1;
 pub const HANDOFF_SCHEMA_VERSION: u32 = 1;
 pub const ERROR_SCHEMA_VERSION: u32 = 1;
 pub const DISK_LIST_SCHEMA_VERSION: u32 = 1;
+pub const INSPECT_SCHEMA_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
@@ -25,12 +28,121 @@ impl fmt::Display for InstallMode {
     }
 }
 
+// Boot firmware the plan targets. BIOS targets skip the ESP role: GPT disks
+// get a BIOS boot partition for GRUB's core image, MBR disks a bootable flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Firmware {
+    #[default]
+    Uefi,
+    BiosGpt,
+    BiosMbr,
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Firmware::Uefi => write!(f, "uefi"),
+            Firmware::BiosGpt => write!(f, "bios-gpt"),
+            Firmware::BiosMbr => write!(f, "bios-mbr"),
+        }
+    }
+}
+
+// Architecture selecting the DPS root partition type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Arch {
+    #[serde(rename = "x86-64")]
+    X86_64,
+    #[serde(rename = "arm64")]
+    Arm64,
+}
+
+impl Arch {
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" => Some(Arch::X86_64),
+            "aarch64" => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arch::X86_64 => write!(f, "x86-64"),
+            Arch::Arm64 => write!(f, "arm64"),
+        }
+    }
+}
+
+// One of the two root slots of an A/B layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+impl AbSlot {
+    pub fn other(self) -> Self {
+        match self {
+            AbSlot::A => AbSlot::B,
+            AbSlot::B => AbSlot::A,
+        }
+    }
+}
+
+impl fmt::Display for AbSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbSlot::A => write!(f, "A"),
+            AbSlot::B => write!(f, "B"),
+        }
+    }
+}
+
+// GPT partition attribute bits defined by the Discoverable Partitions
+// Specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GptAttribute {
+    GrowFs,
+    ReadOnly,
+    NoAuto,
+}
+
+impl GptAttribute {
+    pub fn bit(self) -> u8 {
+        match self {
+            GptAttribute::GrowFs => 59,
+            GptAttribute::ReadOnly => 60,
+            GptAttribute::NoAuto => 63,
+        }
+    }
+}
+
+impl fmt::Display for GptAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GptAttribute::GrowFs => write!(f, "grow-fs"),
+            GptAttribute::ReadOnly => write!(f, "read-only"),
+            GptAttribute::NoAuto => write!(f, "no-auto"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DiskTarget {
     pub path: PathBuf,
     pub size_bytes: u64,
     pub logical_sector_bytes: u32,
     pub physical_sector_bytes: u32,
+    // From sysfs queue/optimal_io_size and alignment_offset; 0 when unreported.
+    #[serde(default)]
+    pub optimal_io_bytes: u32,
+    #[serde(default)]
+    pub alignment_offset_bytes: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,6 +151,10 @@ pub struct DiskInventory {
     pub size_bytes: u64,
     pub logical_sector_bytes: u32,
     pub physical_sector_bytes: u32,
+    #[serde(default)]
+    pub optimal_io_bytes: u32,
+    #[serde(default)]
+    pub alignment_offset_bytes: u32,
     pub model: String,
     pub transport: String,
     pub read_only: bool,
@@ -52,12 +168,53 @@ pub struct DiskListResult {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PartitionTemplate {
+    // Disk the partition is created on; `index` numbers partitions per disk.
+    pub disk: PathBuf,
     pub index: u8,
     pub name: String,
     pub filesystem: String,
     pub label: String,
     pub gpt_type: String,
+    // GPT partition name; unset for logical volumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partlabel: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<GptAttribute>,
+    // The attribute bits a slot reinstall found on disk, in sfdisk's form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_attrs: Option<String>,
     pub size_mb: Option<u32>,
+    // Inclusive on-disk sector range in logical sectors; unset for logical volumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_sector: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_sector: Option<u64>,
+    // Seed-derived ids: the PARTUUID on the template's disk (unset for
+    // logical volumes) and the UUID of the filesystem on template_device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partuuid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_uuid: Option<String>,
+    pub mountpoint: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subvolumes: Vec<Subvolume>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    // Set for logical volumes; `index` then numbers volumes within the group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_group: Option<String>,
+    // Set for RAID1 members; the filesystem lives on /dev/md/<name>.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mirrored: bool,
+    // An existing partition (a shared ESP) that is mounted but never
+    // partitioned or formatted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subvolume {
+    pub name: String,
     pub mountpoint: String,
 }
 
@@ -67,9 +224,230 @@ pub struct PartitionPlan {
     pub mode: InstallMode,
     pub layout_request: LayoutRequest,
     pub resolved_layout: ResolvedLayout,
+    // Empty for plans written before profiles existed; those used the
+    // built-in profile named after their mode.
+    #[serde(default)]
+    pub profile: String,
     pub disk: DiskTarget,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror_disk: Option<DiskTarget>,
+    #[serde(default)]
+    pub firmware: Firmware,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Arch>,
+    // The A/B slot mounted as sysroot; unset for mutable plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_slot: Option<AbSlot>,
+    // Additional disks that receive partitions moved off the primary disk.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_disks: Vec<DiskTarget>,
+    // Free-space plans: the gap new partitions fill and the partitions that
+    // already exist on the disk and are left untouched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_space: Option<FreeGap>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preserved_partitions: Vec<ExistingPartition>,
+    // An existing partition shrunk (in the resize phase) to make the gap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shrink: Option<PartitionShrink>,
+    // Seed every PARTUUID, disk label id and filesystem UUID derives from.
+    #[serde(default)]
+    pub uuid_seed: String,
     pub partitions: Vec<PartitionTemplate>,
+    // Script for the primary disk; `sfdisk_scripts` covers every wiped disk.
     pub sfdisk_script: String,
+    pub sfdisk_scripts: Vec<DiskScript>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskScript {
+    pub disk: PathBuf,
+    pub script: String,
+    // Run with `sfdisk --append` into the existing table instead of wiping.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub append: bool,
+}
+
+// A disk's current partition table as reported by `sfdisk --json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionTable {
+    // "gpt" or "dos".
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_lba: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_lba: Option<u64>,
+    pub partitions: Vec<ExistingPartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExistingPartition {
+    pub device: String,
+    pub index: u8,
+    pub start_sector: u64,
+    pub end_sector: u64,
+    pub gpt_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partuuid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partlabel: Option<String>,
+    // GPT attribute bits as sfdisk prints them, e.g. `RequiredPartition GUID:63`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attrs: Option<String>,
+}
+
+// Aligned unallocated sectors between (or around) existing partitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeGap {
+    pub start_sector: u64,
+    pub end_sector: u64,
+    pub size_mb: u64,
+}
+
+// Filesystem signature blkid reports on a device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesystemProbe {
+    pub filesystem: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_uuid: Option<String>,
+}
+
+// A partition found on disk, described with the fields of a
+// `PartitionTemplate` so it can be shown next to a plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InspectedPartition {
+    pub disk: PathBuf,
+    pub device: String,
+    pub index: u8,
+    pub gpt_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partlabel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attrs: Option<String>,
+    pub size_mb: u64,
+    pub start_sector: u64,
+    pub end_sector: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partuuid: Option<String>,
+    // Unset when blkid finds no signature on the partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesystem: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_uuid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskInspectResult {
+    pub schema_version: u32,
+    pub disk: DiskTarget,
+    // "gpt" or "dos"; unset when the disk has no partition table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_id: Option<String>,
+    pub partitions: Vec<InspectedPartition>,
+    pub free_gaps: Vec<FreeGap>,
+}
+
+// How far the filesystem on an existing partition can shrink, as reported
+// by `resize2fs -P` or `ntfsresize --info`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShrinkCandidate {
+    pub device: String,
+    // "ext4" or "ntfs".
+    pub filesystem: String,
+    pub min_size_bytes: u64,
+}
+
+// An existing partition and its filesystem cut down to end at
+// `new_end_sector`; its start never moves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionShrink {
+    pub device: String,
+    pub index: u8,
+    pub filesystem: String,
+    pub start_sector: u64,
+    pub end_sector: u64,
+    pub new_end_sector: u64,
+    pub min_size_bytes: u64,
+    pub new_size_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutProfile {
+    pub name: String,
+    pub mode: InstallMode,
+    pub partitions: Vec<ProfilePartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfilePartition {
+    pub name: String,
+    pub filesystem: String,
+    pub label: String,
+    pub gpt_type: String,
+    // GPT partition name; defaults to the partition name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partlabel: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<GptAttribute>,
+    pub size: SizeRule,
+    pub mountpoint: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    // Only used when the partition is formatted as btrfs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subvolumes: Vec<Subvolume>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeRule {
+    FixedMb(u32),
+    Layout(LayoutSizeField),
+    Remaining,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutSizeField {
+    EfiSizeMb,
+    RootSizeMb,
+    RootASizeMb,
+    RootBSizeMb,
+    StateSizeMb,
+    SwapSizeMb,
+    HomeSizeMb,
+    XbootldrSizeMb,
+    RecoverySizeMb,
+    SlotBootSizeMb,
+    RootAVeritySizeMb,
+    RootBVeritySizeMb,
+}
+
+impl fmt::Display for LayoutSizeField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutSizeField::EfiSizeMb => write!(f, "efi_size_mb"),
+            LayoutSizeField::RootSizeMb => write!(f, "root_size_mb"),
+            LayoutSizeField::RootASizeMb => write!(f, "root_a_size_mb"),
+            LayoutSizeField::RootBSizeMb => write!(f, "root_b_size_mb"),
+            LayoutSizeField::StateSizeMb => write!(f, "state_size_mb"),
+            LayoutSizeField::SwapSizeMb => write!(f, "swap_size_mb"),
+            LayoutSizeField::HomeSizeMb => write!(f, "home_size_mb"),
+            LayoutSizeField::XbootldrSizeMb => write!(f, "xbootldr_size_mb"),
+            LayoutSizeField::RecoverySizeMb => write!(f, "recovery_size_mb"),
+            LayoutSizeField::SlotBootSizeMb => write!(f, "slot_boot_size_mb"),
+            LayoutSizeField::RootAVeritySizeMb => write!(f, "root_a_verity_size_mb"),
+            LayoutSizeField::RootBVeritySizeMb => write!(f, "root_b_verity_size_mb"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -79,6 +457,85 @@ pub struct LayoutRequest {
     pub root_a_size_mb: Option<u32>,
     pub root_b_size_mb: Option<u32>,
     pub state_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub efi_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_a_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_b_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap_hibernate_memory_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home_size: Option<SizeExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xbootldr_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xbootldr_size: Option<SizeExpr>,
+    // Add the ab RECOVERY partition; either size field implies it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recovery: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_size: Option<SizeExpr>,
+    // Give each ab slot its own /boot; either size field implies it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub slot_boot: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot_boot_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot_boot_size: Option<SizeExpr>,
+    // Reserve a dm-verity hash partition next to each ab root slot.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verity: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub filesystems: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encrypt: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lvm_volume_group: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partition_disks: BTreeMap<String, PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<Firmware>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Arch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid_seed: Option<String>,
+    // Keep the existing partition table and place new partitions in free
+    // space: the largest gap, or the gap containing `free_space_start_sector`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub use_free_space: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_space_start_sector: Option<u64>,
+    // Existing ESP to share instead of creating one, by partition device or
+    // PARTUUID. Only for free-space plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reuse_esp: Option<String>,
+    // Existing ext4/NTFS partition (device or PARTUUID) to shrink so the
+    // layout fits behind it, and its new size; by default it shrinks just
+    // enough for the layout's minimum.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shrink_partition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shrink_size_mb: Option<u32>,
+    // A/B slot to install into (default A); the other slot is marked no-auto.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_slot: Option<AbSlot>,
+    // Reformat only this slot of an existing A/B install, keeping the ESP,
+    // the other slot and STATE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reinstall_slot: Option<AbSlot>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -89,6 +546,24 @@ pub struct ResolvedLayout {
     pub root_a_size_mb: Option<u32>,
     pub root_b_size_mb: Option<u32>,
     pub state_size_mb: Option<u32>,
+    pub swap_size_mb: Option<u32>,
+    #[serde(default)]
+    pub separate_home: bool,
+    pub home_size_mb: Option<u32>,
+    // Set when a Boot Loader Specification XBOOTLDR partition holds /boot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xbootldr_size_mb: Option<u32>,
+    // Set when the ab layout has a RECOVERY partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_size_mb: Option<u32>,
+    // Size of each of BOOT_A and BOOT_B when the ab slots have their own /boot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot_boot_size_mb: Option<u32>,
+    // Verity hash partition sizes, derived from the slot sizes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_a_verity_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_b_verity_size_mb: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -96,12 +571,25 @@ pub struct ApplyOptions {
     pub dry_run: bool,
     pub confirm_token: Option<String>,
     pub mount_root: PathBuf,
+    pub luks_key: Option<LuksKey>,
+}
+
+// Passphrase or keyfile bytes handed to cryptsetup on stdin; never printed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LuksKey(pub Vec<u8>);
+
+impl fmt::Debug for LuksKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LuksKey(<redacted>)")
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MountMapping {
     pub path: String,
     pub device: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -110,19 +598,77 @@ pub struct ModeContext {
     pub inactive_slot_hint: Option<String>,
     pub slot_a_device: Option<String>,
     pub slot_b_device: Option<String>,
+    // Devices of the install target slot and the other slot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_target_device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inactive_slot_device: Option<String>,
+    // The ab RECOVERY partition, which recab installs the recovery system to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_device: Option<String>,
+    // Per-slot /boot partitions, paired with slot_a_device and slot_b_device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_a_device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_b_device: Option<String>,
+    // Unformatted dm-verity hash partitions for recguard, one per slot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_a_verity_device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_b_verity_device: Option<String>,
     pub notes: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LuksVolume {
+    pub partition: String,
+    pub device: String,
+    pub mapper_name: String,
+    pub mapper_device: String,
+    pub uuid: Option<String>,
+    pub crypttab: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaidArray {
+    pub partition: String,
+    pub device: String,
+    pub level: u8,
+    pub members: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HandoffPayload {
     pub schema_version: u32,
     pub mode: InstallMode,
     pub install_target: String,
     pub mount_map: Vec<MountMapping>,
+    #[serde(default)]
+    pub swap_devices: Vec<String>,
+    #[serde(default)]
+    pub luks_volumes: Vec<LuksVolume>,
+    #[serde(default)]
+    pub raid_arrays: Vec<RaidArray>,
+    #[serde(default)]
+    pub bootloader: BootloaderTarget,
     pub next_commands: Vec<String>,
     pub mode_context: ModeContext,
 }
 
+// What downstream tools install: EFI binaries into `efi_directory`, or
+// BIOS boot code onto each of `boot_disks`. Kernels and loader entries go to
+// `xbootldr_directory` when the plan has an XBOOTLDR partition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct BootloaderTarget {
+    pub firmware: Firmware,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub efi_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xbootldr_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boot_disks: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CommandRecord {
     pub phase: String,
@@ -146,6 +692,9 @@ pub struct ApplyResult {
     pub steps: Vec<CommandRecord>,
     pub partition_map: Vec<PartitionTemplate>,
     pub formatted_devices: Vec<String>,
+    // Existing filesystems mounted as they are (a shared ESP).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reused_devices: Vec<String>,
     pub mounted: Vec<MountMapping>,
     pub handoff: HandoffPayload,
     pub warnings: Vec<String>,
@@ -0,0 +1,117 @@
+// Stable identifiers derived from a plan's uuid_seed, so planning again with
+// the same seed yields the same PARTUUIDs and filesystem UUIDs. FNV-1a is
+// fixed by its specification (unlike std's DefaultHasher); the ids only
+// need to be distinct, not unguessable.
+use crate::error::{ErrorCode, RecpartError, Result};
+use crate::filesystem::FilesystemSpec;
+use crate::types::Firmware;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const FNV_OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+fn fnv1a(fields: &[&str]) -> u128 {
+    let mut hash = FNV_OFFSET;
+    for field in fields {
+        for byte in field.bytes().chain([0]) {
+            hash ^= u128::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    // FNV barely moves the high bits for trailing bytes, so ids for similar
+    // names would share most digits. The splitmix64 finalizer is bijective
+    // and spreads every input bit across both halves.
+    let mut low = hash as u64;
+    let mut high = (hash >> 64) as u64 ^ mix64(low);
+    low ^= mix64(high);
+    high ^= mix64(low);
+    (u128::from(high) << 64) | u128::from(low)
+}
+
+fn mix64(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+// Seed recorded when the caller does not pass one: 128 random bits, so
+// machines sharing a disk model and profile still get distinct ids.
+pub fn default_seed() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .map_err(|err| {
+            RecpartError::new(
+                ErrorCode::Internal,
+                "plan",
+                "/dev/urandom supplies a uuid seed",
+                err.to_string(),
+                "Pass --uuid-seed <SEED>, e.g. the target's machine id.",
+            )
+        })?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+// An RFC 9562 version 8 (custom) UUID.
+pub fn derive_uuid(seed: &str, purpose: &str, name: &str) -> String {
+    let mut value = fnv1a(&[seed, purpose, name]);
+    value = (value & !(0xf << 76)) | (0x8 << 76);
+    value = (value & !(0x3 << 62)) | (0x2 << 62);
+    let hex = format!("{value:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn derive_u32(seed: &str, purpose: &str, name: &str) -> u32 {
+    (fnv1a(&[seed, purpose, name]) >> 96) as u32
+}
+
+// GPT disk GUID, or the 32-bit MBR disk identifier as sfdisk writes it.
+pub fn disk_label_id(seed: &str, firmware: Firmware, disk: &Path) -> String {
+    let name = disk.to_string_lossy();
+    match firmware {
+        Firmware::BiosMbr => format!("0x{:08x}", derive_u32(seed, "disk", &name)),
+        Firmware::Uefi | Firmware::BiosGpt => derive_uuid(seed, "disk", &name),
+    }
+}
+
+// MBR partitions have no PARTUUID of their own; the kernel reports the disk
+// identifier followed by the partition number.
+pub fn partition_uuid(
+    seed: &str,
+    firmware: Firmware,
+    disk: &Path,
+    index: u8,
+    name: &str,
+) -> String {
+    let disk_name = disk.to_string_lossy();
+    match firmware {
+        Firmware::BiosMbr => mbr_partition_uuid(derive_u32(seed, "disk", &disk_name), index),
+        Firmware::Uefi | Firmware::BiosGpt => {
+            derive_uuid(seed, "partition", &format!("{disk_name}:{name}"))
+        }
+    }
+}
+
+pub fn mbr_partition_uuid(disk_id: u32, index: u8) -> String {
+    format!("{disk_id:08x}-{index:02x}")
+}
+
+// Filesystem UUID for the filesystem created on `device`, in the form blkid
+// reports it. vfat only carries a 32-bit volume ID ("ABCD-1234").
+pub fn filesystem_uuid(seed: &str, spec: &FilesystemSpec, device: &str) -> String {
+    if spec.volume_id {
+        let id = derive_u32(seed, "filesystem", device);
+        format!("{:04X}-{:04X}", id >> 16, id & 0xffff)
+    } else {
+        derive_uuid(seed, "filesystem", device)
+    }
+}
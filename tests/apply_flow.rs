@@ -9,6 +9,8 @@ fn fake_disk() -> DiskTarget {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
@@ -21,6 +23,7 @@ fn dry_run_apply_order_is_partition_then_format_then_mount() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("dry-run apply");
@@ -56,6 +59,7 @@ fn dry_run_partition_sequence_includes_wipefs_and_udevadm() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("dry-run apply");
@@ -0,0 +1,156 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::execute_plan;
+use recpart::types::{ApplyOptions, DiskTarget, InstallMode, LayoutRequest};
+use recpart::SizeExpr;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn dry_run_opts() -> ApplyOptions {
+    ApplyOptions {
+        dry_run: true,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: None,
+    }
+}
+
+fn btrfs(partition: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([(partition.to_string(), "btrfs".to_string())])
+}
+
+#[test]
+fn mutable_btrfs_root_creates_and_mounts_subvolumes() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            filesystems: btrfs("root"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let root = plan.partitions.iter().find(|p| p.name == "root").unwrap();
+    assert_eq!(root.filesystem, "btrfs");
+    let names = root
+        .subvolumes
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["@", "@home", "@var", "@snapshots"]);
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let commands = result
+        .steps
+        .iter()
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+    let mkfs = format!(
+        "mkfs.btrfs -f -L ROOT -U {} /dev/vda2",
+        root.fs_uuid.as_deref().unwrap()
+    );
+    assert!(commands.contains(&mkfs.as_str()));
+    assert!(commands.contains(&"mount /dev/vda2 /mnt/.btrfs-root"));
+    assert!(commands.contains(&"btrfs subvolume create /mnt/.btrfs-root/@snapshots"));
+    assert!(commands.contains(&"umount /mnt/.btrfs-root"));
+    assert!(commands.contains(&"mount -o subvol=@ /dev/vda2 /mnt/sysroot"));
+    assert!(commands.contains(&"mount -o subvol=@home /dev/vda2 /mnt/sysroot/home"));
+
+    assert_eq!(result.mounted[0].path, "/mnt/sysroot");
+    assert_eq!(result.mounted[0].options.as_deref(), Some("subvol=@"));
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/.snapshots"
+            && m.options.as_deref() == Some("subvol=@snapshots")));
+    assert_eq!(result.handoff.mount_map, result.mounted);
+}
+
+#[test]
+fn ab_btrfs_state_keeps_subvolumes_under_state() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            filesystems: btrfs("state"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let mounts = result
+        .mounted
+        .iter()
+        .map(|m| (m.path.as_str(), m.options.as_deref()))
+        .collect::<Vec<_>>();
+    assert!(mounts.contains(&("/mnt/sysroot/state", Some("subvol=@"))));
+    assert!(mounts.contains(&("/mnt/sysroot/state/var", Some("subvol=@var"))));
+    assert_eq!(result.mounted[0].path, "/mnt/sysroot");
+    assert_eq!(result.mounted[0].options, None);
+}
+
+#[test]
+fn separate_home_partition_replaces_home_subvolume() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            filesystems: btrfs("root"),
+            home_size: Some("remaining".parse::<SizeExpr>().unwrap()),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let root = plan.partitions.iter().find(|p| p.name == "root").unwrap();
+    assert!(root.subvolumes.iter().all(|s| s.name != "@home"));
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let home = result
+        .mounted
+        .iter()
+        .filter(|m| m.path == "/mnt/sysroot/home")
+        .collect::<Vec<_>>();
+    assert_eq!(home.len(), 1);
+    assert_eq!(home[0].device, "/dev/vda3");
+}
+
+#[test]
+fn ext4_plans_have_no_subvolumes() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, LayoutRequest::default())
+            .expect("plan");
+    assert!(plan.partitions.iter().all(|p| p.subvolumes.is_empty()));
+
+    let json = serde_json::to_value(&plan).expect("json");
+    assert!(json["partitions"][1].get("subvolumes").is_none());
+}
+
+#[test]
+fn filesystem_overrides_are_limited_to_data_partitions() {
+    for (partition, filesystem) in [("efi", "btrfs"), ("root", "ntfs"), ("nope", "btrfs")] {
+        let err = build_plan_with_layout_request(
+            fake_disk(),
+            InstallMode::Mutable,
+            LayoutRequest {
+                filesystems: BTreeMap::from([(partition.to_string(), filesystem.to_string())]),
+                ..LayoutRequest::default()
+            },
+        )
+        .expect_err("override must fail");
+        assert_eq!(err.code.code(), "E003", "{partition}={filesystem}");
+    }
+}
@@ -49,3 +49,83 @@ fn list_disks_accepts_json_flag() {
         _ => panic!("expected list-disks command"),
     }
 }
+
+#[test]
+fn plan_accepts_size_expression_flags() {
+    let cli = Cli::parse_from([
+        "recpart",
+        "plan",
+        "--disk",
+        "/dev/vda",
+        "--root-a-size",
+        "20G",
+        "--state-size",
+        "min:8G,max:40G",
+    ]);
+
+    match cli.command {
+        Commands::Plan(args) => {
+            assert_eq!(args.layout.root_a_size.as_deref(), Some("20G"));
+            assert_eq!(args.layout.state_size.as_deref(), Some("min:8G,max:40G"));
+        }
+        _ => panic!("expected plan command"),
+    }
+}
+
+#[test]
+fn plan_accepts_repeated_filesystem_overrides() {
+    let cli = Cli::parse_from([
+        "recpart",
+        "plan",
+        "--disk",
+        "/dev/vda",
+        "--filesystem",
+        "state=btrfs",
+        "--filesystem",
+        "root_a=ext4",
+    ]);
+
+    match cli.command {
+        Commands::Plan(args) => {
+            assert_eq!(args.layout.filesystems, vec!["state=btrfs", "root_a=ext4"]);
+        }
+        _ => panic!("expected plan command"),
+    }
+}
+
+#[test]
+fn inspect_takes_a_disk_and_json_flag() {
+    let cli = Cli::parse_from(["recpart", "inspect", "--disk", "/dev/vda", "--json"]);
+    assert!(cli.json_requested());
+    match cli.command {
+        Commands::Inspect(args) => assert_eq!(args.disk, std::path::PathBuf::from("/dev/vda")),
+        _ => panic!("expected inspect command"),
+    }
+}
+
+#[test]
+fn slot_flags_accept_either_case() {
+    let cli = Cli::parse_from([
+        "recpart",
+        "apply",
+        "--disk",
+        "/dev/vda",
+        "--reinstall-slot",
+        "b",
+        "--install-slot",
+        "B",
+    ]);
+    match cli.command {
+        Commands::Apply(args) => {
+            assert!(matches!(
+                args.layout.reinstall_slot,
+                Some(recpart::cli::SlotArg::B)
+            ));
+            assert!(matches!(
+                args.layout.install_slot,
+                Some(recpart::cli::SlotArg::B)
+            ));
+        }
+        _ => panic!("expected apply command"),
+    }
+}
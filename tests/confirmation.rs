@@ -11,6 +11,8 @@ fn apply_requires_confirmation_token_when_not_dry_run() {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     };
     let plan = build_plan(disk, InstallMode::Mutable).expect("plan");
 
@@ -20,6 +22,7 @@ fn apply_requires_confirmation_token_when_not_dry_run() {
             dry_run: false,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect_err("missing confirmation should fail");
@@ -0,0 +1,149 @@
+use recpart::build_plan_with_layout_request;
+use recpart::build_plan_with_profile;
+use recpart::dps;
+use recpart::profile::{parse_profile, ProfileFormat};
+use recpart::types::{Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn types(request: LayoutRequest, mode: InstallMode) -> Vec<(String, String)> {
+    build_plan_with_layout_request(fake_disk(), mode, request)
+        .expect("plan")
+        .partitions
+        .into_iter()
+        .map(|p| (p.name, p.gpt_type))
+        .collect()
+}
+
+fn pair(name: &str, gpt_type: &str) -> (String, String) {
+    (name.to_string(), gpt_type.to_string())
+}
+
+#[test]
+fn ab_slots_use_the_requested_architecture_root_type() {
+    let arm64 = types(
+        LayoutRequest {
+            arch: Some(Arch::Arm64),
+            swap_size_mb: Some(2048),
+            ..LayoutRequest::default()
+        },
+        InstallMode::Ab,
+    );
+    assert_eq!(
+        arm64,
+        vec![
+            pair("efi", dps::ESP),
+            pair("root_a", "B921B045-1DF0-41C3-AF44-4C6F280D3FAE"),
+            pair("root_b", "B921B045-1DF0-41C3-AF44-4C6F280D3FAE"),
+            pair("swap", dps::SWAP),
+            pair("state", dps::LINUX_DATA),
+        ]
+    );
+
+    let x86 = types(
+        LayoutRequest {
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+        InstallMode::Mutable,
+    );
+    assert_eq!(x86[1], pair("root", "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709"));
+}
+
+#[test]
+fn arch_defaults_to_the_host() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, LayoutRequest::default())
+            .expect("plan");
+    assert_eq!(plan.arch, Arch::host());
+}
+
+#[test]
+fn separate_boot_and_var_get_their_dps_types() {
+    let profile = parse_profile(
+        r#"
+name = "split"
+mode = "mutable"
+
+[[partitions]]
+name = "esp"
+filesystem = "vfat"
+label = "ESP"
+gpt_type = "U"
+size = { fixed_mb = 512 }
+mountpoint = "/efi"
+
+[[partitions]]
+name = "boot"
+filesystem = "ext4"
+label = "BOOT"
+gpt_type = "L"
+size = { fixed_mb = 1024 }
+mountpoint = "/boot"
+
+[[partitions]]
+name = "var"
+filesystem = "ext4"
+label = "VAR"
+gpt_type = "L"
+size = { fixed_mb = 8192 }
+mountpoint = "/var"
+
+[[partitions]]
+name = "root"
+filesystem = "ext4"
+label = "ROOT"
+gpt_type = "L"
+size = "remaining"
+mountpoint = "/"
+"#,
+        ProfileFormat::Toml,
+    )
+    .expect("profile");
+    let plan = build_plan_with_profile(
+        fake_disk(),
+        &profile,
+        LayoutRequest {
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+    let types = plan
+        .partitions
+        .iter()
+        .map(|p| p.gpt_type.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            dps::ESP,
+            dps::XBOOTLDR,
+            dps::VAR,
+            dps::root_type(Arch::X86_64)
+        ]
+    );
+}
+
+#[test]
+fn mbr_plans_keep_sfdisk_shortcuts() {
+    let mbr = types(
+        LayoutRequest {
+            firmware: Some(Firmware::BiosMbr),
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+        InstallMode::Mutable,
+    );
+    assert_eq!(mbr, vec![pair("efi", "c"), pair("root", "L")]);
+}
@@ -36,7 +36,7 @@ fn e2e_plan_on_real_disk_json_contract() {
     let stdout = String::from_utf8_lossy(&output.stdout);
     let payload = parse_json(&stdout);
 
-    assert_eq!(payload["schema_version"], 1);
+    assert_eq!(payload["schema_version"], 2);
     assert_eq!(payload["mode"], "ab");
     assert_eq!(payload["disk"]["path"], disk);
     assert_eq!(payload["partitions"].as_array().map(|v| v.len()), Some(4));
@@ -25,4 +25,5 @@ fn exit_codes_remain_stable() {
     assert_eq!(ErrorCode::MissingTool.exit_code(), 2);
     assert_eq!(ErrorCode::PlanGeneration.exit_code(), 3);
     assert_eq!(ErrorCode::MissingConfirmation.exit_code(), 4);
+    assert_eq!(ErrorCode::MissingLuksKey.exit_code(), 14);
 }
@@ -0,0 +1,168 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::filesystem::filesystem_spec;
+use recpart::preflight::required_tools;
+use recpart::profile::{parse_profile, ProfileFormat};
+use recpart::types::{ApplyOptions, DiskTarget, InstallMode, LayoutRequest};
+use recpart::{build_plan, build_plan_with_layout_request, build_plan_with_profile, execute_plan};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn profile_with(filesystem: &str, label: &str, size: &str) -> String {
+    format!(
+        r#"
+name = "custom"
+mode = "mutable"
+
+[[partitions]]
+name = "efi"
+filesystem = "vfat"
+label = "EFI"
+gpt_type = "U"
+size = {{ fixed_mb = 512 }}
+mountpoint = "/boot"
+
+[[partitions]]
+name = "data"
+filesystem = "{filesystem}"
+label = "{label}"
+gpt_type = "L"
+size = {size}
+mountpoint = "/srv"
+
+[[partitions]]
+name = "root"
+filesystem = "ext4"
+label = "ROOT"
+gpt_type = "L"
+size = "remaining"
+mountpoint = "/"
+"#
+    )
+}
+
+#[test]
+fn format_steps_follow_the_partition_filesystem() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            filesystems: BTreeMap::from([
+                ("root_a".to_string(), "xfs".to_string()),
+                ("state".to_string(), "f2fs".to_string()),
+            ]),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    let formats = result
+        .steps
+        .iter()
+        .filter(|s| s.phase == "format")
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+    let uuids = plan
+        .partitions
+        .iter()
+        .map(|p| p.fs_uuid.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        formats,
+        vec![
+            format!(
+                "mkfs.vfat -F 32 -n EFI -i {} /dev/vda1",
+                uuids[0].replace('-', "")
+            ),
+            format!("mkfs.xfs -f -L ROOT_A -m uuid={} /dev/vda2", uuids[1]),
+            format!("mkfs.ext4 -F -L ROOT_B -U {} /dev/vda3", uuids[2]),
+            format!("mkfs.f2fs -f -l STATE -U {} /dev/vda4", uuids[3]),
+        ]
+    );
+}
+
+#[test]
+fn preflight_requires_only_the_mkfs_tools_in_use() {
+    let plan = build_plan(fake_disk(), InstallMode::Mutable).expect("plan");
+    let tools = required_tools(&plan);
+    assert!(tools.contains(&"mkfs.vfat"));
+    assert!(tools.contains(&"mkfs.ext4"));
+    assert!(tools.contains(&"wipefs"));
+    for unused in ["mkfs.xfs", "mkfs.f2fs", "mkfs.btrfs", "mkswap", "btrfs"] {
+        assert!(!tools.contains(&unused), "{unused} should not be required");
+    }
+
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            filesystems: BTreeMap::from([("root".to_string(), "btrfs".to_string())]),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+    let tools = required_tools(&plan);
+    assert!(tools.contains(&"mkfs.btrfs"));
+    assert!(tools.contains(&"btrfs"));
+    assert!(!tools.contains(&"mkfs.ext4"));
+}
+
+#[test]
+fn xfs_labels_are_limited_to_twelve_bytes() {
+    assert_eq!(filesystem_spec("xfs").unwrap().max_label_bytes, 12);
+    let ok = parse_profile(
+        &profile_with("f2fs", "SERVICE_DATA", "{ fixed_mb = 1024 }"),
+        ProfileFormat::Toml,
+    )
+    .expect("profile");
+    build_plan_with_profile(fake_disk(), &ok, LayoutRequest::default()).expect("f2fs plan");
+
+    let long = parse_profile(
+        &profile_with("xfs", "SERVICE_DATA1", "{ fixed_mb = 1024 }"),
+        ProfileFormat::Toml,
+    )
+    .expect("profile");
+    let err = build_plan_with_profile(fake_disk(), &long, LayoutRequest::default())
+        .expect_err("label too long for xfs");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn partitions_below_the_filesystem_minimum_are_rejected() {
+    let small = parse_profile(
+        &profile_with("xfs", "DATA", "{ fixed_mb = 256 }"),
+        ProfileFormat::Toml,
+    )
+    .expect("profile");
+    let err = build_plan_with_profile(fake_disk(), &small, LayoutRequest::default())
+        .expect_err("xfs needs 300MB");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.observed.contains("256MB"));
+
+    let ext4 = parse_profile(
+        &profile_with("ext4", "DATA", "{ fixed_mb = 256 }"),
+        ProfileFormat::Toml,
+    )
+    .expect("profile");
+    build_plan_with_profile(fake_disk(), &ext4, LayoutRequest::default()).expect("ext4 plan");
+}
@@ -0,0 +1,144 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_plan_with_layout_request, PartitionPlan};
+use std::path::PathBuf;
+
+fn disk(gib: u64) -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: gib * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn firmware_plan(mode: InstallMode, firmware: Firmware) -> PartitionPlan {
+    build_plan_with_layout_request(
+        disk(250),
+        mode,
+        LayoutRequest {
+            firmware: Some(firmware),
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan")
+}
+
+fn dry_run(plan: &PartitionPlan) -> recpart::ApplyResult {
+    execute_plan(
+        plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run")
+}
+
+// The sfdisk script without its seed-derived label-id and uuid= fields.
+fn layout_only(script: &str) -> String {
+    script
+        .lines()
+        .filter(|line| !line.starts_with("label-id:"))
+        .map(|line| {
+            line.split(", ")
+                .filter(|field| !field.starts_with("uuid="))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+#[test]
+fn uefi_is_the_default_firmware() {
+    let plan = build_plan_with_layout_request(disk(250), InstallMode::Ab, LayoutRequest::default())
+        .expect("plan");
+    assert_eq!(plan.firmware, Firmware::Uefi);
+
+    let handoff = dry_run(&plan).handoff;
+    assert_eq!(handoff.bootloader.firmware, Firmware::Uefi);
+    assert_eq!(handoff.bootloader.efi_directory.as_deref(), Some("/boot"));
+    assert!(handoff.bootloader.boot_disks.is_empty());
+}
+
+#[test]
+fn bios_gpt_adds_a_bios_boot_partition_first() {
+    let plan = firmware_plan(InstallMode::Mutable, Firmware::BiosGpt);
+    assert_eq!(plan.partitions[0].name, "bios_boot");
+    assert_eq!(plan.partitions[0].size_mb, Some(1));
+    assert_eq!(
+        layout_only(&plan.sfdisk_script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=2048, type=21686148-6449-6E6F-744E-656564454649, name=\"bios_boot\"\nstart=4096, size=2097152, type={}, name=\"efi\"\nstart=2101248, size=522186719, type={}, name=\"root\"\n",
+            dps::XBOOTLDR,
+            dps::root_type(Arch::X86_64)
+        )
+    );
+
+    let result = dry_run(&plan);
+    assert!(!result.formatted_devices.contains(&"/dev/vda1".to_string()));
+    assert!(result
+        .steps
+        .iter()
+        .any(|s| s.command.starts_with("mkfs.vfat -F 32 -n EFI -i ")
+            && s.command.ends_with(" /dev/vda2")));
+    assert_eq!(result.handoff.bootloader.boot_disks, vec!["/dev/vda"]);
+    assert_eq!(result.handoff.bootloader.efi_directory, None);
+    assert!(result
+        .handoff
+        .next_commands
+        .iter()
+        .any(|c| c.contains("grub-install --target=i386-pc") && c.ends_with("/dev/vda")));
+}
+
+#[test]
+fn bios_mbr_uses_a_dos_label_with_a_bootable_boot_partition() {
+    let plan = firmware_plan(InstallMode::Ab, Firmware::BiosMbr);
+    assert_eq!(
+        layout_only(&plan.sfdisk_script),
+        "label: dos\nunit: sectors\nstart=2048, size=2097152, type=c, bootable\nstart=2099200, size=41943040, type=L\nstart=44042240, size=41943040, type=L\nstart=85985280, size=438302720, type=L\n"
+    );
+    assert_eq!(
+        dry_run(&plan).handoff.bootloader.firmware,
+        Firmware::BiosMbr
+    );
+}
+
+#[test]
+fn bios_mbr_rejects_more_than_four_primary_partitions() {
+    let err = build_plan_with_layout_request(
+        disk(250),
+        InstallMode::Ab,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosMbr),
+            swap_size_mb: Some(4096),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("five partitions");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.expectation.contains("4 primary partitions"));
+}
+
+#[test]
+fn bios_mbr_rejects_disks_over_two_tib() {
+    let err = build_plan_with_layout_request(
+        disk(3 * 1024),
+        InstallMode::Mutable,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosMbr),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("disk too large for MBR");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.remediation.contains("bios-gpt"));
+}
@@ -0,0 +1,215 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::preflight::required_tools;
+use recpart::profile::builtin_profile;
+use recpart::table::parse_sfdisk_json;
+use recpart::types::{
+    ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest, PartitionTable,
+};
+use recpart::{build_free_space_plan, build_plan_with_layout_request, execute_plan};
+use std::path::PathBuf;
+
+// A Windows install: ESP, MSR, C: and a recovery partition at the end of the
+// disk, with ~150GiB unallocated after C:.
+const WINDOWS_GPT: &str = r#"{
+   "partitiontable": {
+      "label": "gpt",
+      "id": "9A1F52E4-1B43-4E26-A2E4-3C1D8C0B7F10",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "firstlba": 34,
+      "lastlba": 524287966,
+      "sectorsize": 512,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 204800, "type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "uuid": "4B1D2E6A-0C5F-4F8E-9B7A-2D6C1E3F4A5B", "name": "EFI system partition"},
+         {"node": "/dev/vda2", "start": 206848, "size": 32768, "type": "E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "uuid": "5C2E3F7B-1D60-4A9F-8C8B-3E7D2F405B6C", "name": "Microsoft reserved partition"},
+         {"node": "/dev/vda3", "start": 239616, "size": 209715200, "type": "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7", "uuid": "6D3F4A8C-2E71-4BA0-9D9C-4F8E3A516C7D", "name": "Basic data partition"},
+         {"node": "/dev/vda4", "start": 523239424, "size": 1046528, "type": "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC", "uuid": "7E4A5B9D-3F82-4CB1-AEAD-5A9F4B627D8E", "attrs": "RequiredPartition GUID:63"}
+      ]
+   }
+}"#;
+
+const WINDOWS_MBR: &str = r#"{
+   "partitiontable": {
+      "label": "dos",
+      "id": "0x1234abcd",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "sectorsize": 512,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 1124352, "type": "7", "bootable": true},
+         {"node": "/dev/vda2", "start": 1126400, "size": 209715200, "type": "7"}
+      ]
+   }
+}"#;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn table(json: &str) -> PartitionTable {
+    parse_sfdisk_json(json).expect("sfdisk json")
+}
+
+fn request(firmware: Firmware) -> LayoutRequest {
+    LayoutRequest {
+        firmware: Some(firmware),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn free_space_plan(
+    json: &str,
+    mode: InstallMode,
+    request: LayoutRequest,
+) -> recpart::Result<recpart::PartitionPlan> {
+    build_free_space_plan(
+        fake_disk(),
+        table(json),
+        &builtin_profile(mode).expect("profile"),
+        request,
+    )
+}
+
+#[test]
+fn sfdisk_json_becomes_existing_partitions() {
+    let table = table(WINDOWS_GPT);
+    assert_eq!(table.label, "gpt");
+    assert_eq!(table.last_lba, Some(524287966));
+    assert_eq!(table.partitions.len(), 4);
+    let c_drive = &table.partitions[2];
+    assert_eq!(c_drive.device, "/dev/vda3");
+    assert_eq!(c_drive.index, 3);
+    assert_eq!(c_drive.end_sector, 239616 + 209715200 - 1);
+    assert_eq!(c_drive.partlabel.as_deref(), Some("Basic data partition"));
+    assert_eq!(table.partitions[3].partlabel, None);
+}
+
+#[test]
+fn new_partitions_fill_the_largest_gap_and_keep_existing_ones() {
+    let plan =
+        free_space_plan(WINDOWS_GPT, InstallMode::Ab, request(Firmware::Uefi)).expect("plan");
+    assert!(plan.layout_request.use_free_space);
+    assert_eq!(plan.preserved_partitions, table(WINDOWS_GPT).partitions);
+
+    let gap = plan.free_space.expect("gap");
+    assert_eq!(gap.start_sector, 209954816);
+    assert_eq!(gap.end_sector, 523239423);
+
+    let indexes = plan.partitions.iter().map(|p| p.index).collect::<Vec<_>>();
+    assert_eq!(indexes, vec![5, 6, 7, 8]);
+    assert_eq!(plan.partitions[0].start_sector, Some(gap.start_sector));
+    assert_eq!(plan.partitions[3].end_sector, Some(gap.end_sector));
+
+    let script = &plan.sfdisk_scripts[0];
+    assert!(script.append);
+    assert!(!script.script.contains("label:"));
+    assert!(script
+        .script
+        .starts_with("unit: sectors\n/dev/vda5 : start=209954816, "));
+    assert!(!script.script.contains("bootable"));
+}
+
+#[test]
+fn apply_appends_without_wiping_the_disk() {
+    let plan =
+        free_space_plan(WINDOWS_GPT, InstallMode::Mutable, request(Firmware::Uefi)).expect("plan");
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    let commands = result
+        .steps
+        .iter()
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+    assert!(commands.iter().all(|c| !c.starts_with("wipefs")));
+    assert!(!required_tools(&plan).contains(&"wipefs"));
+    assert!(commands.contains(&"sfdisk --append /dev/vda"));
+    assert!(commands
+        .iter()
+        .filter(|c| c.starts_with("mkfs"))
+        .all(|c| c.ends_with("/dev/vda5") || c.ends_with("/dev/vda6")));
+}
+
+#[test]
+fn start_sector_selects_a_gap_and_must_be_free() {
+    let plan = free_space_plan(
+        WINDOWS_GPT,
+        InstallMode::Mutable,
+        LayoutRequest {
+            free_space_start_sector: Some(300_000_000),
+            ..request(Firmware::Uefi)
+        },
+    )
+    .expect("plan");
+    assert_eq!(plan.free_space.expect("gap").start_sector, 209954816);
+
+    let err = free_space_plan(
+        WINDOWS_GPT,
+        InstallMode::Mutable,
+        LayoutRequest {
+            free_space_start_sector: Some(1_000_000),
+            ..request(Firmware::Uefi)
+        },
+    )
+    .expect_err("sector inside C:");
+    assert_eq!(err.code.code(), "E001");
+    assert!(err.observed.contains("209954816-523239423"));
+}
+
+#[test]
+fn the_table_must_match_the_firmware_and_exist() {
+    let err = free_space_plan(WINDOWS_GPT, InstallMode::Ab, request(Firmware::BiosMbr))
+        .expect_err("gpt table with bios-mbr");
+    assert_eq!(err.code.code(), "E001");
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            use_free_space: true,
+            ..request(Firmware::Uefi)
+        },
+    )
+    .expect_err("no table");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn mbr_partitions_join_the_existing_disk_identifier() {
+    let plan = free_space_plan(
+        WINDOWS_MBR,
+        InstallMode::Mutable,
+        request(Firmware::BiosMbr),
+    )
+    .expect("plan");
+    assert_eq!(plan.partitions[0].index, 3);
+    assert_eq!(plan.partitions[0].partuuid.as_deref(), Some("1234abcd-03"));
+
+    let err = free_space_plan(
+        WINDOWS_MBR,
+        InstallMode::Mutable,
+        LayoutRequest {
+            swap_size_mb: Some(1024),
+            home_size_mb: Some(4096),
+            ..request(Firmware::BiosMbr)
+        },
+    )
+    .expect_err("five primaries");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.observed.contains("/dev/vda1, /dev/vda2"));
+}
@@ -0,0 +1,86 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::geometry::{alignment_grain_bytes, last_usable_sector};
+use recpart::profile::builtin_profile;
+use recpart::types::{DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_mirrored_plan, build_plan};
+use std::path::PathBuf;
+
+fn disk(logical: u32, optimal_io: u32, offset: u32) -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: logical,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: optimal_io,
+        alignment_offset_bytes: offset,
+    }
+}
+
+#[test]
+fn partitions_record_contiguous_aligned_sector_ranges() {
+    let plan = build_plan(disk(512, 0, 0), InstallMode::Ab).expect("plan");
+    let ranges = plan
+        .partitions
+        .iter()
+        .map(|p| (p.start_sector.unwrap(), p.end_sector.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(ranges[0], (2048, 2099199));
+    for pair in ranges.windows(2) {
+        assert_eq!(pair[0].1 + 1, pair[1].0);
+        assert_eq!(pair[1].0 % 2048, 0);
+    }
+    assert_eq!(
+        ranges.last().unwrap().1,
+        last_usable_sector(&plan.disk, Firmware::Uefi)
+    );
+}
+
+#[test]
+fn optimal_io_size_and_alignment_offset_move_partition_starts() {
+    let plan = build_plan(disk(512, 4 * 1024 * 1024, 3584), InstallMode::Mutable).expect("plan");
+    let starts = plan
+        .partitions
+        .iter()
+        .map(|p| p.start_sector.unwrap())
+        .collect::<Vec<_>>();
+    // 4MiB grain shifted by 3584 bytes (7 sectors).
+    assert_eq!(starts, vec![8199, 2105351]);
+    assert!(plan
+        .sfdisk_script
+        .contains(&format!("\nstart=8199, size=2097152, type={},", dps::ESP)));
+}
+
+#[test]
+fn non_power_of_two_optimal_io_is_ignored() {
+    assert_eq!(
+        alignment_grain_bytes(&disk(512, 33_553_920, 0)),
+        1024 * 1024
+    );
+}
+
+#[test]
+fn native_4k_disks_count_in_4k_sectors() {
+    let plan = build_plan(disk(4096, 0, 0), InstallMode::Mutable).expect("plan");
+    assert_eq!(plan.partitions[0].start_sector, Some(256));
+    assert_eq!(plan.partitions[0].end_sector, Some(256 + 262_144 - 1));
+    // Backup GPT header plus 4 sectors of partition entries.
+    assert_eq!(
+        last_usable_sector(&plan.disk, Firmware::Uefi),
+        65_536_000 - 6
+    );
+}
+
+#[test]
+fn mirrored_disks_must_share_logical_sector_size() {
+    let mut mirror = disk(4096, 0, 0);
+    mirror.path = PathBuf::from("/dev/vdb");
+    let err = build_mirrored_plan(
+        disk(512, 0, 0),
+        mirror,
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        LayoutRequest::default(),
+    )
+    .expect_err("sector size mismatch");
+    assert_eq!(err.code.code(), "E001");
+}
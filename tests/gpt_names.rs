@@ -0,0 +1,121 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::profile::{parse_profile, ProfileFormat};
+use recpart::types::{
+    Arch, DiskTarget, Firmware, GptAttribute, InstallMode, LayoutProfile, LayoutRequest,
+};
+use recpart::{build_plan_with_layout_request, build_plan_with_profile};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn request(firmware: Firmware) -> LayoutRequest {
+    LayoutRequest {
+        firmware: Some(firmware),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn profile(root_extra: &str) -> LayoutProfile {
+    parse_profile(
+        &format!(
+            r#"
+name = "appliance"
+mode = "mutable"
+
+[[partitions]]
+name = "esp"
+filesystem = "vfat"
+label = "EFI"
+gpt_type = "U"
+size = {{ fixed_mb = 512 }}
+mountpoint = "/boot"
+
+[[partitions]]
+name = "root"
+filesystem = "ext4"
+label = "ROOT"
+gpt_type = "L"
+size = "remaining"
+mountpoint = "/"
+{root_extra}
+"#
+        ),
+        ProfileFormat::Toml,
+    )
+    .expect("profile")
+}
+
+#[test]
+fn every_partition_is_named_and_the_inactive_slot_is_no_auto() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, request(Firmware::Uefi))
+            .expect("plan");
+    let names = plan
+        .partitions
+        .iter()
+        .map(|p| p.partlabel.as_deref().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["efi", "root_a", "root_b", "state"]);
+    assert!(plan.partitions[1].attributes.is_empty());
+    assert_eq!(plan.partitions[2].attributes, vec![GptAttribute::NoAuto]);
+
+    let slot_b = plan
+        .sfdisk_script
+        .lines()
+        .find(|line| line.contains("name=\"root_b\""))
+        .expect("slot B line");
+    assert!(slot_b.ends_with(", attrs=\"GUID:63\""));
+}
+
+#[test]
+fn profiles_set_partlabels_and_attribute_bits() {
+    let plan = build_plan_with_profile(
+        fake_disk(),
+        &profile("partlabel = \"appliance-root\"\nattributes = [\"read-only\", \"grow-fs\"]"),
+        request(Firmware::Uefi),
+    )
+    .expect("plan");
+    let root = &plan.partitions[1];
+    assert_eq!(root.partlabel.as_deref(), Some("appliance-root"));
+    assert_eq!(
+        root.attributes,
+        vec![GptAttribute::ReadOnly, GptAttribute::GrowFs]
+    );
+    assert!(plan
+        .sfdisk_script
+        .contains(", name=\"appliance-root\", attrs=\"GUID:59,60\"\n"));
+}
+
+#[test]
+fn partlabels_must_fit_gpt_and_stay_unique() {
+    for root_extra in [
+        "partlabel = \"esp\"",
+        "partlabel = \"a-partition-name-longer-than-36-chars\"",
+        "partlabel = 'root\"quoted'",
+    ] {
+        let err =
+            build_plan_with_profile(fake_disk(), &profile(root_extra), request(Firmware::Uefi))
+                .expect_err(root_extra);
+        assert_eq!(err.code.code(), "E003");
+        assert!(err.expectation.contains("GPT partition names"));
+    }
+}
+
+#[test]
+fn mbr_scripts_carry_no_names_or_attributes() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, request(Firmware::BiosMbr))
+            .expect("plan");
+    assert!(!plan.sfdisk_script.contains("name="));
+    assert!(!plan.sfdisk_script.contains("attrs="));
+}
@@ -9,6 +9,8 @@ fn fake_disk() -> DiskTarget {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
@@ -21,6 +23,7 @@ fn ab_mode_handoff_contains_slot_context() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("dry run should succeed");
@@ -49,6 +52,7 @@ fn mutable_mode_handoff_has_no_slot_context() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("dry run should succeed");
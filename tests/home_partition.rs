@@ -0,0 +1,137 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::geometry::last_usable_sector;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::SizeExpr;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn expr(value: &str) -> Option<SizeExpr> {
+    Some(value.parse().expect("size expression"))
+}
+
+#[test]
+fn remaining_home_follows_default_sized_root() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            home_size: expr("remaining"),
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    assert!(plan.resolved_layout.separate_home);
+    assert_eq!(plan.resolved_layout.root_size_mb, Some(40_960));
+    assert_eq!(plan.resolved_layout.home_size_mb, None);
+    let labels = plan
+        .partitions
+        .iter()
+        .map(|p| p.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["EFI", "ROOT", "HOME"]);
+    assert!(plan.sfdisk_script.contains(&format!(
+        " size=83886080, type={},",
+        dps::root_type(Arch::X86_64)
+    )));
+    assert_eq!(
+        plan.partitions[2].end_sector,
+        Some(last_usable_sector(&fake_disk(), Firmware::Uefi))
+    );
+
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    assert!(result.steps.iter().any(|s| s.command
+        == format!(
+            "mkfs.ext4 -F -L HOME -U {} /dev/vda3",
+            plan.partitions[2].fs_uuid.as_deref().unwrap()
+        )));
+    let mounts = result
+        .mounted
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(mounts[0], ("/mnt/sysroot", "/dev/vda2"));
+    assert!(mounts.contains(&("/mnt/sysroot/home", "/dev/vda3")));
+}
+
+#[test]
+fn sized_home_and_root_resolve_explicitly() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            root_size_mb: Some(20_480),
+            home_size: expr("50%"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    assert_eq!(plan.resolved_layout.root_size_mb, Some(20_480));
+    assert_eq!(plan.resolved_layout.home_size_mb, Some(128_000));
+    assert!(plan
+        .sfdisk_script
+        .contains(&format!(" size=262144000, type={},", dps::HOME)));
+}
+
+#[test]
+fn default_mutable_plan_has_no_home() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, LayoutRequest::default())
+            .expect("plan");
+    assert!(!plan.resolved_layout.separate_home);
+    assert!(plan.partitions.iter().all(|p| p.label != "HOME"));
+}
+
+#[test]
+fn home_fields_are_rejected_in_ab_mode() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            home_size_mb: Some(10_240),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("home is mutable-only");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.observed.contains("home_size_mb"));
+}
+
+#[test]
+fn root_cannot_take_remaining_space_with_separate_home() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            root_size: expr("remaining"),
+            home_size_mb: Some(10_240),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("root must be sized when home is separate");
+    assert_eq!(err.code.code(), "E003");
+}
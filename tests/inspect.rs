@@ -0,0 +1,124 @@
+use recpart::table::{inspect_table, parse_blkid_export, parse_sfdisk_json};
+use recpart::types::{DiskTarget, FreeGap};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const WINDOWS_GPT: &str = r#"{
+   "partitiontable": {
+      "label": "gpt",
+      "id": "9A1F52E4-1B43-4E26-A2E4-3C1D8C0B7F10",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "firstlba": 34,
+      "lastlba": 524287966,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 204800, "type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "uuid": "4B1D2E6A-0C5F-4F8E-9B7A-2D6C1E3F4A5B", "name": "EFI system partition"},
+         {"node": "/dev/vda2", "start": 206848, "size": 32768, "type": "E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "uuid": "5C2E3F7B-1D60-4A9F-8C8B-3E7D2F405B6C", "name": "Microsoft reserved partition"},
+         {"node": "/dev/vda3", "start": 239616, "size": 209715200, "type": "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7", "uuid": "6D3F4A8C-2E71-4BA0-9D9C-4F8E3A516C7D", "name": "Basic data partition"},
+         {"node": "/dev/vda4", "start": 523239424, "size": 1046528, "type": "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC", "uuid": "7E4A5B9D-3F82-4CB1-AEAD-5A9F4B627D8E"}
+      ]
+   }
+}"#;
+
+// `blkid -p -o export` for the partitions above; the MSR holds no filesystem.
+const BLKID: &str = "DEVNAME=/dev/vda1
+UUID=4A1B-2C3D
+VERSION=FAT32
+BLOCK_SIZE=512
+TYPE=vfat
+USAGE=filesystem
+
+DEVNAME=/dev/vda3
+LABEL=Windows\\ 11
+UUID=01DA2B3C4D5E6F70
+BLOCK_SIZE=512
+TYPE=ntfs
+USAGE=filesystem
+
+DEVNAME=/dev/vda4
+UUID=9A8B7C6D5E4F3A2B
+TYPE=ntfs
+USAGE=filesystem
+";
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+#[test]
+fn blkid_export_blocks_become_filesystem_probes() {
+    let probes = parse_blkid_export(BLKID);
+    assert_eq!(probes.len(), 3);
+    let windows = &probes["/dev/vda3"];
+    assert_eq!(windows.filesystem, "ntfs");
+    assert_eq!(windows.label.as_deref(), Some("Windows 11"));
+    assert_eq!(windows.fs_uuid.as_deref(), Some("01DA2B3C4D5E6F70"));
+    assert_eq!(probes["/dev/vda1"].label, None);
+}
+
+#[test]
+fn partitions_carry_their_table_and_filesystem_details() {
+    let inspection = inspect_table(
+        fake_disk(),
+        Some(parse_sfdisk_json(WINDOWS_GPT).expect("sfdisk json")),
+        &parse_blkid_export(BLKID),
+    );
+    assert_eq!(inspection.label.as_deref(), Some("gpt"));
+    assert_eq!(
+        inspection.label_id.as_deref(),
+        Some("9A1F52E4-1B43-4E26-A2E4-3C1D8C0B7F10")
+    );
+
+    let esp = &inspection.partitions[0];
+    assert_eq!(esp.disk, PathBuf::from("/dev/vda"));
+    assert_eq!((esp.device.as_str(), esp.index), ("/dev/vda1", 1));
+    assert_eq!(
+        (esp.start_sector, esp.end_sector, esp.size_mb),
+        (2048, 206847, 100)
+    );
+    assert_eq!(esp.gpt_type, "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+    assert_eq!(esp.partlabel.as_deref(), Some("EFI system partition"));
+    assert_eq!(
+        esp.partuuid.as_deref(),
+        Some("4b1d2e6a-0c5f-4f8e-9b7a-2d6c1e3f4a5b")
+    );
+    assert_eq!(esp.filesystem.as_deref(), Some("vfat"));
+    assert_eq!(esp.fs_uuid.as_deref(), Some("4A1B-2C3D"));
+
+    let msr = &inspection.partitions[1];
+    assert_eq!((msr.filesystem.as_ref(), msr.label.as_ref()), (None, None));
+    assert_eq!(
+        inspection.partitions[2].label.as_deref(),
+        Some("Windows 11")
+    );
+
+    assert_eq!(
+        inspection.free_gaps,
+        vec![FreeGap {
+            start_sector: 209954816,
+            end_sector: 523239423,
+            size_mb: 152971,
+        }]
+    );
+}
+
+#[test]
+fn a_disk_without_a_table_is_one_free_gap() {
+    let inspection = inspect_table(fake_disk(), None, &BTreeMap::new());
+    assert_eq!(inspection.label, None);
+    assert!(inspection.partitions.is_empty());
+    let gap = inspection.free_gaps[0];
+    assert_eq!(inspection.free_gaps.len(), 1);
+    assert_eq!(gap.start_sector, 2048);
+
+    let json = serde_json::to_value(&inspection).expect("json");
+    assert_eq!(json["schema_version"], 1);
+    assert!(json.get("label").is_none());
+}
@@ -0,0 +1,91 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::types::{
+    AbSlot, ApplyOptions, Arch, DiskTarget, GptAttribute, InstallMode, LayoutRequest,
+};
+use recpart::{build_plan_with_layout_request, execute_plan, ApplyResult, PartitionPlan};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn slot_plan(mode: InstallMode, slot: Option<AbSlot>) -> recpart::Result<PartitionPlan> {
+    build_plan_with_layout_request(
+        fake_disk(),
+        mode,
+        LayoutRequest {
+            install_slot: slot,
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+}
+
+fn dry_run(plan: &PartitionPlan) -> ApplyResult {
+    execute_plan(
+        plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run")
+}
+
+#[test]
+fn slot_b_becomes_sysroot_and_slot_a_is_no_auto() {
+    let plan = slot_plan(InstallMode::Ab, Some(AbSlot::B)).expect("plan");
+    assert_eq!(plan.install_slot, Some(AbSlot::B));
+    assert_eq!(plan.partitions[1].attributes, vec![GptAttribute::NoAuto]);
+    assert!(plan.partitions[2].attributes.is_empty());
+    let slot_a = plan
+        .sfdisk_script
+        .lines()
+        .find(|line| line.contains("name=\"root_a\""))
+        .expect("slot A line");
+    assert!(slot_a.ends_with(", attrs=\"GUID:63\""));
+
+    let result = dry_run(&plan);
+    assert_eq!(result.mounted[0].path, "/mnt/sysroot");
+    assert_eq!(result.mounted[0].device, "/dev/vda3");
+    assert!(result.mounted.iter().all(|m| m.device != "/dev/vda2"));
+
+    let context = &result.handoff.mode_context;
+    assert_eq!(context.install_target_slot.as_deref(), Some("B"));
+    assert_eq!(context.inactive_slot_hint.as_deref(), Some("A"));
+    assert_eq!(context.install_target_device.as_deref(), Some("/dev/vda3"));
+    assert_eq!(context.inactive_slot_device.as_deref(), Some("/dev/vda2"));
+    assert_eq!(context.slot_a_device.as_deref(), Some("/dev/vda2"));
+}
+
+#[test]
+fn slot_a_is_the_default() {
+    let plan = slot_plan(InstallMode::Ab, None).expect("plan");
+    assert_eq!(plan.install_slot, Some(AbSlot::A));
+    let context = dry_run(&plan).handoff.mode_context;
+    assert_eq!(context.install_target_device.as_deref(), Some("/dev/vda2"));
+    assert_eq!(context.inactive_slot_device.as_deref(), Some("/dev/vda3"));
+}
+
+#[test]
+fn mutable_plans_have_no_install_slot() {
+    let plan = slot_plan(InstallMode::Mutable, None).expect("plan");
+    assert_eq!(plan.install_slot, None);
+    assert_eq!(
+        dry_run(&plan).handoff.mode_context.install_target_device,
+        None
+    );
+
+    let err = slot_plan(InstallMode::Mutable, Some(AbSlot::B)).expect_err("mutable slot");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.observed.contains("install_slot"));
+}
@@ -26,6 +26,7 @@ fn help_shows_expected_commands() {
     );
     assert!(stdout.contains("plan"), "help missing plan command");
     assert!(stdout.contains("apply"), "help missing apply command");
+    assert!(stdout.contains("inspect"), "help missing inspect command");
     assert!(
         !stdout.contains(" tui"),
         "help still includes removed tui command"
@@ -1,6 +1,7 @@
 use recpart::build_plan;
 use recpart::execute_plan;
 use recpart::types::{ApplyOptions, DiskTarget, InstallMode};
+use recpart::PartitionPlan;
 use std::path::PathBuf;
 
 #[test]
@@ -10,13 +11,15 @@ fn plan_and_apply_include_schema_versions() {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     };
 
     let plan = build_plan(disk, InstallMode::Ab).expect("plan");
     let plan_json = serde_json::to_value(&plan).expect("plan json");
     assert_eq!(
         plan_json.get("schema_version").and_then(|v| v.as_u64()),
-        Some(1)
+        Some(2)
     );
     assert!(plan_json.get("layout_request").is_some());
     assert!(plan_json.get("resolved_layout").is_some());
@@ -27,6 +30,7 @@ fn plan_and_apply_include_schema_versions() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("apply dry-run");
@@ -41,3 +45,25 @@ fn plan_and_apply_include_schema_versions() {
     assert!(apply_json.get("partition_map").is_some());
     assert!(apply_json.get("handoff").is_some());
 }
+
+#[test]
+fn plans_without_a_profile_name_still_deserialize() {
+    let disk = DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    };
+
+    let plan = build_plan(disk, InstallMode::Mutable).expect("plan");
+    let mut plan_json = serde_json::to_value(&plan).expect("plan json");
+    plan_json
+        .as_object_mut()
+        .expect("plan object")
+        .remove("profile");
+    let old: PartitionPlan = serde_json::from_value(plan_json).expect("older plan");
+    assert_eq!(old.profile, "");
+    assert_eq!(old.partitions, plan.partitions);
+}
@@ -0,0 +1,161 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::profile::{builtin_profile, load_profile, parse_profile, ProfileFormat};
+use recpart::types::{Arch, DiskTarget, InstallMode, LayoutRequest};
+use recpart::{build_plan, build_plan_with_profile};
+use std::fs;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+const DATA_PROFILE: &str = r#"
+name = "mutable-data"
+mode = "mutable"
+
+[[partitions]]
+name = "efi"
+filesystem = "vfat"
+label = "EFI"
+gpt_type = "U"
+size = { fixed_mb = 512 }
+mountpoint = "/boot"
+
+[[partitions]]
+name = "root"
+filesystem = "ext4"
+label = "ROOT"
+gpt_type = "L"
+size = { layout = "root_size_mb" }
+mountpoint = "/"
+
+[[partitions]]
+name = "data"
+filesystem = "ext4"
+label = "DATA"
+gpt_type = "L"
+size = "remaining"
+mountpoint = "/srv"
+"#;
+
+// The sfdisk script without its seed-derived label-id and uuid= fields.
+fn layout_only(script: &str) -> String {
+    script
+        .lines()
+        .filter(|line| !line.starts_with("label-id:"))
+        .map(|line| {
+            line.split(", ")
+                .filter(|field| !field.starts_with("uuid="))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+#[test]
+fn builtin_profiles_reproduce_mode_layouts() {
+    for mode in [InstallMode::Ab, InstallMode::Mutable] {
+        let profile = builtin_profile(mode).expect("built-in profile parses");
+        let legacy = build_plan(fake_disk(), mode).expect("mode plan");
+        let mut plan = build_plan_with_profile(
+            fake_disk(),
+            &profile,
+            LayoutRequest {
+                uuid_seed: Some(legacy.uuid_seed.clone()),
+                ..LayoutRequest::default()
+            },
+        )
+        .expect("profile plan");
+        plan.layout_request.uuid_seed = None;
+
+        assert_eq!(profile.mode, mode);
+        assert_eq!(plan, legacy);
+    }
+}
+
+#[test]
+fn toml_profile_drives_partitions_and_mounts() {
+    let profile = parse_profile(DATA_PROFILE, ProfileFormat::Toml).expect("toml profile");
+    let plan = build_plan_with_profile(
+        fake_disk(),
+        &profile,
+        LayoutRequest {
+            root_size_mb: Some(32_768),
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    assert_eq!(plan.profile, "mutable-data");
+    let labels = plan
+        .partitions
+        .iter()
+        .map(|p| p.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["EFI", "ROOT", "DATA"]);
+    assert_eq!(
+        layout_only(&plan.sfdisk_script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=1048576, type={}, name=\"efi\", bootable\nstart=1050624, size=67108864, type={}, name=\"root\"\nstart=68159488, size=456128479, type={}, name=\"data\"\n",
+            dps::ESP,
+            dps::root_type(Arch::X86_64),
+            dps::SRV
+        )
+    );
+}
+
+#[test]
+fn json_profile_loads_from_file() {
+    let profile = parse_profile(DATA_PROFILE, ProfileFormat::Toml).expect("toml profile");
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("data.json");
+    fs::write(&path, serde_json::to_string(&profile).expect("json")).expect("write");
+
+    assert_eq!(load_profile(&path).expect("json profile"), profile);
+}
+
+#[test]
+fn remaining_partition_must_be_last() {
+    let source = DATA_PROFILE.replace(
+        "size = { layout = \"root_size_mb\" }",
+        "size = \"remaining\"",
+    );
+    let profile = parse_profile(&source, ProfileFormat::Toml).expect("toml profile");
+    let err = build_plan_with_profile(fake_disk(), &profile, LayoutRequest::default())
+        .expect_err("remaining before last partition must fail");
+
+    assert_eq!(err.code.code(), "E003");
+    assert_eq!(err.component, "profile");
+}
+
+#[test]
+fn duplicate_labels_and_mode_fields_are_rejected() {
+    let duplicate = DATA_PROFILE.replace("label = \"DATA\"", "label = \"ROOT\"");
+    let profile = parse_profile(&duplicate, ProfileFormat::Toml).expect("toml profile");
+    let err = build_plan_with_profile(fake_disk(), &profile, LayoutRequest::default())
+        .expect_err("duplicate labels must fail");
+    assert_eq!(err.code.code(), "E003");
+
+    let wrong_field = DATA_PROFILE.replace("root_size_mb", "root_a_size_mb");
+    let profile = parse_profile(&wrong_field, ProfileFormat::Toml).expect("toml profile");
+    let err = build_plan_with_profile(fake_disk(), &profile, LayoutRequest::default())
+        .expect_err("ab-only layout field must fail in mutable profile");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn malformed_profile_reports_plan_generation_error() {
+    let err = parse_profile("name = ", ProfileFormat::Toml).expect_err("must fail");
+    assert_eq!(err.code.code(), "E003");
+    assert_eq!(err.component, "profile");
+}
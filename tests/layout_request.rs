@@ -9,6 +9,8 @@ fn fake_disk() -> DiskTarget {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
@@ -101,6 +103,9 @@ fn identical_layout_request_is_deterministic() {
         root_b_size_mb: Some(16_384),
         state_size_mb: None,
         root_size_mb: None,
+        // Without a seed each plan draws a random one.
+        uuid_seed: Some("machine-1".to_string()),
+        ..LayoutRequest::default()
     };
 
     let a = build_plan_with_layout_request(fake_disk(), InstallMode::Ab, request.clone())
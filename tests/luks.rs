@@ -0,0 +1,163 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::execute_plan;
+use recpart::types::{ApplyOptions, DiskTarget, InstallMode, LayoutRequest, LuksKey};
+use recpart::SizeExpr;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn dry_run_opts() -> ApplyOptions {
+    ApplyOptions {
+        dry_run: true,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: Some(LuksKey(b"correct horse".to_vec())),
+    }
+}
+
+fn encrypt(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn ab_state_is_formatted_and_mounted_through_luks_mapper() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            encrypt: encrypt(&["state"]),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+    assert!(plan
+        .partitions
+        .iter()
+        .any(|p| p.name == "state" && p.encrypted));
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let commands = result
+        .steps
+        .iter()
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+    assert!(commands
+        .contains(&"cryptsetup luksFormat --type luks2 --batch-mode --key-file - /dev/vda4"));
+    assert!(commands.contains(&"cryptsetup open --key-file - /dev/vda4 luks-state"));
+    let state = plan.partitions.iter().find(|p| p.name == "state").unwrap();
+    let mkfs = format!(
+        "mkfs.ext4 -F -L STATE -U {} /dev/mapper/luks-state",
+        state.fs_uuid.as_deref().unwrap()
+    );
+    assert!(commands.contains(&mkfs.as_str()));
+    assert!(commands.iter().all(|c| !c.contains("correct horse")));
+
+    assert!(result
+        .formatted_devices
+        .contains(&"/dev/mapper/luks-state".to_string()));
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/state" && m.device == "/dev/mapper/luks-state"));
+
+    let volumes = &result.handoff.luks_volumes;
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(volumes[0].device, "/dev/vda4");
+    assert_eq!(volumes[0].mapper_device, "/dev/mapper/luks-state");
+    assert_eq!(volumes[0].uuid, None);
+    assert_eq!(volumes[0].crypttab, "luks-state /dev/vda4 none luks");
+}
+
+#[test]
+fn mutable_root_and_home_can_both_be_encrypted() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            home_size: Some("remaining".parse::<SizeExpr>().unwrap()),
+            encrypt: encrypt(&["root", "home"]),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let mounts = result
+        .mounted
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(mounts[0], ("/mnt/sysroot", "/dev/mapper/luks-root"));
+    assert!(mounts.contains(&("/mnt/sysroot/home", "/dev/mapper/luks-home")));
+    assert!(mounts.contains(&("/mnt/sysroot/boot", "/dev/vda1")));
+    assert_eq!(result.handoff.luks_volumes.len(), 2);
+    assert!(result
+        .handoff
+        .next_commands
+        .iter()
+        .any(|c| c.contains("/etc/crypttab")));
+}
+
+#[test]
+fn only_data_partitions_in_the_plan_can_be_encrypted() {
+    for (mode, name) in [
+        (InstallMode::Ab, "efi"),
+        (InstallMode::Ab, "root_a"),
+        (InstallMode::Mutable, "home"),
+        (InstallMode::Mutable, "state"),
+    ] {
+        let err = build_plan_with_layout_request(
+            fake_disk(),
+            mode,
+            LayoutRequest {
+                encrypt: encrypt(&[name]),
+                ..LayoutRequest::default()
+            },
+        )
+        .expect_err("encryption must be rejected");
+        assert_eq!(err.code.code(), "E003", "{mode} {name}");
+    }
+}
+
+#[test]
+fn apply_requires_a_luks_key_for_encrypted_plans() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            encrypt: encrypt(&["state"]),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let err = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: false,
+            confirm_token: Some("DESTROY".to_string()),
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect_err("missing key must fail");
+    assert_eq!(err.code.code(), "E014");
+    assert_eq!(err.code.exit_code(), 14);
+    assert!(err.observed.contains("state"));
+}
+
+#[test]
+fn luks_key_debug_output_is_redacted() {
+    let key = LuksKey(b"secret".to_vec());
+    assert_eq!(format!("{key:?}"), "LuksKey(<redacted>)");
+}
@@ -0,0 +1,215 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::preflight::required_tools;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, GptAttribute, InstallMode, LayoutRequest};
+use recpart::PartitionPlan;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn dry_run_opts() -> ApplyOptions {
+    ApplyOptions {
+        dry_run: true,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: None,
+    }
+}
+
+fn lvm_request() -> LayoutRequest {
+    LayoutRequest {
+        lvm_volume_group: Some("recvg".to_string()),
+        swap_size_mb: Some(4_096),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+// The sfdisk script without its seed-derived label-id and uuid= fields.
+fn layout_only(script: &str) -> String {
+    script
+        .lines()
+        .filter(|line| !line.starts_with("label-id:"))
+        .map(|line| {
+            line.split(", ")
+                .filter(|field| !field.starts_with("uuid="))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+fn fs_uuid(plan: &PartitionPlan, name: &str) -> String {
+    let part = plan.partitions.iter().find(|p| p.name == name).unwrap();
+    part.fs_uuid.clone().unwrap()
+}
+
+#[test]
+fn ab_lvm_plan_keeps_esp_slots_and_pv_on_disk() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, lvm_request()).expect("plan");
+
+    assert_eq!(
+        layout_only(&plan.sfdisk_script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=2097152, type={}, name=\"efi\", bootable\nstart=2099200, size=41943040, type={root}, name=\"root_a\"\nstart=44042240, size=41943040, type={root}, name=\"root_b\", attrs=\"GUID:63\"\nstart=85985280, size=438302687, type=V, name=\"lvm\"\n",
+            dps::ESP,
+            root = dps::root_type(Arch::X86_64)
+        )
+    );
+    assert_eq!(plan.partitions[2].attributes, vec![GptAttribute::NoAuto]);
+    let volumes = plan
+        .partitions
+        .iter()
+        .filter(|p| p.volume_group.as_deref() == Some("recvg"))
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(volumes, vec!["swap", "state"]);
+    assert!(required_tools(&plan).contains(&"lvcreate"));
+}
+
+#[test]
+fn slot_boot_and_verity_partitions_stay_on_disk() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            slot_boot: true,
+            verity: true,
+            ..lvm_request()
+        },
+    )
+    .expect("plan");
+    let on_disk = plan
+        .partitions
+        .iter()
+        .filter(|p| p.volume_group.is_none())
+        .map(|p| (p.name.as_str(), p.gpt_type.as_str()))
+        .collect::<Vec<_>>();
+    let root = dps::root_type(Arch::X86_64);
+    let verity = dps::root_verity_type(Arch::X86_64);
+    assert_eq!(
+        on_disk,
+        vec![
+            ("efi", dps::ESP),
+            ("boot_a", dps::LINUX_DATA),
+            ("boot_b", dps::LINUX_DATA),
+            ("root_a", root),
+            ("root_b", root),
+            ("root_a_verity", verity),
+            ("root_b_verity", verity),
+            ("lvm", "V"),
+        ]
+    );
+    let boot_b = plan.partitions.iter().find(|p| p.name == "boot_b").unwrap();
+    assert_eq!(boot_b.attributes, vec![GptAttribute::NoAuto]);
+}
+
+#[test]
+fn lvm_actions_precede_formatting_and_mount_lv_devices() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, lvm_request()).expect("plan");
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+
+    let lvm = result
+        .steps
+        .iter()
+        .filter(|s| s.phase == "lvm")
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lvm,
+        vec![
+            "pvcreate -ff -y /dev/vda4",
+            "vgcreate recvg /dev/vda4",
+            "lvcreate -y --wipesignatures y -n swap -L 4096M recvg",
+            "lvcreate -y --wipesignatures y -n state -l 100%FREE recvg",
+        ]
+    );
+    let first_lvm = result.steps.iter().position(|s| s.phase == "lvm").unwrap();
+    let first_format = result
+        .steps
+        .iter()
+        .position(|s| s.phase == "format")
+        .unwrap();
+    assert!(first_lvm < first_format);
+    assert!(result.steps.iter().any(|s| s.command
+        == format!(
+            "mkswap -L SWAP -U {} /dev/recvg/swap",
+            fs_uuid(&plan, "swap")
+        )));
+
+    assert_eq!(
+        result.formatted_devices,
+        vec![
+            "/dev/vda1",
+            "/dev/vda2",
+            "/dev/vda3",
+            "/dev/recvg/swap",
+            "/dev/recvg/state",
+        ]
+    );
+    let mounts = result
+        .mounted
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(mounts[0], ("/mnt/sysroot", "/dev/vda2"));
+    assert!(mounts.contains(&("/mnt/sysroot/state", "/dev/recvg/state")));
+    assert!(mounts.contains(&("/mnt/sysroot/boot", "/dev/vda1")));
+    assert_eq!(result.handoff.swap_devices, vec!["/dev/recvg/swap"]);
+    assert_eq!(
+        result.handoff.mode_context.slot_b_device.as_deref(),
+        Some("/dev/vda3")
+    );
+}
+
+#[test]
+fn lvm_composes_with_luks_on_logical_volumes() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            lvm_volume_group: Some("recvg".to_string()),
+            encrypt: vec!["root".to_string()],
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+
+    assert!(result
+        .steps
+        .iter()
+        .any(|s| s.command == "cryptsetup open --key-file - /dev/recvg/root luks-root"));
+    assert_eq!(result.mounted[0].device, "/dev/mapper/luks-root");
+    assert_eq!(result.handoff.luks_volumes[0].device, "/dev/recvg/root");
+}
+
+#[test]
+fn invalid_volume_group_names_are_rejected() {
+    for vg in ["", "-vg", "bad/name", ".."] {
+        let err = build_plan_with_layout_request(
+            fake_disk(),
+            InstallMode::Mutable,
+            LayoutRequest {
+                lvm_volume_group: Some(vg.to_string()),
+                ..LayoutRequest::default()
+            },
+        )
+        .expect_err("invalid vg");
+        assert_eq!(err.code.code(), "E003", "{vg}");
+    }
+}
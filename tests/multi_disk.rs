@@ -0,0 +1,187 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::plan::target_disks;
+use recpart::profile::builtin_profile;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, InstallMode, LayoutRequest};
+use recpart::{build_multi_disk_plan, PartitionPlan};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+fn disk(path: &str, gib: u64) -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from(path),
+        size_bytes: gib * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn placement(pairs: &[(&str, &str)]) -> LayoutRequest {
+    LayoutRequest {
+        partition_disks: pairs
+            .iter()
+            .map(|(name, path)| (name.to_string(), PathBuf::from(path)))
+            .collect::<BTreeMap<_, _>>(),
+        arch: Some(Arch::Arm64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn state_on_hdd_plan() -> PartitionPlan {
+    build_multi_disk_plan(
+        disk("/dev/nvme0n1", 64),
+        vec![disk("/dev/sda", 1000)],
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        placement(&[("state", "/dev/sda")]),
+    )
+    .expect("multi-disk plan")
+}
+
+// The sfdisk script without its seed-derived label-id and uuid= fields.
+fn layout_only(script: &str) -> String {
+    script
+        .lines()
+        .filter(|line| !line.starts_with("label-id:"))
+        .map(|line| {
+            line.split(", ")
+                .filter(|field| !field.starts_with("uuid="))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+fn fs_uuid(plan: &PartitionPlan, name: &str) -> String {
+    let part = plan.partitions.iter().find(|p| p.name == name).unwrap();
+    part.fs_uuid.clone().unwrap()
+}
+
+#[test]
+fn moved_partitions_get_their_own_disk_script_and_index() {
+    let plan = state_on_hdd_plan();
+    assert_eq!(plan.schema_version, 2);
+    assert_eq!(
+        target_disks(&plan),
+        vec![Path::new("/dev/nvme0n1"), Path::new("/dev/sda")]
+    );
+
+    let state = plan.partitions.iter().find(|p| p.name == "state").unwrap();
+    assert_eq!(state.disk, PathBuf::from("/dev/sda"));
+    assert_eq!(state.index, 1);
+
+    assert_eq!(plan.sfdisk_scripts.len(), 2);
+    assert_eq!(
+        layout_only(&plan.sfdisk_scripts[0].script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=2097152, type={}, name=\"efi\", bootable\nstart=2099200, size=41943040, type={root}, name=\"root_a\"\nstart=44042240, size=41943040, type={root}, name=\"root_b\", attrs=\"GUID:63\"\n",
+            dps::ESP,
+            root = dps::root_type(Arch::Arm64)
+        )
+    );
+    assert_eq!(plan.sfdisk_script, plan.sfdisk_scripts[0].script);
+    assert_eq!(plan.sfdisk_scripts[1].disk, PathBuf::from("/dev/sda"));
+    assert_eq!(
+        layout_only(&plan.sfdisk_scripts[1].script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=2097149919, type={}, name=\"state\"\n",
+            dps::LINUX_DATA
+        )
+    );
+}
+
+#[test]
+fn apply_wipes_and_partitions_every_disk_with_its_script() {
+    let plan = state_on_hdd_plan();
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    let commands = result
+        .steps
+        .iter()
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+
+    for disk in ["/dev/nvme0n1", "/dev/sda"] {
+        assert!(commands.contains(&format!("wipefs -a --force {disk}").as_str()));
+        assert!(commands.contains(&format!("sfdisk {disk}").as_str()));
+    }
+    for (name, label, device) in [
+        ("root_a", "ROOT_A", "/dev/nvme0n1p2"),
+        ("state", "STATE", "/dev/sda1"),
+    ] {
+        let mkfs = format!(
+            "mkfs.ext4 -F -L {label} -U {} {device}",
+            fs_uuid(&plan, name)
+        );
+        assert!(commands.contains(&mkfs.as_str()));
+    }
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/state" && m.device == "/dev/sda1"));
+}
+
+#[test]
+fn esp_cannot_move_off_the_primary_disk() {
+    let err = build_multi_disk_plan(
+        disk("/dev/nvme0n1", 64),
+        vec![disk("/dev/sda", 1000)],
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        placement(&[("efi", "/dev/sda")]),
+    )
+    .expect_err("esp must stay");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.expectation.contains("primary disk"));
+}
+
+#[test]
+fn disks_without_partitions_are_rejected() {
+    let err = build_multi_disk_plan(
+        disk("/dev/nvme0n1", 64),
+        vec![disk("/dev/sda", 1000), disk("/dev/sdb", 1000)],
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        placement(&[("root", "/dev/sda")]),
+    )
+    .expect_err("unused disk");
+    assert_eq!(err.code.code(), "E001");
+    assert!(err.observed.contains("/dev/sdb"));
+}
+
+#[test]
+fn placement_on_an_unknown_disk_is_rejected() {
+    let err = build_multi_disk_plan(
+        disk("/dev/nvme0n1", 64),
+        vec![disk("/dev/sda", 1000)],
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        placement(&[("state", "/dev/sda"), ("root_b", "/dev/sdc")]),
+    )
+    .expect_err("unknown disk");
+    assert_eq!(err.code.code(), "E001");
+}
+
+#[test]
+fn each_disk_is_checked_against_its_own_size() {
+    let err = build_multi_disk_plan(
+        disk("/dev/nvme0n1", 64),
+        vec![disk("/dev/sda", 10)],
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        LayoutRequest {
+            state_size_mb: Some(20 * 1024),
+            ..placement(&[("state", "/dev/sda")])
+        },
+    )
+    .expect_err("state does not fit");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.observed.contains("10240MB"));
+}
@@ -1,5 +1,5 @@
-use recpart::build_plan;
-use recpart::types::{DiskTarget, InstallMode};
+use recpart::build_plan_with_layout_request;
+use recpart::types::{DiskTarget, InstallMode, LayoutRequest};
 use std::path::PathBuf;
 
 #[test]
@@ -9,10 +9,19 @@ fn same_input_produces_identical_plan() {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    };
+    // Identifiers come from the seed, which is random unless given.
+    let request = LayoutRequest {
+        uuid_seed: Some("machine-1".to_string()),
+        ..LayoutRequest::default()
     };
 
-    let plan_a = build_plan(disk.clone(), InstallMode::Ab).expect("first plan");
-    let plan_b = build_plan(disk, InstallMode::Ab).expect("second plan");
+    let plan_a = build_plan_with_layout_request(disk.clone(), InstallMode::Ab, request.clone())
+        .expect("first plan");
+    let plan_b =
+        build_plan_with_layout_request(disk, InstallMode::Ab, request).expect("second plan");
 
     assert_eq!(plan_a.sfdisk_script, plan_b.sfdisk_script);
 
@@ -8,6 +8,8 @@ fn fake_disk() -> DiskTarget {
         size_bytes: 200 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
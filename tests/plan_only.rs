@@ -10,6 +10,8 @@ fn dry_run_does_not_require_confirmation_and_marks_all_steps_dry() {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     };
 
     let plan = build_plan(disk, InstallMode::Mutable).expect("plan");
@@ -19,6 +21,7 @@ fn dry_run_does_not_require_confirmation_and_marks_all_steps_dry() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect("dry-run apply");
@@ -0,0 +1,167 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::preflight::required_tools;
+use recpart::profile::builtin_profile;
+use recpart::types::{ApplyOptions, DiskTarget, InstallMode, LayoutRequest};
+use recpart::{build_mirrored_plan, PartitionPlan};
+use std::path::PathBuf;
+
+fn disk(path: &str, gib: u64) -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from(path),
+        size_bytes: gib * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn mirrored_plan(mode: InstallMode) -> PartitionPlan {
+    build_mirrored_plan(
+        disk("/dev/vda", 250),
+        disk("/dev/vdb", 200),
+        &builtin_profile(mode).expect("profile"),
+        LayoutRequest::default(),
+    )
+    .expect("mirrored plan")
+}
+
+fn dry_run_opts() -> ApplyOptions {
+    ApplyOptions {
+        dry_run: true,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: None,
+    }
+}
+
+// The sfdisk script without its seed-derived label-id and uuid= fields.
+fn layout_only(script: &str) -> String {
+    script
+        .lines()
+        .filter(|line| !line.starts_with("label-id:"))
+        .map(|line| {
+            line.split(", ")
+                .filter(|field| !field.starts_with("uuid="))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+fn fs_uuid(plan: &PartitionPlan, name: &str) -> String {
+    let part = plan.partitions.iter().find(|p| p.name == name).unwrap();
+    part.fs_uuid.clone().unwrap()
+}
+
+#[test]
+fn mirrored_plan_marks_non_esp_partitions_as_raid_members() {
+    let plan = mirrored_plan(InstallMode::Ab);
+    assert_eq!(
+        plan.mirror_disk.as_ref().unwrap().path,
+        PathBuf::from("/dev/vdb")
+    );
+    assert_eq!(
+        layout_only(&plan.sfdisk_script),
+        format!(
+            "label: gpt\nunit: sectors\nstart=2048, size=2097152, type={}, name=\"efi\", bootable\nstart=2099200, size=41943040, type=R, name=\"root_a\"\nstart=44042240, size=41943040, type=R, name=\"root_b\", attrs=\"GUID:63\"\nstart=85985280, size=333445087, type=R, name=\"state\"\n",
+            dps::ESP
+        )
+    );
+    let mirrored = plan
+        .partitions
+        .iter()
+        .filter(|p| p.mirrored)
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(mirrored, vec!["root_a", "root_b", "state"]);
+    assert!(required_tools(&plan).contains(&"mdadm"));
+}
+
+#[test]
+fn both_disks_are_partitioned_and_arrays_assembled_before_format() {
+    let plan = mirrored_plan(InstallMode::Mutable);
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    let commands = result
+        .steps
+        .iter()
+        .map(|s| s.command.as_str())
+        .collect::<Vec<_>>();
+
+    for disk in ["/dev/vda", "/dev/vdb"] {
+        assert!(commands.contains(&format!("wipefs -a --force {disk}").as_str()));
+        assert!(commands.contains(&format!("sfdisk {disk}").as_str()));
+    }
+    assert!(commands.contains(
+        &"mdadm --create /dev/md/root --run --level=1 --raid-devices=2 --metadata=1.2 /dev/vda2 /dev/vdb2"
+    ));
+    // Each ESP copy gets its own volume ID.
+    let esp_formats = commands
+        .iter()
+        .filter(|c| c.starts_with("mkfs.vfat -F 32 -n EFI -i "))
+        .collect::<Vec<_>>();
+    assert_eq!(esp_formats.len(), 2);
+    assert!(esp_formats[0].ends_with(" /dev/vda1") && esp_formats[1].ends_with(" /dev/vdb1"));
+    assert_ne!(
+        esp_formats[0].split(' ').nth(6),
+        esp_formats[1].split(' ').nth(6)
+    );
+    let mkfs = format!(
+        "mkfs.ext4 -F -L ROOT -U {} /dev/md/root",
+        fs_uuid(&plan, "root")
+    );
+    assert!(commands.contains(&mkfs.as_str()));
+    let raid = commands
+        .iter()
+        .position(|c| c.starts_with("mdadm"))
+        .unwrap();
+    let format = commands.iter().position(|c| c.starts_with("mkfs")).unwrap();
+    assert!(raid < format);
+
+    assert_eq!(
+        result.formatted_devices,
+        vec!["/dev/vda1", "/dev/vdb1", "/dev/md/root"]
+    );
+    assert_eq!(result.mounted[0].device, "/dev/md/root");
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/boot" && m.device == "/dev/vda1"));
+
+    let arrays = &result.handoff.raid_arrays;
+    assert_eq!(arrays.len(), 1);
+    assert_eq!(arrays[0].members, vec!["/dev/vda2", "/dev/vdb2"]);
+    assert!(result
+        .handoff
+        .mode_context
+        .notes
+        .iter()
+        .any(|n| n.contains("/dev/vdb1")));
+}
+
+#[test]
+fn mirrored_plan_is_sized_for_the_smaller_disk() {
+    let plan = build_mirrored_plan(
+        disk("/dev/vda", 250),
+        disk("/dev/vdb", 30),
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        LayoutRequest::default(),
+    );
+    let err = plan.expect_err("30GiB mirror cannot hold the ab layout");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn mirror_disk_must_differ_from_primary() {
+    let err = build_mirrored_plan(
+        disk("/dev/vda", 250),
+        disk("/dev/vda", 250),
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        LayoutRequest::default(),
+    )
+    .expect_err("same disk twice");
+    assert_eq!(err.code.code(), "E001");
+}
@@ -0,0 +1,126 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::policy::required_min_mb;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, InstallMode, LayoutRequest};
+use recpart::{build_plan_with_layout_request, execute_plan};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn recovery_request() -> LayoutRequest {
+    LayoutRequest {
+        recovery: true,
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+#[test]
+fn recovery_follows_the_slots_and_counts_toward_the_minimum() {
+    let plan = build_plan_with_layout_request(fake_disk(), InstallMode::Ab, recovery_request())
+        .expect("plan");
+    let names = plan
+        .partitions
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["efi", "root_a", "root_b", "recovery", "state"]);
+
+    let recovery = &plan.partitions[3];
+    assert_eq!(recovery.size_mb, Some(2048));
+    assert_eq!(recovery.label, "RECOVERY");
+    assert_eq!(recovery.filesystem, "ext4");
+    assert_eq!(recovery.mountpoint, "none");
+    assert_eq!(plan.resolved_layout.recovery_size_mb, Some(2048));
+
+    let without = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+    assert_eq!(without.resolved_layout.recovery_size_mb, None);
+    assert_eq!(
+        required_min_mb(&plan.resolved_layout),
+        required_min_mb(&without.resolved_layout) + 2048
+    );
+}
+
+#[test]
+fn recovery_is_formatted_unmounted_and_reported_for_recab() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            recovery_size: Some("4G".parse().expect("size")),
+            ..recovery_request()
+        },
+    )
+    .expect("plan");
+    assert_eq!(plan.partitions[3].size_mb, Some(4096));
+
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    assert!(result
+        .steps
+        .iter()
+        .any(|s| s.phase == "format" && s.command.starts_with("mkfs.ext4 -F -L RECOVERY ")));
+    assert!(result
+        .handoff
+        .mount_map
+        .iter()
+        .all(|m| m.device != "/dev/vda4"));
+    assert_eq!(
+        result.handoff.mode_context.recovery_device.as_deref(),
+        Some("/dev/vda4")
+    );
+}
+
+#[test]
+fn recovery_is_ab_only_and_has_a_floor() {
+    let err = build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, recovery_request())
+        .expect_err("mutable recovery");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            recovery_size_mb: Some(512),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("small recovery");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.expectation.contains("recovery_size_mb"));
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            encrypt: vec!["recovery".to_string()],
+            ..recovery_request()
+        },
+    )
+    .expect_err("encrypted recovery");
+    assert_eq!(err.code.code(), "E003");
+}
@@ -0,0 +1,215 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::fat::fat_free_bytes;
+use recpart::profile::builtin_profile;
+use recpart::table::parse_sfdisk_json;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_free_space_plan, build_plan_with_layout_request, execute_plan};
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+// A Windows disk: 100MiB ESP, MSR and C:, with free space after C:.
+const WINDOWS_GPT: &str = r#"{
+   "partitiontable": {
+      "label": "gpt",
+      "id": "9A1F52E4-1B43-4E26-A2E4-3C1D8C0B7F10",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "firstlba": 34,
+      "lastlba": 524287966,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 204800, "type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "uuid": "4B1D2E6A-0C5F-4F8E-9B7A-2D6C1E3F4A5B", "name": "EFI system partition"},
+         {"node": "/dev/vda2", "start": 206848, "size": 32768, "type": "E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "uuid": "5C2E3F7B-1D60-4A9F-8C8B-3E7D2F405B6C", "name": "Microsoft reserved partition"},
+         {"node": "/dev/vda3", "start": 239616, "size": 209715200, "type": "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7", "uuid": "6D3F4A8C-2E71-4BA0-9D9C-4F8E3A516C7D", "name": "Basic data partition"}
+      ]
+   }
+}"#;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn reuse_request(selector: &str) -> LayoutRequest {
+    LayoutRequest {
+        reuse_esp: Some(selector.to_string()),
+        xbootldr_size_mb: Some(1024),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn reuse_plan(
+    mode: InstallMode,
+    request: LayoutRequest,
+) -> recpart::Result<recpart::PartitionPlan> {
+    build_free_space_plan(
+        fake_disk(),
+        parse_sfdisk_json(WINDOWS_GPT).expect("sfdisk json"),
+        &builtin_profile(mode).expect("profile"),
+        request,
+    )
+}
+
+// A FAT boot sector plus the first FAT, with `used` clusters allocated.
+fn fat_image(fat32: bool, used: u32) -> Vec<u8> {
+    let (sectors_per_cluster, reserved, root_entries, clusters): (u8, u16, u16, u32) = if fat32 {
+        (8, 32, 0, 70_000)
+    } else {
+        (4, 4, 512, 10_000)
+    };
+    let entry_bytes = if fat32 { 4 } else { 2 };
+    let fat_sectors = ((clusters + 2) * entry_bytes).div_ceil(512);
+    let total = u32::from(reserved)
+        + 2 * fat_sectors
+        + u32::from(root_entries) * 32 / 512
+        + clusters * u32::from(sectors_per_cluster);
+
+    let mut image = vec![0u8; (u32::from(reserved) + fat_sectors) as usize * 512];
+    image[11..13].copy_from_slice(&512u16.to_le_bytes());
+    image[13] = sectors_per_cluster;
+    image[14..16].copy_from_slice(&reserved.to_le_bytes());
+    image[16] = 2;
+    image[17..19].copy_from_slice(&root_entries.to_le_bytes());
+    if fat32 {
+        image[32..36].copy_from_slice(&total.to_le_bytes());
+        image[36..40].copy_from_slice(&fat_sectors.to_le_bytes());
+    } else {
+        image[19..21].copy_from_slice(&(total as u16).to_le_bytes());
+        image[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+    }
+    image[510] = 0x55;
+    image[511] = 0xaa;
+
+    let fat = usize::from(reserved) * 512;
+    for cluster in 0..used + 2 {
+        let pos = fat + cluster as usize * entry_bytes as usize;
+        image[pos..pos + entry_bytes as usize].fill(0xff);
+    }
+    image
+}
+
+#[test]
+fn the_existing_esp_is_kept_in_place_and_mounted_at_efi() {
+    let plan = reuse_plan(
+        InstallMode::Ab,
+        reuse_request("4b1d2e6a-0c5f-4f8e-9b7a-2d6c1e3f4a5b"),
+    )
+    .expect("plan");
+    let esp = &plan.partitions[0];
+    assert!(esp.reused);
+    assert_eq!(esp.index, 1);
+    assert_eq!(esp.mountpoint, "/efi");
+    assert_eq!(esp.start_sector, Some(2048));
+    assert_eq!(
+        esp.partuuid.as_deref(),
+        Some("4b1d2e6a-0c5f-4f8e-9b7a-2d6c1e3f4a5b")
+    );
+    assert_eq!(esp.fs_uuid, None);
+    assert_eq!(plan.resolved_layout.efi_size_mb, 100);
+
+    let indexes = plan.partitions[1..]
+        .iter()
+        .map(|p| p.index)
+        .collect::<Vec<_>>();
+    assert_eq!(indexes, vec![4, 5, 6, 7]);
+    assert!(!plan.sfdisk_script.contains("/dev/vda1 :"));
+    assert!(plan.sfdisk_script.contains("/dev/vda4 : "));
+}
+
+#[test]
+fn apply_mounts_the_reused_esp_without_formatting_it() {
+    let plan = reuse_plan(InstallMode::Mutable, reuse_request("/dev/vda1")).expect("plan");
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+
+    assert_eq!(result.reused_devices, vec!["/dev/vda1".to_string()]);
+    assert!(!result.formatted_devices.contains(&"/dev/vda1".to_string()));
+    assert!(result
+        .steps
+        .iter()
+        .all(|s| !(s.phase == "format" && s.command.ends_with("/dev/vda1"))));
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/efi" && m.device == "/dev/vda1"));
+    assert_eq!(
+        result.handoff.bootloader.efi_directory.as_deref(),
+        Some("/efi")
+    );
+}
+
+#[test]
+fn only_an_esp_on_a_kept_table_can_be_reused() {
+    for selector in ["/dev/vda3", "/dev/vda9"] {
+        let err = reuse_plan(InstallMode::Mutable, reuse_request(selector)).expect_err(selector);
+        assert_eq!(err.code.code(), "E001");
+    }
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        reuse_request("/dev/vda1"),
+    )
+    .expect_err("whole-disk plan");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = reuse_plan(
+        InstallMode::Mutable,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosGpt),
+            xbootldr_size_mb: None,
+            ..reuse_request("/dev/vda1")
+        },
+    )
+    .expect_err("bios firmware");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn a_dry_run_checks_a_present_esp() {
+    let mut plan = reuse_plan(InstallMode::Mutable, reuse_request("/dev/vda1")).expect("plan");
+    let dir = tempfile::tempdir().expect("tempdir");
+    plan.partitions[0].disk = dir.path().join("vda");
+    fs::write(dir.path().join("vda1"), vec![0u8; 1024 * 1024]).expect("write");
+
+    let err = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect_err("not vfat");
+    assert_eq!(err.code.code(), "E001");
+    assert_eq!(err.observed, "blkid reports type ''");
+}
+
+#[test]
+fn free_bytes_are_counted_from_the_fat() {
+    let free = fat_free_bytes(&mut Cursor::new(fat_image(true, 1_000))).expect("fat32");
+    assert_eq!(free, 69_000 * 8 * 512);
+
+    let free = fat_free_bytes(&mut Cursor::new(fat_image(false, 2_500))).expect("fat16");
+    assert_eq!(free, 7_500 * 4 * 512);
+
+    let mut ntfs = fat_image(true, 0);
+    ntfs[510] = 0;
+    assert!(fat_free_bytes(&mut Cursor::new(ntfs)).is_err());
+}
@@ -11,6 +11,8 @@ fn fake_disk(path: &str) -> DiskTarget {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
@@ -37,6 +39,7 @@ fn apply_rejects_protected_mount_root_even_in_dry_run() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/"),
+            luks_key: None,
         },
     )
     .expect_err("protected mount root should fail");
@@ -51,6 +54,8 @@ fn too_small_disk_fails_plan_generation() {
         size_bytes: 200 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     };
 
     let err = build_plan(tiny, InstallMode::Ab).expect_err("tiny disk must fail");
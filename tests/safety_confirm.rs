@@ -11,6 +11,8 @@ fn destructive_apply_requires_destroy_confirmation() {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     };
     let plan = build_plan(disk, InstallMode::Ab).expect("plan");
 
@@ -20,6 +22,7 @@ fn destructive_apply_requires_destroy_confirmation() {
             dry_run: false,
             confirm_token: Some("NOPE".to_string()),
             mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
         },
     )
     .expect_err("must fail");
@@ -0,0 +1,234 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::exec::accepted_exit_status;
+use recpart::profile::builtin_profile;
+use recpart::resize::{ext4_min_size_bytes, ntfs_min_size_bytes};
+use recpart::table::parse_sfdisk_json;
+use recpart::types::{
+    ApplyOptions, Arch, DiskTarget, InstallMode, LayoutRequest, PartitionPlan, ShrinkCandidate,
+};
+use recpart::{build_free_space_plan, build_shrink_plan, execute_plan};
+use std::path::PathBuf;
+
+// Windows filling the disk: ESP, MSR, C: and a recovery partition at the end.
+const FULL_DISK: &str = r#"{
+   "partitiontable": {
+      "label": "gpt",
+      "id": "9A1F52E4-1B43-4E26-A2E4-3C1D8C0B7F10",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "firstlba": 34,
+      "lastlba": 524287966,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 204800, "type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"},
+         {"node": "/dev/vda2", "start": 206848, "size": 32768, "type": "E3C9E316-0B5C-4DB8-817D-F92DF00215AE"},
+         {"node": "/dev/vda3", "start": 239616, "size": 522999808, "type": "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7"},
+         {"node": "/dev/vda4", "start": 523239424, "size": 1046528, "type": "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC"}
+      ]
+   }
+}"#;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * GIB,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn candidate(filesystem: &str) -> ShrinkCandidate {
+    ShrinkCandidate {
+        device: "/dev/vda3".to_string(),
+        filesystem: filesystem.to_string(),
+        min_size_bytes: 60 * GIB,
+    }
+}
+
+fn shrink_request(shrink_size_mb: Option<u32>) -> LayoutRequest {
+    LayoutRequest {
+        shrink_partition: Some("/dev/vda3".to_string()),
+        shrink_size_mb,
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn shrink_plan(
+    mode: InstallMode,
+    candidate: &ShrinkCandidate,
+    request: LayoutRequest,
+) -> recpart::Result<PartitionPlan> {
+    build_shrink_plan(
+        fake_disk(),
+        parse_sfdisk_json(FULL_DISK).expect("sfdisk json"),
+        candidate,
+        &builtin_profile(mode).expect("profile"),
+        request,
+    )
+}
+
+fn apply_options(dry_run: bool) -> ApplyOptions {
+    ApplyOptions {
+        dry_run,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: None,
+    }
+}
+
+#[test]
+fn by_default_the_partition_shrinks_just_enough_for_the_layout() {
+    let plan = shrink_plan(
+        InstallMode::Mutable,
+        &candidate("ntfs"),
+        shrink_request(None),
+    )
+    .expect("plan");
+    let shrink = plan.shrink.as_ref().expect("shrink");
+    assert_eq!(shrink.index, 3);
+    assert_eq!(shrink.end_sector, 523239423);
+    // 9216MB (1GiB ESP + 8GiB root) before the recovery partition.
+    assert_eq!(shrink.new_end_sector, 523239423 - 9216 * 2048);
+    assert_eq!(
+        shrink.new_size_bytes,
+        (shrink.new_end_sector - 239616 + 1) * 512
+    );
+    assert_eq!(
+        plan.preserved_partitions[2].end_sector,
+        shrink.new_end_sector
+    );
+
+    let gap = plan.free_space.expect("gap");
+    assert_eq!(gap.start_sector, shrink.new_end_sector + 1);
+    assert_eq!(gap.size_mb, 9216);
+    let indexes = plan.partitions.iter().map(|p| p.index).collect::<Vec<_>>();
+    assert_eq!(indexes, vec![5, 6]);
+}
+
+#[test]
+fn an_explicit_size_leaves_the_rest_to_the_layout() {
+    let plan = shrink_plan(
+        InstallMode::Ab,
+        &candidate("ntfs"),
+        shrink_request(Some(100 * 1024)),
+    )
+    .expect("plan");
+    let shrink = plan.shrink.expect("shrink");
+    assert_eq!(shrink.new_size_bytes, 100 * GIB);
+    assert_eq!(plan.partitions.last().unwrap().end_sector, Some(523239423));
+
+    let err = shrink_plan(
+        InstallMode::Ab,
+        &candidate("ntfs"),
+        shrink_request(Some(50 * 1024)),
+    )
+    .expect_err("below the NTFS minimum");
+    assert_eq!(err.code.code(), "E003");
+    assert_eq!(err.observed, "it needs at least 61440MB");
+
+    let err = shrink_plan(
+        InstallMode::Ab,
+        &candidate("ntfs"),
+        shrink_request(Some(300 * 1024)),
+    )
+    .expect_err("larger than the partition");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn resize_runs_before_partitioning() {
+    let plan = shrink_plan(
+        InstallMode::Mutable,
+        &candidate("ext4"),
+        shrink_request(None),
+    )
+    .expect("plan");
+    let shrink = plan.shrink.clone().expect("shrink");
+    let result = execute_plan(&plan, &apply_options(true)).expect("dry-run");
+    let steps = result
+        .steps
+        .iter()
+        .map(|s| (s.phase.as_str(), s.command.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps[..4],
+        [
+            ("resize", "e2fsck -f -y /dev/vda3".to_string()),
+            (
+                "resize",
+                format!("resize2fs /dev/vda3 {}K", shrink.new_size_bytes / 1024)
+            ),
+            ("resize", "sfdisk -N 3 /dev/vda".to_string()),
+            ("partition", "sfdisk --append /dev/vda".to_string()),
+        ]
+    );
+
+    let ntfs = shrink_plan(
+        InstallMode::Mutable,
+        &candidate("ntfs"),
+        shrink_request(None),
+    )
+    .expect("plan");
+    let result = execute_plan(&ntfs, &apply_options(true)).expect("dry-run");
+    let ntfs_shrink = ntfs.shrink.as_ref().expect("shrink");
+    assert_eq!(
+        result.steps[0].command,
+        format!(
+            "ntfsresize --no-progress-bar --size {} /dev/vda3",
+            ntfs_shrink.new_size_bytes
+        )
+    );
+
+    // e2fsck reports corrected errors with exit status 1.
+    assert!(accepted_exit_status("e2fsck", 1));
+    assert!(!accepted_exit_status("e2fsck", 4));
+    assert!(!accepted_exit_status("resize2fs", 1));
+
+    let err = execute_plan(&plan, &apply_options(false)).expect_err("no confirmation");
+    assert_eq!(err.code.code(), "E004");
+}
+
+#[test]
+fn shrink_plans_need_a_matching_candidate() {
+    let err = build_free_space_plan(
+        fake_disk(),
+        parse_sfdisk_json(FULL_DISK).expect("sfdisk json"),
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        shrink_request(None),
+    )
+    .expect_err("no candidate");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = shrink_plan(
+        InstallMode::Mutable,
+        &ShrinkCandidate {
+            device: "/dev/vda3".to_string(),
+            filesystem: "btrfs".to_string(),
+            min_size_bytes: 0,
+        },
+        shrink_request(None),
+    )
+    .expect_err("btrfs");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn minimum_sizes_come_from_the_resize_tools() {
+    let resize2fs =
+        "resize2fs 1.47.0 (5-Feb-2023)\nEstimated minimum size of the filesystem: 1234567\n";
+    let tune2fs = "Block count:              26214400\nBlock size:               4096\n";
+    assert_eq!(
+        ext4_min_size_bytes(resize2fs, tune2fs),
+        Some(1234567 * 4096)
+    );
+
+    let ntfsresize = "ntfsresize v2022.10.3 (libntfs-3g)\nDevice name        : /dev/vda3\n\
+        Checking filesystem consistency ...\n\
+        You might resize at 41234567168 bytes or 41235 MB (freeing 58765 MB).\n";
+    assert_eq!(ntfs_min_size_bytes(ntfsresize), Some(41234567168));
+    assert_eq!(ntfs_min_size_bytes("ERROR: volume is dirty"), None);
+}
@@ -0,0 +1,133 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::geometry::last_usable_sector;
+use recpart::types::{DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::SizeExpr;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn expr(value: &str) -> Option<SizeExpr> {
+    Some(value.parse().expect("size expression"))
+}
+
+#[test]
+fn size_expressions_parse_binary_units_and_render_canonically() {
+    for (input, rendered) in [
+        ("20G", "20G"),
+        ("512MiB", "512M"),
+        ("2048", "2G"),
+        ("1048576K", "1G"),
+        ("25%", "25%"),
+        ("min:8G,max:40G", "min:8G,max:40G"),
+        ("10%, min:4GiB", "10%,min:4G"),
+        ("remaining,max:100G", "remaining,max:100G"),
+    ] {
+        let parsed = input.parse::<SizeExpr>().expect(input);
+        assert_eq!(parsed.to_string(), rendered, "{input}");
+    }
+}
+
+#[test]
+fn invalid_size_expressions_are_rejected() {
+    for input in ["", "12Q", "0%", "101%", "512K", "min:40G,max:8G", "1G,2G"] {
+        assert!(input.parse::<SizeExpr>().is_err(), "{input} should fail");
+    }
+}
+
+#[test]
+fn percent_and_absolute_expressions_resolve_into_layout() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            efi_size: expr("512MiB"),
+            root_a_size: expr("10%"),
+            root_b_size: expr("10%,max:16G"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    assert_eq!(plan.resolved_layout.efi_size_mb, 512);
+    assert_eq!(plan.resolved_layout.root_a_size_mb, Some(25_600));
+    assert_eq!(plan.resolved_layout.root_b_size_mb, Some(16_384));
+    assert_eq!(plan.resolved_layout.state_size_mb, None);
+}
+
+#[test]
+fn bounds_clamp_remaining_space_partitions() {
+    let ab = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            state_size: expr("min:8G,max:40G"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("ab plan");
+    assert_eq!(ab.resolved_layout.state_size_mb, Some(40_960));
+
+    let mutable = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            root_size: expr("min:8G,max:1T"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("mutable plan");
+    assert_eq!(mutable.resolved_layout.root_size_mb, None);
+    assert_eq!(
+        mutable.partitions.last().and_then(|p| p.end_sector),
+        Some(last_usable_sector(&fake_disk(), Firmware::Uefi))
+    );
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            root_size: expr("min:300G"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("minimum beyond remaining space must fail");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn mb_and_expression_for_same_field_conflict() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            efi_size_mb: Some(512),
+            efi_size: expr("1G"),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("conflicting fields must fail");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn expressions_round_trip_through_layout_request_json() {
+    let request = LayoutRequest {
+        state_size: expr("25%,min:8G"),
+        ..LayoutRequest::default()
+    };
+    let json = serde_json::to_value(&request).expect("json");
+    assert_eq!(json["state_size"], "25%,min:8G");
+
+    let parsed: LayoutRequest = serde_json::from_value(json).expect("parse");
+    assert_eq!(parsed, request);
+}
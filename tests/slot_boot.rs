@@ -0,0 +1,115 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::types::{
+    AbSlot, ApplyOptions, Arch, DiskTarget, Firmware, GptAttribute, InstallMode, LayoutRequest,
+};
+use recpart::{build_plan_with_layout_request, execute_plan, PartitionPlan};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn slot_boot_request() -> LayoutRequest {
+    LayoutRequest {
+        slot_boot: true,
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn dry_run(plan: &PartitionPlan) -> recpart::ApplyResult {
+    execute_plan(
+        plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run")
+}
+
+#[test]
+fn each_slot_gets_a_boot_partition_and_the_esp_moves_to_efi() {
+    let plan = build_plan_with_layout_request(fake_disk(), InstallMode::Ab, slot_boot_request())
+        .expect("plan");
+    assert_eq!(plan.resolved_layout.slot_boot_size_mb, Some(1024));
+    assert_eq!(plan.resolved_layout.efi_size_mb, 256);
+
+    let layout = plan
+        .partitions
+        .iter()
+        .map(|p| (p.name.as_str(), p.mountpoint.as_str(), p.gpt_type.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(layout[0], ("efi", "/efi", dps::ESP));
+    assert_eq!(layout[1], ("boot_a", "/slots/A/boot", dps::LINUX_DATA));
+    assert_eq!(layout[2], ("boot_b", "/slots/B/boot", dps::LINUX_DATA));
+    assert_eq!(layout[3].0, "root_a");
+    assert_eq!(plan.partitions[1].size_mb, Some(1024));
+    assert_eq!(plan.partitions[1].label, "BOOT_A");
+    assert!(plan.partitions[1].attributes.is_empty());
+    assert_eq!(plan.partitions[2].attributes, vec![GptAttribute::NoAuto]);
+}
+
+#[test]
+fn the_target_slots_boot_is_mounted_and_the_pairing_reported() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            install_slot: Some(AbSlot::B),
+            ..slot_boot_request()
+        },
+    )
+    .expect("plan");
+    let result = dry_run(&plan);
+    let mounts = result
+        .handoff
+        .mount_map
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(mounts[0], ("/mnt/sysroot", "/dev/vda5"));
+    assert!(mounts.contains(&("/mnt/sysroot/boot", "/dev/vda3")));
+    assert!(mounts.contains(&("/mnt/sysroot/efi", "/dev/vda1")));
+    assert!(mounts.iter().all(|(_, device)| *device != "/dev/vda2"));
+
+    let context = &result.handoff.mode_context;
+    assert_eq!(context.boot_a_device.as_deref(), Some("/dev/vda2"));
+    assert_eq!(context.slot_a_device.as_deref(), Some("/dev/vda4"));
+    assert_eq!(context.boot_b_device.as_deref(), Some("/dev/vda3"));
+    assert_eq!(context.slot_b_device.as_deref(), Some("/dev/vda5"));
+}
+
+#[test]
+fn slot_boot_excludes_xbootldr_bios_and_mutable() {
+    for (mode, request) in [
+        (
+            InstallMode::Ab,
+            LayoutRequest {
+                xbootldr_size_mb: Some(1024),
+                ..slot_boot_request()
+            },
+        ),
+        (
+            InstallMode::Ab,
+            LayoutRequest {
+                firmware: Some(Firmware::BiosGpt),
+                ..slot_boot_request()
+            },
+        ),
+        (InstallMode::Mutable, slot_boot_request()),
+    ] {
+        let err = build_plan_with_layout_request(fake_disk(), mode, request).expect_err("rejected");
+        assert_eq!(err.code.code(), "E003");
+    }
+}
@@ -0,0 +1,363 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::plan::template_device;
+use recpart::preflight::check_partition_unmounted;
+use recpart::profile::builtin_profile;
+use recpart::table::{inspect_table, parse_blkid_export, parse_sfdisk_json};
+use recpart::types::{
+    AbSlot, ApplyOptions, Arch, DiskInspectResult, DiskTarget, Firmware, GptAttribute, InstallMode,
+    LayoutRequest,
+};
+use recpart::{build_plan_with_layout_request, build_slot_reinstall_plan, execute_plan};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// A default A/B install on a 250GiB disk.
+const AB_INSTALL: &str = r#"{
+   "partitiontable": {
+      "label": "gpt",
+      "id": "2B1A7D3C-5E4F-4A6B-8C9D-0E1F2A3B4C5D",
+      "device": "/dev/vda",
+      "unit": "sectors",
+      "firstlba": 34,
+      "lastlba": 524287966,
+      "partitions": [
+         {"node": "/dev/vda1", "start": 2048, "size": 2097152, "type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "uuid": "0B6E0F35-4C1D-8E2A-9F3B-1C2D3E4F5A6B", "name": "efi"},
+         {"node": "/dev/vda2", "start": 2099200, "size": 41943040, "type": "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709", "uuid": "1C7F1A46-5D2E-8F3B-A04C-2D3E4F5A6B7C", "name": "root_a"},
+         {"node": "/dev/vda3", "start": 44042240, "size": 41943040, "type": "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709", "uuid": "2D8A2B57-6E3F-804C-B15D-3E4F5A6B7C8D", "name": "root_b", "attrs": "GUID:63"},
+         {"node": "/dev/vda4", "start": 85985280, "size": 438300672, "type": "0FC63DAF-8483-4772-8E79-3D69D8477DE4", "uuid": "3E9B3C68-7F40-815D-826E-4F5A6B7C8D9E", "name": "state"}
+      ]
+   }
+}"#;
+
+// Slot B's filesystem is gone; its GPT name still identifies it.
+const AB_BLKID: &str = "DEVNAME=/dev/vda1
+LABEL=EFI
+UUID=4E1A-2B3C
+TYPE=vfat
+
+DEVNAME=/dev/vda2
+LABEL=ROOT_A
+UUID=5f6a7b8c-9d0e-4f1a-8b2c-3d4e5f6a7b8c
+TYPE=ext4
+
+DEVNAME=/dev/vda4
+LABEL=STATE
+UUID=6a7b8c9d-0e1f-4a2b-9c3d-4e5f6a7b8c9d
+TYPE=ext4
+";
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn inspection(blkid: &str) -> DiskInspectResult {
+    inspect_table(
+        fake_disk(),
+        Some(parse_sfdisk_json(AB_INSTALL).expect("sfdisk json")),
+        &parse_blkid_export(blkid),
+    )
+}
+
+fn reinstall_request(slot: AbSlot) -> LayoutRequest {
+    LayoutRequest {
+        reinstall_slot: Some(slot),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn reinstall(blkid: &str, request: LayoutRequest) -> recpart::Result<recpart::PartitionPlan> {
+    build_slot_reinstall_plan(
+        inspection(blkid),
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        request,
+    )
+}
+
+#[test]
+fn only_the_chosen_slot_is_formatted() {
+    let plan = reinstall(AB_BLKID, reinstall_request(AbSlot::B)).expect("plan");
+    assert_eq!(plan.install_slot, Some(AbSlot::B));
+    assert!(plan.sfdisk_scripts.is_empty());
+    let reused = plan
+        .partitions
+        .iter()
+        .map(|p| (p.name.as_str(), p.index, p.reused))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reused,
+        vec![
+            ("efi", 1, true),
+            ("root_a", 2, true),
+            ("root_b", 3, false),
+            ("state", 4, true),
+        ]
+    );
+    assert_eq!(plan.partitions[0].fs_uuid.as_deref(), Some("4E1A-2B3C"));
+    assert_eq!(
+        plan.partitions[2].partuuid.as_deref(),
+        Some("2d8a2b57-6e3f-804c-b15d-3e4f5a6b7c8d")
+    );
+    assert_eq!(plan.resolved_layout.state_size_mb, Some(214014));
+    assert_eq!(plan.partitions[1].attributes, vec![GptAttribute::NoAuto]);
+    assert!(plan.partitions[2].attributes.is_empty());
+
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    assert!(result.steps.iter().all(|s| s.program != "wipefs"));
+    let sfdisk = result
+        .steps
+        .iter()
+        .filter(|s| s.program == "sfdisk")
+        .map(|s| s.args.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sfdisk,
+        vec![
+            vec!["--part-attrs", "/dev/vda", "2", "GUID:63"],
+            vec!["--part-attrs", "/dev/vda", "3", ""],
+        ]
+    );
+    let formats = result
+        .steps
+        .iter()
+        .filter(|s| s.phase == "format")
+        .map(|s| s.command.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        formats,
+        vec![format!(
+            "mkfs.ext4 -F -L ROOT_B -U {} /dev/vda3",
+            plan.partitions[2].fs_uuid.as_deref().unwrap()
+        )]
+    );
+    assert_eq!(result.formatted_devices, vec!["/dev/vda3"]);
+    assert_eq!(
+        result.reused_devices,
+        vec!["/dev/vda1", "/dev/vda2", "/dev/vda4"]
+    );
+
+    let mounts = result
+        .mounted
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mounts,
+        vec![
+            ("/mnt/sysroot", "/dev/vda3"),
+            ("/mnt/sysroot/boot", "/dev/vda1"),
+            ("/mnt/sysroot/state", "/dev/vda4"),
+        ]
+    );
+    let context = &result.handoff.mode_context;
+    assert_eq!(context.install_target_slot.as_deref(), Some("B"));
+    assert_eq!(context.inactive_slot_hint.as_deref(), Some("A"));
+    assert!(context.notes[0].contains("slot A is marked no-auto"));
+}
+
+#[test]
+fn only_the_no_auto_bit_of_the_slots_is_rewritten() {
+    let part_attrs = |inspected: DiskInspectResult, slot: AbSlot| {
+        let plan = build_slot_reinstall_plan(
+            inspected,
+            &builtin_profile(InstallMode::Ab).expect("profile"),
+            reinstall_request(slot),
+        )
+        .expect("plan");
+        let result = execute_plan(
+            &plan,
+            &ApplyOptions {
+                dry_run: true,
+                confirm_token: None,
+                mount_root: PathBuf::from("/mnt"),
+                luks_key: None,
+            },
+        )
+        .expect("dry-run");
+        result
+            .steps
+            .iter()
+            .filter(|s| s.program == "sfdisk")
+            .map(|s| s.args.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let mut inspected = inspection(AB_BLKID);
+    inspected.partitions[1].attrs = Some("RequiredPartition GUID:48,59".to_string());
+    inspected.partitions[2].attrs = Some("GUID:59,60,63".to_string());
+    assert_eq!(
+        part_attrs(inspected, AbSlot::B),
+        vec![
+            vec![
+                "--part-attrs",
+                "/dev/vda",
+                "2",
+                "RequiredPartition GUID:48,59,63"
+            ],
+            vec!["--part-attrs", "/dev/vda", "3", "GUID:59,60"],
+        ]
+    );
+
+    // Slot B already carries no-auto, so reinstalling A changes nothing.
+    assert!(part_attrs(inspection(AB_BLKID), AbSlot::A).is_empty());
+}
+
+#[test]
+fn the_existing_layout_must_match_the_ab_profile() {
+    let err = reinstall(
+        &AB_BLKID.replace("TYPE=ext4\n", "TYPE=btrfs\n"),
+        reinstall_request(AbSlot::A),
+    )
+    .expect_err("state is btrfs");
+    assert_eq!(err.code.code(), "E001");
+    assert_eq!(err.observed, "'/dev/vda4' holds btrfs");
+
+    let plan = reinstall(
+        &AB_BLKID.replace("TYPE=ext4\n", "TYPE=btrfs\n"),
+        LayoutRequest {
+            filesystems: BTreeMap::from([("state".to_string(), "btrfs".to_string())]),
+            ..reinstall_request(AbSlot::B)
+        },
+    )
+    .expect("matching override");
+    assert!(!plan.partitions[3].subvolumes.is_empty());
+
+    let mut unlabelled = inspection(AB_BLKID);
+    unlabelled.partitions[3].partlabel = None;
+    unlabelled.partitions[3].label = None;
+    let err = build_slot_reinstall_plan(
+        unlabelled,
+        &builtin_profile(InstallMode::Ab).expect("profile"),
+        reinstall_request(AbSlot::B),
+    )
+    .expect_err("no state partition");
+    assert_eq!(err.code.code(), "E001");
+    assert!(err.expectation.contains("state partition"));
+}
+
+#[test]
+fn optional_partitions_on_disk_must_be_requested() {
+    // The disk was installed with --slot-boot.
+    let mut inspected = inspection(AB_BLKID);
+    for (slot, index) in [("a", 5), ("b", 6)] {
+        let mut boot = inspected.partitions[0].clone();
+        boot.device = format!("/dev/vda{index}");
+        boot.index = index;
+        boot.partlabel = Some(format!("boot_{slot}"));
+        boot.label = Some(format!("BOOT_{}", slot.to_ascii_uppercase()));
+        inspected.partitions.push(boot);
+    }
+    let profile = builtin_profile(InstallMode::Ab).expect("profile");
+
+    let err = build_slot_reinstall_plan(inspected.clone(), &profile, reinstall_request(AbSlot::B))
+        .expect_err("slot boot left out");
+    assert_eq!(err.code.code(), "E001");
+    assert_eq!(
+        err.observed,
+        "disk '/dev/vda' holds boot_a, boot_b, left out of the request"
+    );
+
+    let plan = build_slot_reinstall_plan(
+        inspected,
+        &profile,
+        LayoutRequest {
+            slot_boot: true,
+            ..reinstall_request(AbSlot::B)
+        },
+    )
+    .expect("plan");
+    let boot_b = plan.partitions.iter().find(|p| p.name == "boot_b").unwrap();
+    assert_eq!((boot_b.index, boot_b.reused), (6, false));
+    assert_eq!(plan.partitions[0].mountpoint, "/efi");
+}
+
+#[test]
+fn reinstalls_keep_the_layout_as_installed() {
+    let err = reinstall(
+        AB_BLKID,
+        LayoutRequest {
+            encrypt: vec!["state".to_string()],
+            ..reinstall_request(AbSlot::B)
+        },
+    )
+    .expect_err("encrypt");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = reinstall(
+        AB_BLKID,
+        LayoutRequest {
+            install_slot: Some(AbSlot::A),
+            ..reinstall_request(AbSlot::B)
+        },
+    )
+    .expect_err("conflicting install slot");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = reinstall(
+        AB_BLKID,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosMbr),
+            ..reinstall_request(AbSlot::B)
+        },
+    )
+    .expect_err("gpt disk with mbr firmware");
+    assert_eq!(err.code.code(), "E001");
+
+    let err = build_slot_reinstall_plan(
+        inspection(AB_BLKID),
+        &builtin_profile(InstallMode::Mutable).expect("profile"),
+        reinstall_request(AbSlot::B),
+    )
+    .expect_err("mutable");
+    assert_eq!(err.code.code(), "E003");
+
+    let err =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, reinstall_request(AbSlot::B))
+            .expect_err("whole-disk plan");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn a_mounted_or_running_target_slot_is_refused() {
+    let plan = reinstall(AB_BLKID, reinstall_request(AbSlot::B)).expect("plan");
+    let target = plan
+        .partitions
+        .iter()
+        .find(|p| !p.reused)
+        .map(template_device)
+        .expect("target slot");
+    assert_eq!(target, "/dev/vda3");
+
+    // The kept ESP and STATE may stay mounted.
+    let kept = "/dev/vda1 /boot vfat rw 0 0\n/dev/vda4 /state ext4 rw 0 0\n";
+    check_partition_unmounted(&target, kept).expect("only kept partitions mounted");
+
+    let err = check_partition_unmounted(
+        &target,
+        &format!("{kept}/dev/vda3 /mnt/slot\\040b ext4 rw 0 0\n"),
+    )
+    .expect_err("mounted slot");
+    assert_eq!(err.code.code(), "E001");
+    assert_eq!(err.observed, "'/dev/vda3' is mounted at /mnt/slot b");
+
+    let err = check_partition_unmounted(&target, &format!("/dev/vda3 / ext4 ro 0 0\n{kept}"))
+        .expect_err("running slot");
+    assert_eq!(err.code.code(), "E001");
+    assert!(err.remediation.contains("Boot the other slot"));
+}
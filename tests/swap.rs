@@ -0,0 +1,135 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::build_plan_with_layout_request;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::policy::hibernation_swap_mb;
+use recpart::preflight::parse_mem_total_mb;
+use recpart::types::{ApplyOptions, DiskTarget, InstallMode, LayoutRequest};
+use recpart::PartitionPlan;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn dry_run_opts() -> ApplyOptions {
+    ApplyOptions {
+        dry_run: true,
+        confirm_token: None,
+        mount_root: PathBuf::from("/mnt"),
+        luks_key: None,
+    }
+}
+
+fn fs_uuid(plan: &PartitionPlan, name: &str) -> String {
+    let part = plan.partitions.iter().find(|p| p.name == name).unwrap();
+    part.fs_uuid.clone().unwrap()
+}
+
+#[test]
+fn ab_swap_is_planned_before_state_and_formatted_with_mkswap() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            swap_size_mb: Some(8_192),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    let labels = plan
+        .partitions
+        .iter()
+        .map(|p| p.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["EFI", "ROOT_A", "ROOT_B", "SWAP", "STATE"]);
+    assert!(plan
+        .sfdisk_script
+        .contains(&format!(" size=16777216, type={},", dps::SWAP)));
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    assert!(result
+        .steps
+        .iter()
+        .any(|s| s.command == format!("mkswap -L SWAP -U {} /dev/vda4", fs_uuid(&plan, "swap"))));
+    assert!(result.mounted.iter().all(|m| m.device != "/dev/vda4"));
+    assert!(result
+        .mounted
+        .iter()
+        .any(|m| m.path == "/mnt/sysroot/state" && m.device == "/dev/vda5"));
+    assert_eq!(result.handoff.swap_devices, vec!["/dev/vda4"]);
+    assert!(result.partition_map.iter().any(|p| p.filesystem == "swap"));
+}
+
+#[test]
+fn mutable_hibernation_swap_precedes_root() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            swap_hibernate_memory_mb: Some(16_384),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan");
+
+    assert_eq!(plan.resolved_layout.swap_size_mb, Some(20_480));
+    let labels = plan
+        .partitions
+        .iter()
+        .map(|p| p.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["EFI", "SWAP", "ROOT"]);
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    assert_eq!(result.mounted[0].path, "/mnt/sysroot");
+    assert_eq!(result.mounted[0].device, "/dev/vda3");
+}
+
+#[test]
+fn default_plans_have_no_swap() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, LayoutRequest::default())
+            .expect("plan");
+    assert!(plan.partitions.iter().all(|p| p.filesystem != "swap"));
+
+    let result = execute_plan(&plan, &dry_run_opts()).expect("dry-run");
+    assert!(result.handoff.swap_devices.is_empty());
+}
+
+#[test]
+fn hibernation_sizing_is_ram_plus_sqrt_ram() {
+    assert_eq!(hibernation_swap_mb(16_384), 20 * 1024);
+    assert_eq!(hibernation_swap_mb(8_000), 11 * 1024);
+    assert_eq!(hibernation_swap_mb(512), 2 * 1024);
+}
+
+#[test]
+fn meminfo_total_is_parsed_in_mb() {
+    let meminfo = "MemTotal:       16318480 kB\nMemFree:         1234 kB\n";
+    assert_eq!(parse_mem_total_mb(meminfo), Some(15_937));
+    assert_eq!(parse_mem_total_mb("MemFree: 1 kB\n"), None);
+}
+
+#[test]
+fn auto_swap_conflicts_with_explicit_size() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            swap_size_mb: Some(4_096),
+            swap_hibernate_memory_mb: Some(8_192),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect_err("conflicting swap sizing must fail");
+    assert_eq!(err.code.code(), "E003");
+}
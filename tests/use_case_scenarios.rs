@@ -9,6 +9,8 @@ fn fake_disk() -> DiskTarget {
         size_bytes: 250 * 1024 * 1024 * 1024,
         logical_sector_bytes: 512,
         physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
     }
 }
 
@@ -32,6 +34,7 @@ fn uc_002_ab_dry_run_has_slot_context_and_state_mount() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt/recpart-uc"),
+            luks_key: None,
         },
     )
     .expect("ab dry-run should succeed");
@@ -60,6 +63,7 @@ fn uc_005_mutable_dry_run_has_single_root_handoff() {
             dry_run: true,
             confirm_token: None,
             mount_root: PathBuf::from("/mnt/recpart-uc"),
+            luks_key: None,
         },
     )
     .expect("mutable dry-run should succeed");
@@ -0,0 +1,125 @@
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_plan_with_layout_request, execute_plan, PartitionPlan};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn seeded_plan(seed: Option<&str>, firmware: Firmware) -> PartitionPlan {
+    build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            uuid_seed: seed.map(str::to_string),
+            firmware: Some(firmware),
+            arch: Some(Arch::X86_64),
+            ..LayoutRequest::default()
+        },
+    )
+    .expect("plan")
+}
+
+#[test]
+fn seed_is_recorded_and_drives_every_uuid() {
+    let plan = seeded_plan(Some("machine-1"), Firmware::Uefi);
+    assert_eq!(plan.uuid_seed, "machine-1");
+    assert_eq!(plan, seeded_plan(Some("machine-1"), Firmware::Uefi));
+
+    let other = seeded_plan(Some("machine-2"), Firmware::Uefi);
+    for (a, b) in plan.partitions.iter().zip(&other.partitions) {
+        assert_ne!(a.partuuid, b.partuuid);
+        assert_ne!(a.fs_uuid, b.fs_uuid);
+    }
+}
+
+#[test]
+fn default_seed_is_random_and_recorded() {
+    let plan = seeded_plan(None, Firmware::Uefi);
+    assert_eq!(plan.uuid_seed.len(), 32);
+    assert!(plan.uuid_seed.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert_eq!(plan.layout_request.uuid_seed, None);
+
+    // Identical inputs on another machine get other ids.
+    let other = seeded_plan(None, Firmware::Uefi);
+    assert_ne!(plan.uuid_seed, other.uuid_seed);
+    assert_ne!(plan.partitions[0].partuuid, other.partitions[0].partuuid);
+
+    // The recorded seed reproduces the plan.
+    assert_eq!(
+        plan.partitions,
+        seeded_plan(Some(&plan.uuid_seed), Firmware::Uefi).partitions
+    );
+}
+
+#[test]
+fn partuuids_are_written_to_the_sfdisk_script() {
+    let plan = seeded_plan(Some("machine-1"), Firmware::Uefi);
+    assert!(plan.sfdisk_script.contains("\nlabel-id: "));
+    for part in &plan.partitions {
+        let partuuid = part.partuuid.as_deref().unwrap();
+        assert_eq!(partuuid.len(), 36);
+        assert_eq!(&partuuid[14..15], "8");
+        assert!(plan.sfdisk_script.contains(&format!(", uuid={partuuid}")));
+    }
+}
+
+#[test]
+fn mkfs_receives_the_planned_filesystem_uuids() {
+    let plan = seeded_plan(Some("machine-1"), Firmware::Uefi);
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    let formats = result
+        .steps
+        .iter()
+        .filter(|s| s.phase == "format")
+        .map(|s| s.command.clone())
+        .collect::<Vec<_>>();
+
+    let esp_id = plan.partitions[0].fs_uuid.as_deref().unwrap();
+    assert_eq!(esp_id.len(), 9);
+    assert_eq!(
+        formats[0],
+        format!(
+            "mkfs.vfat -F 32 -n EFI -i {} /dev/vda1",
+            esp_id.replace('-', "")
+        )
+    );
+    assert_eq!(
+        formats[1],
+        format!(
+            "mkfs.ext4 -F -L ROOT_A -U {} /dev/vda2",
+            plan.partitions[1].fs_uuid.as_deref().unwrap()
+        )
+    );
+}
+
+#[test]
+fn mbr_partuuids_follow_the_disk_identifier() {
+    let plan = seeded_plan(Some("machine-1"), Firmware::BiosMbr);
+    let label_id = plan
+        .sfdisk_script
+        .lines()
+        .find_map(|line| line.strip_prefix("label-id: 0x"))
+        .expect("label-id");
+    assert_eq!(
+        plan.partitions[1].partuuid.as_deref(),
+        Some(format!("{label_id}-02").as_str())
+    );
+    assert!(!plan.sfdisk_script.contains("uuid="));
+}
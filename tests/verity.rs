@@ -0,0 +1,139 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::policy::{required_min_mb, verity_size_mb};
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_plan_with_layout_request, execute_plan};
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn verity_request(arch: Arch) -> LayoutRequest {
+    LayoutRequest {
+        verity: true,
+        arch: Some(arch),
+        ..LayoutRequest::default()
+    }
+}
+
+#[test]
+fn each_slot_gets_a_verity_partition_sized_from_the_slot() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            root_a_size_mb: Some(30 * 1024),
+            ..verity_request(Arch::X86_64)
+        },
+    )
+    .expect("plan");
+    let names = plan
+        .partitions
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "efi",
+            "root_a",
+            "root_b",
+            "root_a_verity",
+            "root_b_verity",
+            "state"
+        ]
+    );
+
+    let layout = &plan.resolved_layout;
+    assert_eq!(verity_size_mb(30 * 1024), 480);
+    assert_eq!(verity_size_mb(1000), 16);
+    assert_eq!(layout.root_a_verity_size_mb, Some(480));
+    assert_eq!(
+        layout.root_b_verity_size_mb,
+        layout.root_b_size_mb.map(verity_size_mb)
+    );
+    assert_eq!(plan.partitions[3].size_mb, Some(480));
+    assert_eq!(
+        plan.partitions[3].gpt_type,
+        "2C7357ED-EBD2-46D9-AEC1-23D437EC2BF5"
+    );
+    assert_eq!(plan.partitions[3].fs_uuid, None);
+
+    let without = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            verity: false,
+            root_a_size_mb: Some(30 * 1024),
+            ..verity_request(Arch::X86_64)
+        },
+    )
+    .expect("plan");
+    assert_eq!(
+        required_min_mb(layout),
+        required_min_mb(&without.resolved_layout) + 480 + layout.root_b_verity_size_mb.unwrap()
+    );
+}
+
+#[test]
+fn verity_partitions_stay_unformatted_and_are_reported() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Ab, verity_request(Arch::Arm64))
+            .expect("plan");
+    assert_eq!(
+        plan.partitions[4].gpt_type,
+        "DF3300CE-D69F-4C92-978C-9BFB0F38D820"
+    );
+
+    let result = execute_plan(
+        &plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run");
+    assert!(result
+        .steps
+        .iter()
+        .all(|s| !s.command.ends_with("/dev/vda4") && !s.command.ends_with("/dev/vda5")));
+    assert!(result
+        .handoff
+        .mount_map
+        .iter()
+        .all(|m| m.device != "/dev/vda4" && m.device != "/dev/vda5"));
+    let context = &result.handoff.mode_context;
+    assert_eq!(context.root_a_verity_device.as_deref(), Some("/dev/vda4"));
+    assert_eq!(context.root_b_verity_device.as_deref(), Some("/dev/vda5"));
+}
+
+#[test]
+fn verity_is_ab_only_and_needs_gpt() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        verity_request(Arch::X86_64),
+    )
+    .expect_err("mutable verity");
+    assert_eq!(err.code.code(), "E003");
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Ab,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosMbr),
+            ..verity_request(Arch::X86_64)
+        },
+    )
+    .expect_err("mbr verity");
+    assert_eq!(err.code.code(), "E003");
+}
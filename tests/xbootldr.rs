@@ -0,0 +1,142 @@
+use distro_spec::shared::error::ToolErrorCode;
+use recpart::dps;
+use recpart::execute_plan;
+use recpart::types::{ApplyOptions, Arch, DiskTarget, Firmware, InstallMode, LayoutRequest};
+use recpart::{build_plan_with_layout_request, PartitionPlan};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+fn fake_disk() -> DiskTarget {
+    DiskTarget {
+        path: PathBuf::from("/dev/vda"),
+        size_bytes: 250 * 1024 * 1024 * 1024,
+        logical_sector_bytes: 512,
+        physical_sector_bytes: 4096,
+        optimal_io_bytes: 0,
+        alignment_offset_bytes: 0,
+    }
+}
+
+fn xbootldr_request() -> LayoutRequest {
+    LayoutRequest {
+        xbootldr_size_mb: Some(2048),
+        arch: Some(Arch::X86_64),
+        ..LayoutRequest::default()
+    }
+}
+
+fn dry_run(plan: &PartitionPlan) -> recpart::ApplyResult {
+    execute_plan(
+        plan,
+        &ApplyOptions {
+            dry_run: true,
+            confirm_token: None,
+            mount_root: PathBuf::from("/mnt"),
+            luks_key: None,
+        },
+    )
+    .expect("dry-run")
+}
+
+#[test]
+fn xbootldr_takes_boot_and_a_small_esp_moves_to_efi() {
+    let plan = build_plan_with_layout_request(fake_disk(), InstallMode::Ab, xbootldr_request())
+        .expect("plan");
+    assert_eq!(plan.resolved_layout.efi_size_mb, 256);
+    assert_eq!(plan.resolved_layout.xbootldr_size_mb, Some(2048));
+
+    let layout = plan
+        .partitions
+        .iter()
+        .map(|p| (p.name.as_str(), p.mountpoint.as_str(), p.gpt_type.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(layout[0], ("efi", "/efi", dps::ESP));
+    assert_eq!(layout[1], ("xbootldr", "/boot", dps::XBOOTLDR));
+    assert_eq!(plan.partitions[1].size_mb, Some(2048));
+    assert_eq!(plan.partitions[2].name, "root_a");
+}
+
+#[test]
+fn mount_map_and_bootloader_target_show_both_paths() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, xbootldr_request())
+            .expect("plan");
+    let result = dry_run(&plan);
+    let mounts = result
+        .handoff
+        .mount_map
+        .iter()
+        .map(|m| (m.path.as_str(), m.device.as_str()))
+        .collect::<Vec<_>>();
+    assert!(mounts.contains(&("/mnt/sysroot/efi", "/dev/vda1")));
+    assert!(mounts.contains(&("/mnt/sysroot/boot", "/dev/vda2")));
+    assert_eq!(mounts[0], ("/mnt/sysroot", "/dev/vda3"));
+
+    let bootloader = &result.handoff.bootloader;
+    assert_eq!(bootloader.efi_directory.as_deref(), Some("/efi"));
+    assert_eq!(bootloader.xbootldr_directory.as_deref(), Some("/boot"));
+}
+
+#[test]
+fn xbootldr_can_be_ext4_and_explicit_esp_sizes_still_win() {
+    let plan = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            efi_size_mb: Some(512),
+            filesystems: BTreeMap::from([("xbootldr".to_string(), "ext4".to_string())]),
+            ..xbootldr_request()
+        },
+    )
+    .expect("plan");
+    assert_eq!(plan.partitions[0].size_mb, Some(512));
+    assert_eq!(plan.partitions[1].filesystem, "ext4");
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            filesystems: BTreeMap::from([("xbootldr".to_string(), "btrfs".to_string())]),
+            ..xbootldr_request()
+        },
+    )
+    .expect_err("btrfs /boot");
+    assert_eq!(err.code.code(), "E003");
+}
+
+#[test]
+fn without_xbootldr_the_esp_keeps_boot() {
+    let plan =
+        build_plan_with_layout_request(fake_disk(), InstallMode::Mutable, LayoutRequest::default())
+            .expect("plan");
+    assert_eq!(plan.partitions[0].mountpoint, "/boot");
+    assert!(plan.partitions.iter().all(|p| p.name != "xbootldr"));
+    assert_eq!(dry_run(&plan).handoff.bootloader.xbootldr_directory, None);
+}
+
+#[test]
+fn xbootldr_is_rejected_for_bios_firmware_and_encryption() {
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            firmware: Some(Firmware::BiosGpt),
+            ..xbootldr_request()
+        },
+    )
+    .expect_err("bios xbootldr");
+    assert_eq!(err.code.code(), "E003");
+    assert!(err.expectation.contains("uefi"));
+
+    let err = build_plan_with_layout_request(
+        fake_disk(),
+        InstallMode::Mutable,
+        LayoutRequest {
+            filesystems: BTreeMap::from([("xbootldr".to_string(), "ext4".to_string())]),
+            encrypt: vec!["xbootldr".to_string()],
+            ..xbootldr_request()
+        },
+    )
+    .expect_err("encrypted /boot");
+    assert_eq!(err.code.code(), "E003");
+}